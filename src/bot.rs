@@ -1,21 +1,33 @@
 //! Core de l'application.
 //! L'initialisation du bot et la gestion des composants se fait dans ce module.
+use std::sync::Arc;
+use std::time::Duration;
+
 use crate::{
     component_system::{
         self as cmp,
+        bus::BusEvent,
+        data::write_behind,
         manager::{ArcManager, Manager},
         Component,
     },
-    config::Config,
+    config::{self, Config, SharedConfig},
+    logger,
     util::ArcRw,
+    ResultLog,
 };
+use notify::{RecursiveMode, Watcher};
 use serenity::{
-    client::bridge::gateway::GatewayIntents,
-    model::id::{ApplicationId, UserId},
+    client::bridge::gateway::{GatewayIntents, ShardManager},
+    model::id::ApplicationId,
+    prelude::Mutex,
     Client,
 };
 type Result<T> = serenity::Result<T>;
 
+/// Délai avant la relecture du fichier de configuration modifié, pour regrouper les écritures successives d'un éditeur
+const CONFIG_RELOAD_DELAY: Duration = Duration::from_millis(500);
+
 /// Structure du bot.
 ///
 /// Il s'agit de la classe mère De l'application.
@@ -26,35 +38,46 @@ type Result<T> = serenity::Result<T>;
 pub struct Bot {
     /// Client discord de serenity
     client: Client,
-    /// Handler des composants.
-    /// Actuellement un vecteur mais prochainement un gestionnaire est prévu.
-    _components: ArcManager,
+    /// Gestionnaire des composants.
+    components: ArcManager,
 }
 
 impl Bot {
     /// Crée un nouveau bot et l'initialise.
     pub async fn new(config: &Config) -> Result<Bot> {
-        let manager = ArcRw::new(Manager::new());
-        let owners_id = config
-            .owners
-            .iter()
-            .map(|id| id.parse::<u64>().unwrap())
-            .map(UserId)
-            .collect::<Vec<_>>();
         let app_id = ApplicationId(config.app_id);
+        let manager = ArcRw::new(Manager::new(app_id, config.owners_id()));
+        let bus = manager.read().await.bus().clone();
         {
             use cmp::components::*;
             let mut manager_instance = manager.write().await;
             // AJOUTER LES COMPOSANTS ICI A LA SUITE
             manager_instance
-                .add_component(Misc::new(app_id, config.permissions).to_arc())
-                .add_component(Tickets::new().to_arc())
+                .add_component(Misc::new(config.permissions, bus.clone()).to_arc())
+                .add_component(Tickets::new(bus.clone()).to_arc())
                 .add_component(Help::new(manager.clone()).to_arc())
-                .add_component(Moderation::new(app_id, owners_id.clone()).to_arc())
-                .add_component(SlashCommands::new(manager.clone(), owners_id, app_id).to_arc());
+                .add_component(Moderation::new(bus.clone()).to_arc())
+                .add_component(ComponentCommands::new(manager.clone()).to_arc())
+                .add_component(
+                    SlashCommands::new(
+                        manager.clone(),
+                        app_id,
+                        config.slash_dry_run,
+                        config.slash.clone(),
+                    )
+                    .to_arc(),
+                );
+            if let Some(admin_api) = &config.admin_api {
+                manager_instance
+                    .add_component(AdminApi::new(manager.clone(), admin_api.clone()).to_arc());
+            }
+            manager_instance
+                .validate_components()
+                .await
+                .expect_log("Invalid command definitions");
         };
 
-        let framework = cmp::Framework::new(config.prefix, manager.clone());
+        let framework = cmp::Framework::new(config.prefix, manager.clone(), &bus);
         let event_container = cmp::EventDispatcher::new(manager.clone());
         let client = Client::builder(&config.token)
             .framework(framework)
@@ -64,18 +87,155 @@ impl Bot {
             .await?;
         Ok(Bot {
             client,
-            _components: manager,
+            components: manager,
         })
     }
     /// Lance le bot.
     pub async fn start(&mut self) -> Result<()> {
         self.client.start().await
     }
+    /// Surveille le fichier de configuration et applique ses modifications sans redémarrer le bot.
+    ///
+    /// Le fichier modifié est relu et validé. S'il est invalide, il est rejeté, les différences avec le fichier en cours sont
+    /// affichées et la configuration en cours est conservée. Sinon, les owners, les permissions et le préfixe de `config`
+    /// sont mis à jour, les owners sont transmis au manager pour la vérification des permissions des commandes,
+    /// les autres valeurs sont envoyées aux composants par le bus ([`BusEvent::ConfigReloaded`]) et les niveaux de
+    /// journalisation sont appliqués.
+    /// Les autres paramètres ne sont pris en compte qu'au redémarrage du bot.
+    pub async fn watch_config(&self, config: SharedConfig) -> notify::Result<()> {
+        let manager = self.components.clone();
+        let bus = manager.read().await.bus().clone();
+        let path = config.read().unwrap().filepath().to_path_buf();
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            if let Ok(notify::Event { paths, .. }) = event {
+                let _ = sender.send(paths);
+            }
+        })?;
+        // Le dossier est surveillé car certains éditeurs remplacent le fichier à l'enregistrement
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => std::path::Path::new("."),
+        };
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+        let mut running = std::fs::read_to_string(&path).unwrap_or_default();
+        tokio::spawn(async move {
+            let _watcher = watcher;
+            while let Some(paths) = receiver.recv().await {
+                if !paths.iter().any(|p| p.file_name() == path.file_name()) {
+                    continue;
+                }
+                tokio::time::sleep(CONFIG_RELOAD_DELAY).await;
+                while receiver.try_recv().is_ok() {}
+                let content = match tokio::fs::read_to_string(&path).await {
+                    Ok(content) if content != running => content,
+                    Ok(_) => continue,
+                    Err(e) => {
+                        log::error!(target: "config", "Unable to read {}: {}", path.to_string_lossy(), e);
+                        continue;
+                    }
+                };
+                let diff = config::diff(&running, &content);
+                let new_config = match Config::parse(&content, &path) {
+                    Ok(new_config) => new_config,
+                    Err(e) => {
+                        log::error!(
+                            target: "config",
+                            "Configuration rejected, the running configuration is kept: {}\n{}",
+                            e,
+                            diff
+                        );
+                        continue;
+                    }
+                };
+                log::info!(target: "config", "Configuration reloaded\n{}", diff);
+                {
+                    let mut config = config.write().unwrap();
+                    let restart = config.restart_required(&new_config);
+                    if !restart.is_empty() {
+                        log::warn!(target: "config", "Restart the bot to apply: {}", restart.join(", "));
+                    }
+                    config.owners = new_config.owners.clone();
+                    config.permissions = new_config.permissions;
+                    config.prefix = new_config.prefix;
+                    config.slash_dry_run = new_config.slash_dry_run;
+                    config.log.level = new_config.log.level;
+                    config.log.components = new_config.log.components.clone();
+                    logger::set_levels(&config.log);
+                }
+                running = content;
+                manager.write().await.set_owners(new_config.owners_id());
+                bus.publish(
+                    "bot",
+                    BusEvent::ConfigReloaded {
+                        permissions: new_config.permissions,
+                        prefix: new_config.prefix,
+                        slash_dry_run: new_config.slash_dry_run,
+                    },
+                );
+            }
+        });
+        Ok(())
+    }
+    /// Retourne une poignée permettant d'arrêter le bot pendant qu'il tourne.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle {
+            components: self.components.clone(),
+            shard_manager: self.client.shard_manager.clone(),
+        }
+    }
+}
+
+/// Poignée d'arrêt du bot.
+///
+/// Voir [`Bot::shutdown_handle`].
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    components: ArcManager,
+    shard_manager: Arc<Mutex<ShardManager>>,
+}
+impl ShutdownHandle {
+    /// Durée maximale de l'arrêt des composants
+    const COMPONENTS_TIMEOUT: Duration = Duration::from_secs(10);
+    /// Durée maximale de l'enregistrement des données en attente
+    const FLUSH_TIMEOUT: Duration = Duration::from_secs(10);
+    /// Durée maximale de la fermeture des connexions à Discord
+    const SHARDS_TIMEOUT: Duration = Duration::from_secs(5);
+    /// Arrête proprement le bot.
+    ///
+    /// Les composants sont arrêtés, les données en attente sont enregistrées puis les connexions à Discord sont fermées.
+    /// Chaque étape a sa propre durée maximale : un composant trop long à s'arrêter n'empêche pas l'enregistrement
+    /// des données.
+    pub async fn shutdown(&self) {
+        let components = self.components.read().await;
+        Self::step(
+            "Components shutdown",
+            Self::COMPONENTS_TIMEOUT,
+            components.shutdown(),
+        )
+        .await;
+        Self::step(
+            "Pending data flush",
+            Self::FLUSH_TIMEOUT,
+            write_behind::flush(),
+        )
+        .await;
+        let shard_manager = self.shard_manager.clone();
+        Self::step("Shards shutdown", Self::SHARDS_TIMEOUT, async move {
+            shard_manager.lock().await.shutdown_all().await
+        })
+        .await;
+    }
+    /// Exécute l'étape d'arrêt `name`, abandonnée après `timeout`
+    async fn step(name: &str, timeout: Duration, step: impl std::future::Future<Output = ()>) {
+        if tokio::time::timeout(timeout, step).await.is_err() {
+            log::error!("{} timed out after {} seconds", name, timeout.as_secs());
+        }
+    }
 }
 
-//TODO: Enregistrer la configuration du bot lors du drop de ce dernier
 impl Drop for Bot {
     fn drop(&mut self) {
-        println!("Bot dropped");
+        log::debug!("Bot dropped");
     }
 }
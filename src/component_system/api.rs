@@ -0,0 +1,67 @@
+//! Requêtes de l'API d'administration adressées aux composants.
+//!
+//! Le composant [`admin`] expose un serveur HTTP local protégé par un jeton. Les requêtes sur
+//! `/components/<nom>/...` sont transmises au composant `<nom>` via [`Component::api`], qui y répond en JSON.
+//!
+//! [`admin`]: super::components::AdminApi
+//! [`Component::api`]: super::Component::api
+
+use serde_json::Value;
+
+/// Méthode HTTP d'une requête
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Post,
+    Delete,
+}
+
+/// Requête adressée à un composant
+#[derive(Debug, Clone)]
+pub struct ApiRequest {
+    pub method: Method,
+    /// Segments du chemin après `/components/<nom>`. Exemple : `/components/mod/guilds/42/actions` -> `["guilds", "42", "actions"]`
+    pub path: Vec<String>,
+}
+
+impl ApiRequest {
+    /// Retourne les segments du chemin, pour les comparer avec un motif de slice
+    pub fn segments(&self) -> Vec<&str> {
+        self.path.iter().map(String::as_str).collect()
+    }
+}
+
+/// Erreur retournée par un composant, renvoyée avec son code HTTP
+#[derive(Debug, Clone)]
+pub struct ApiError {
+    pub status: u16,
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn new<S: ToString>(status: u16, message: S) -> Self {
+        ApiError {
+            status,
+            message: message.to_string(),
+        }
+    }
+    pub fn bad_request<S: ToString>(message: S) -> Self {
+        Self::new(400, message)
+    }
+    pub fn not_found<S: ToString>(message: S) -> Self {
+        Self::new(404, message)
+    }
+    pub fn internal<S: ToString>(message: S) -> Self {
+        Self::new(500, message)
+    }
+}
+
+/// Réponse d'un composant : la valeur JSON renvoyée ou une erreur
+pub type ApiResult = Result<Value, ApiError>;
+
+/// Lit un identifiant Discord dans un segment du chemin
+pub fn parse_id(segment: &str) -> Result<u64, ApiError> {
+    segment
+        .parse()
+        .map_err(|_| ApiError::bad_request(format!("Identifiant invalide: {}", segment)))
+}
@@ -0,0 +1,135 @@
+//! Bus de messages entre composants.
+//!
+//! Les composants publient des évènements de leur domaine ([`BusEvent`]) sur le bus du [manager]
+//! et les autres composants s'y abonnent pour y réagir. Par exemple, le composant de modération
+//! signale le bannissement d'un membre et le composant de tickets ferme les tickets de ce membre.
+//!
+//! Chaque publication est journalisée avec la cible `bus` et chaque erreur d'un abonné avec le nom de l'abonné.
+//!
+//! [manager]: super::manager::Manager
+
+use std::future::Future;
+
+use serenity::model::id::{ChannelId, GuildId, UserId};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// Nombre d'évènements conservés pour les abonnés en retard
+const BUS_CAPACITY: usize = 256;
+
+/// Sanction appliquée à un membre
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sanction {
+    Ban,
+    Unban,
+    Mute,
+    Unmute,
+}
+
+/// Évènement publié sur le bus
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub enum BusEvent {
+    /// Un membre a été sanctionné ou sa sanction a été levée
+    MemberSanctioned {
+        guild_id: GuildId,
+        user_id: UserId,
+        /// Auteur de la sanction
+        moderator: UserId,
+        sanction: Sanction,
+        reason: Option<String>,
+    },
+    /// Un ticket a été ouvert
+    TicketOpened {
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        /// Auteur du ticket
+        user_id: UserId,
+        /// Nom de la catégorie du ticket
+        category: String,
+    },
+    /// Un ticket a été fermé
+    TicketClosed {
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        /// Membre ayant fermé le ticket. None si le ticket a été fermé par le bot.
+        closed_by: Option<UserId>,
+    },
+    /// Un composant a été activé ou désactivé sur un serveur
+    ComponentToggled {
+        guild_id: GuildId,
+        /// Nom du composant
+        name: String,
+        enabled: bool,
+    },
+    /// Le fichier de configuration a été modifié et rechargé
+    ConfigReloaded {
+        /// Permissions demandées par le bot
+        permissions: u64,
+        /// Préfixe des commandes
+        prefix: char,
+        /// Mode *dry run* de la synchronisation des slash commandes
+        slash_dry_run: bool,
+    },
+}
+
+/// Bus de messages entre composants
+///
+/// Le bus est partagé : ses copies publient et reçoivent les mêmes évènements.
+#[derive(Debug, Clone)]
+pub struct Bus {
+    sender: broadcast::Sender<BusEvent>,
+}
+
+impl Bus {
+    pub fn new() -> Bus {
+        let (sender, _) = broadcast::channel(BUS_CAPACITY);
+        Bus { sender }
+    }
+    /// Publie un évènement du composant `from` à tous les abonnés
+    pub fn publish(&self, from: &str, evt: BusEvent) {
+        let received = self.sender.send(evt.clone()).unwrap_or(0);
+        log::info!(
+            target: "bus",
+            "{} -> {} abonné(s): {:?}",
+            from,
+            received,
+            evt
+        );
+    }
+    /// Abonne le composant `name` au bus.
+    ///
+    /// Le handler est appelé, dans une tâche dédiée à l'abonné, pour chaque évènement publié après l'abonnement.
+    /// Une erreur retournée par le handler est journalisée avec le nom du composant.
+    ///
+    /// Doit être appelé depuis le runtime tokio, par exemple dans [`Component::init`].
+    ///
+    /// [`Component::init`]: super::Component::init
+    pub fn subscribe<F, Fut>(&self, name: &'static str, handler: F)
+    where
+        F: Fn(BusEvent) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), String>> + Send,
+    {
+        let mut receiver = self.sender.subscribe();
+        tokio::spawn(async move {
+            loop {
+                let evt = match receiver.recv().await {
+                    Ok(evt) => evt,
+                    Err(RecvError::Lagged(count)) => {
+                        log::warn!(target: name, "bus: {} évènement(s) perdu(s)", count);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                if let Err(what) = handler(evt.clone()).await {
+                    log::error!(target: name, "bus error: {}\nEvent: {:?}", what, evt);
+                }
+            }
+        });
+    }
+}
+
+impl Default for Bus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -10,7 +10,10 @@
 //! Le parseur de commande foncitonne de la même manière qu'un parseur de ligne de commande:
 //! - Chaque partie de la ligne de commande est séparée par des espaces, sauf les arguments quotés. `mot1 mot2 "mot 3"`
 //! - Les groupes et les commandes sont analysés en mot clé au debut de la commande et de manière recursif : `group [...sous_groupes...] command`
-//! - Les arguments avec des tirets tel que `-nom_parametre valeur` ou `-nom_parametre "valeur avec des espaces"` sont considérés comme des paramètres. (TODO : ajouter des flags)
+//! - Les arguments avec des tirets tel que `-nom_parametre valeur` ou `-nom_parametre "valeur avec des espaces"` sont considérés comme des paramètres.
+//! - Les flags (voir [`Argument::set_flag`]) sont des paramètres booléens sans valeur : `-force`.
+//! - Un paramètre absent prend sa valeur par défaut (voir [`Argument::set_default`]) et un paramètre à choix (voir [`Argument::set_choices`]) n'accepte que les valeurs listées.
+//! - La valeur d'un paramètre doit être du type de l'argument et respecter ses contraintes (bornes, longueur, motif), sinon [`ParseError::InvalidValue`] est retournée.
 //! - Les arguments sans tiret sont considérés comme des arguments de commande.
 //!
 //! Le parseur est là pour aidé a concevoir une commande, mais ne fait pas de traitement.
@@ -37,9 +40,19 @@
 //! [`help`]: crate::component::components::help
 
 #![allow(dead_code)]
+use super::components::utils::{app_command::ApplicationCommandEmbed, message};
+use futures::future::BoxFuture;
+use regex::Regex;
+use serde::Serialize;
 pub use serenity::model::interactions::application_command::ApplicationCommandOptionType as ValueType;
+use serenity::{
+    async_trait,
+    client::Context,
+    model::{channel::ChannelType, id::GuildId},
+};
 use std::{
     collections::{hash_map::DefaultHasher, VecDeque},
+    fmt,
     hash::{Hash, Hasher},
     sync::Arc,
 };
@@ -110,6 +123,15 @@ pub enum ParseError<'a> {
     ExpectedPath(&'a str),
     /// Paramètres requis manquants
     RequiredParameters(String),
+    /// La valeur du paramètre n'est pas du type attendu ou ne respecte pas ses contraintes
+    InvalidValue { name: &'a str, reason: String },
+    /// La valeur du paramètre ne fait pas partie des choix possibles
+    InvalidChoice {
+        name: &'a str,
+        value: &'a str,
+        /// Choix possibles, séparés par des virgules
+        choices: String,
+    },
     /// Erreur inconnue
     Todo,
 }
@@ -122,11 +144,98 @@ impl<'a> ToString for ParseError<'a> {
             ParseError::MissingParameterValue(v) => format!("Valeur du paramètre {} manquant", v),
             ParseError::RequiredParameters(v) => format!("Paramètre {} requis", v),
             ParseError::ExpectedPath(v) => format!("Groupe ou commande attendu après {}", v),
+            ParseError::InvalidValue { name, reason } => {
+                format!("Valeur invalide pour le paramètre {} : {}", name, reason)
+            }
+            ParseError::InvalidChoice {
+                name,
+                value,
+                choices,
+            } => format!(
+                "Valeur {} invalide pour le paramètre {}. Valeurs possibles : {}",
+                value, name, choices
+            ),
             ParseError::Todo => "Unknown parser error".to_string(),
         }
     }
 }
 
+/// Limites imposées par Discord aux slash commandes
+pub mod limits {
+    /// Nombre maximal de caractères d'un nom de groupe, de commande ou d'argument
+    pub const NAME_LENGTH: usize = 32;
+    /// Nombre maximal de caractères d'une description
+    pub const DESCRIPTION_LENGTH: usize = 100;
+    /// Nombre maximal d'options d'une commande ou d'un groupe (sous groupes, sous commandes ou arguments)
+    pub const OPTIONS: usize = 25;
+    /// Nombre maximal de choix d'un argument
+    pub const CHOICES: usize = 25;
+    /// Nombre maximal de caractères d'un choix
+    pub const CHOICE_LENGTH: usize = 100;
+    /// Nombre maximal de commandes de premier niveau d'une application sur un serveur
+    pub const COMMANDS: usize = 100;
+    /// Nombre maximal de commandes de menu contextuel par cible (membre ou message)
+    pub const CONTEXT_COMMANDS: usize = 5;
+}
+
+/// Non respect d'une limite de Discord dans un arbre de commandes.
+///
+/// Voir [`Node::validate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    /// Chemin de l'élément fautif, tel que `groupe.commande -argument`
+    pub path: String,
+    pub reason: String,
+}
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.reason)
+    }
+}
+
+/// Vérifie le nom d'un groupe, d'une commande ou d'un argument.
+///
+/// Discord n'accepte que les minuscules, les chiffres, `-` et `_`.
+fn validate_name(path: &str, name: &str, errors: &mut Vec<ValidationError>) {
+    let valid_chars = name
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+    if name.is_empty() || name.chars().count() > limits::NAME_LENGTH || !valid_chars {
+        errors.push(ValidationError {
+            path: path.to_string(),
+            reason: format!(
+                "nom invalide, 1 à {} caractères parmi a-z, 0-9, - et _",
+                limits::NAME_LENGTH
+            ),
+        });
+    }
+}
+/// Vérifie la description d'un groupe, d'une commande ou d'un argument, obligatoire pour Discord
+fn validate_help(path: &str, help: Option<&str>, errors: &mut Vec<ValidationError>) {
+    let reason = match help.map(|help| help.chars().count()) {
+        None | Some(0) => "description manquante".to_string(),
+        Some(len) if len > limits::DESCRIPTION_LENGTH => format!(
+            "description de {} caractères, {} maximum",
+            len,
+            limits::DESCRIPTION_LENGTH
+        ),
+        _ => return,
+    };
+    errors.push(ValidationError {
+        path: path.to_string(),
+        reason,
+    });
+}
+/// Vérifie le nombre d'options d'une commande ou d'un groupe
+fn validate_options(path: &str, count: usize, errors: &mut Vec<ValidationError>) {
+    if count > limits::OPTIONS {
+        errors.push(ValidationError {
+            path: path.to_string(),
+            reason: format!("{} options, {} maximum", count, limits::OPTIONS),
+        });
+    }
+}
+
 /// Convertit une chaine de caractère en groupe d'arguments
 pub fn split_shell<'a>(txt: &'a str) -> Vec<&'a str> {
     let mut mode = false;
@@ -144,12 +253,95 @@ pub fn split_shell<'a>(txt: &'a str) -> Vec<&'a str> {
     args
 }
 
+/// Retourne le nom d'un type de valeur d'argument
+pub fn value_type_str(value_type: ValueType) -> &'static str {
+    match value_type {
+        ValueType::String => "string",
+        ValueType::Integer => "integer",
+        ValueType::Boolean => "boolean",
+        ValueType::User => "user",
+        ValueType::Channel => "channel",
+        ValueType::Role => "role",
+        ValueType::Mentionable => "mentionable",
+        ValueType::Number => "number",
+        _ => "unknown",
+    }
+}
+
+/// Lit un booléen : `vrai`, `true` ou `1`, et `faux`, `false` ou `0`
+pub fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "vrai" | "true" | "1" => Some(true),
+        "faux" | "false" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// Lit un identifiant Discord, brut (`123`) ou sous forme de mention (`<@123>`, `<@!123>`, `<@&123>`, `<#123>`)
+pub fn parse_id(value: &str) -> Option<u64> {
+    let value = match value.strip_prefix('<').and_then(|v| v.strip_suffix('>')) {
+        Some(mention) => mention.trim_start_matches(['@', '!', '&', '#']),
+        None => value,
+    };
+    value.parse::<u64>().ok()
+}
+
 fn hash<T: Hash>(t: &T) -> u64 {
     let mut s = DefaultHasher::new();
     t.hash(&mut s);
     s.finish()
 }
 
+/// Borne d'un argument entier ou nombre.
+///
+/// Les bornes entières sont gardées en `i64` : un entier n'est jamais converti en `f64` pour être comparé
+/// à une borne entière, ce qui perdrait la précision au-delà de 2^53 (identifiants Discord par exemple).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Bound {
+    Integer(i64),
+    Number(f64),
+}
+impl Bound {
+    fn as_f64(self) -> f64 {
+        match self {
+            Bound::Integer(value) => value as f64,
+            Bound::Number(value) => value,
+        }
+    }
+}
+impl PartialOrd for Bound {
+    fn partial_cmp(&self, other: &Bound) -> Option<std::cmp::Ordering> {
+        match (self, other) {
+            (Bound::Integer(a), Bound::Integer(b)) => a.partial_cmp(b),
+            (a, b) => a.as_f64().partial_cmp(&b.as_f64()),
+        }
+    }
+}
+impl fmt::Display for Bound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Bound::Integer(value) => value.fmt(f),
+            Bound::Number(value) => value.fmt(f),
+        }
+    }
+}
+impl From<i32> for Bound {
+    fn from(value: i32) -> Bound {
+        Bound::Integer(value.into())
+    }
+}
+impl From<i64> for Bound {
+    fn from(value: i64) -> Bound {
+        Bound::Integer(value)
+    }
+}
+impl From<f64> for Bound {
+    fn from(value: f64) -> Bound {
+        Bound::Number(value)
+    }
+}
+
 ///Argument de commande
 #[derive(Debug, Clone)]
 pub struct Argument {
@@ -161,8 +353,26 @@ pub struct Argument {
     pub value_type: ValueType,
     /// L'argument requis si vrai
     pub required: bool,
-    /// Autocompletion
-    pub autocomplete: Option<Arc<Vec<String>>>,
+    /// Fournisseur des suggestions d'autocomplétion
+    pub autocomplete: Option<Autocomplete>,
+    /// Valeur de l'argument lorsqu'il est absent
+    pub default: Option<String>,
+    /// Valeurs autorisées. Toutes les valeurs si vide.
+    pub choices: Vec<String>,
+    /// L'argument est un flag si vrai : un booléen donné sans valeur
+    pub flag: bool,
+    /// Valeur minimale d'un argument entier ou nombre
+    pub min: Option<Bound>,
+    /// Valeur maximale d'un argument entier ou nombre
+    pub max: Option<Bound>,
+    /// Nombre minimal de caractères d'un argument chaîne
+    pub min_length: Option<usize>,
+    /// Nombre maximal de caractères d'un argument chaîne
+    pub max_length: Option<usize>,
+    /// Motif que doit respecter un argument chaîne, ou la raison de son invalidité
+    pub pattern: Option<Result<Regex, String>>,
+    /// Types de salon autorisés d'un argument salon. Tous les types si vide.
+    pub channel_types: Vec<ChannelType>,
 }
 impl Named for Argument {
     fn name(&self) -> &str {
@@ -177,6 +387,15 @@ impl Argument {
             value_type: ValueType::String,
             required: false,
             autocomplete: None,
+            default: None,
+            choices: Vec::new(),
+            flag: false,
+            min: None,
+            max: None,
+            min_length: None,
+            max_length: None,
+            pattern: None,
+            channel_types: Vec::new(),
         }
     }
 
@@ -184,10 +403,16 @@ impl Argument {
         self.help = Some(h.into());
         self
     }
-    pub fn set_autocomplete(mut self, a: Arc<Vec<String>>) -> Argument {
-        self.autocomplete = Some(a);
+    /// Assigne le fournisseur des suggestions d'autocomplétion de l'argument.
+    ///
+    /// Une liste fixe de valeurs (`Vec<String>`) peut servir de fournisseur.
+    pub fn set_autocomplete<P: AutocompleteProvider + 'static>(mut self, provider: P) -> Argument {
+        self.autocomplete = Some(Autocomplete(Arc::new(provider)));
         self
     }
+    pub fn autocomplete(&self) -> Option<&Autocomplete> {
+        self.autocomplete.as_ref()
+    }
     pub fn help(&self) -> Option<&str> {
         match &self.help {
             Some(h) => Some(h),
@@ -210,17 +435,7 @@ impl Argument {
         self.value_type
     }
     pub fn value_type_str(&self) -> &'static str {
-        match self.value_type {
-            ValueType::String => "string",
-            ValueType::Integer => "integer",
-            ValueType::Boolean => "boolean",
-            ValueType::User => "user",
-            ValueType::Channel => "channel",
-            ValueType::Role => "role",
-            ValueType::Mentionable => "mentionable",
-            ValueType::Number => "number",
-            _ => "unknown",
-        }
+        value_type_str(self.value_type)
     }
     pub fn set_required(mut self, req: bool) -> Argument {
         self.required = req;
@@ -229,7 +444,406 @@ impl Argument {
     pub fn required(&self) -> bool {
         self.required
     }
+    /// Assigne la valeur de l'argument lorsqu'il est absent.
+    ///
+    /// Un argument avec une valeur par défaut n'est jamais requis.
+    pub fn set_default<S: Into<String>>(mut self, default: S) -> Argument {
+        self.default = Some(default.into());
+        self.required = false;
+        self
+    }
+    pub fn default(&self) -> Option<&str> {
+        self.default.as_deref()
+    }
+    /// Limite les valeurs de l'argument à une liste de choix.
+    ///
+    /// Les choix sont envoyés à Discord pour les slash commandes de type chaîne, entier ou nombre.
+    pub fn set_choices<I, S>(mut self, choices: I) -> Argument
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.choices = choices.into_iter().map(Into::into).collect();
+        self
+    }
+    pub fn choices(&self) -> &[String] {
+        &self.choices
+    }
+    /// Fait de l'argument un flag : un booléen donné sans valeur (`-force`), faux lorsqu'il est absent.
+    pub fn set_flag(mut self) -> Argument {
+        self.flag = true;
+        self.value_type = ValueType::Boolean;
+        self.set_default("false")
+    }
+    pub fn is_flag(&self) -> bool {
+        self.flag
+    }
+    /// Assigne la valeur minimale d'un argument entier ou nombre.
+    ///
+    /// La contrainte est envoyée à Discord pour les slash commandes.
+    pub fn set_min<B: Into<Bound>>(mut self, min: B) -> Argument {
+        self.min = Some(min.into());
+        self
+    }
+    pub fn min(&self) -> Option<Bound> {
+        self.min
+    }
+    /// Assigne la valeur maximale d'un argument entier ou nombre.
+    ///
+    /// La contrainte est envoyée à Discord pour les slash commandes.
+    pub fn set_max<B: Into<Bound>>(mut self, max: B) -> Argument {
+        self.max = Some(max.into());
+        self
+    }
+    pub fn max(&self) -> Option<Bound> {
+        self.max
+    }
+    /// Assigne le nombre minimal de caractères d'un argument chaîne
+    pub fn set_min_length(mut self, min: usize) -> Argument {
+        self.min_length = Some(min);
+        self
+    }
+    pub fn min_length(&self) -> Option<usize> {
+        self.min_length
+    }
+    /// Assigne le nombre maximal de caractères d'un argument chaîne
+    pub fn set_max_length(mut self, max: usize) -> Argument {
+        self.max_length = Some(max);
+        self
+    }
+    pub fn max_length(&self) -> Option<usize> {
+        self.max_length
+    }
+    /// Assigne le motif que doit respecter un argument chaîne.
+    ///
+    /// La valeur entière doit correspondre au motif : `^` et `$` sont implicites.
+    /// Un motif invalide est signalé par la [validation](Node::validate) de l'arbre de commandes.
+    pub fn set_pattern(mut self, pattern: &str) -> Argument {
+        self.pattern = Some(
+            Regex::new(&format!("^(?:{})$", pattern))
+                .map_err(|e| format!("motif {} invalide. {}", pattern, e)),
+        );
+        self
+    }
+    pub fn pattern(&self) -> Option<&str> {
+        self.pattern
+            .as_ref()
+            .and_then(|re| re.as_ref().ok())
+            .map(|re| {
+                // Retire `^(?:` et `)$` ajoutés par `set_pattern`
+                let re = re.as_str();
+                &re[4..re.len() - 2]
+            })
+    }
+    /// Limite les types de salon d'un argument salon.
+    ///
+    /// Les types sont envoyés à Discord pour que seuls les salons autorisés soient proposés.
+    pub fn set_channel_types<I: IntoIterator<Item = ChannelType>>(mut self, types: I) -> Argument {
+        self.channel_types = types.into_iter().collect();
+        self
+    }
+    pub fn channel_types(&self) -> &[ChannelType] {
+        &self.channel_types
+    }
+    /// Vérifie que le type de salon est autorisé par l'argument.
+    ///
+    /// Retourne la raison de l'erreur sinon.
+    pub fn check_channel_type(&self, kind: ChannelType) -> Result<(), String> {
+        if self.channel_types.is_empty() || self.channel_types.contains(&kind) {
+            Ok(())
+        } else {
+            Err(format!(
+                "salon de type {} non autorisé. Types possibles : {}",
+                kind.name(),
+                self.channel_types
+                    .iter()
+                    .map(|kind| kind.name())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))
+        }
+    }
+    /// Vérifie qu'un entier ou un nombre respecte les bornes de l'argument.
+    ///
+    /// Retourne la raison de l'erreur sinon.
+    pub fn check_number<B: Into<Bound>>(&self, value: B) -> Result<(), String> {
+        let value = value.into();
+        match (self.min, self.max) {
+            (Some(min), _) if value < min => Err(format!("doit être supérieur ou égal à {}", min)),
+            (_, Some(max)) if value > max => Err(format!("doit être inférieur ou égal à {}", max)),
+            _ => Ok(()),
+        }
+    }
+    /// Vérifie qu'une chaîne respecte la longueur et le motif de l'argument.
+    ///
+    /// Retourne la raison de l'erreur sinon.
+    pub fn check_string(&self, value: &str) -> Result<(), String> {
+        let len = value.chars().count();
+        match (self.min_length, self.max_length) {
+            (Some(min), _) if len < min => return Err(format!("{} caractères minimum", min)),
+            (_, Some(max)) if len > max => return Err(format!("{} caractères maximum", max)),
+            _ => (),
+        }
+        match &self.pattern {
+            Some(Ok(re)) if !re.is_match(value) => Err(format!(
+                "doit correspondre au motif {}",
+                self.pattern().unwrap_or_default()
+            )),
+            _ => Ok(()),
+        }
+    }
+    /// Vérifie que la valeur textuelle est du type de l'argument et respecte ses contraintes
+    fn check_value<'a>(&'a self, value: &str) -> Result<(), ParseError<'a>> {
+        let result = match self.value_type {
+            ValueType::String => self.check_string(value),
+            ValueType::Integer => match value.parse::<i64>() {
+                Ok(value) => self.check_number(value),
+                Err(_) => Err("entier attendu".to_string()),
+            },
+            ValueType::Number => match value.parse::<f64>() {
+                Ok(value) => self.check_number(value),
+                Err(_) => Err("nombre attendu".to_string()),
+            },
+            ValueType::Boolean => match parse_bool(value) {
+                Some(_) => Ok(()),
+                None => Err("vrai ou faux attendu".to_string()),
+            },
+            ValueType::Mentionable if !value.starts_with("<@") => {
+                Err("mention d'un membre ou d'un rôle attendue".to_string())
+            }
+            ValueType::User | ValueType::Channel | ValueType::Role | ValueType::Mentionable => {
+                match parse_id(value) {
+                    Some(_) => Ok(()),
+                    None => Err("mention ou identifiant attendu".to_string()),
+                }
+            }
+            _ => Ok(()),
+        };
+        result.map_err(|reason| ParseError::InvalidValue {
+            name: &self.name,
+            reason,
+        })
+    }
+    /// Vérifie la définition de l'argument. `path` est le chemin de sa commande.
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        let path = format!("{} -{}", path, self.name);
+        let mut error = |reason: String| {
+            errors.push(ValidationError {
+                path: path.clone(),
+                reason,
+            })
+        };
+        if !self.choices.is_empty() {
+            if self.choices.len() > limits::CHOICES {
+                error(format!(
+                    "{} choix, {} maximum",
+                    self.choices.len(),
+                    limits::CHOICES
+                ));
+            }
+            for choice in &self.choices {
+                let valid = match self.value_type {
+                    ValueType::String => true,
+                    ValueType::Integer => choice.parse::<i64>().is_ok(),
+                    ValueType::Number => choice.parse::<f64>().is_ok(),
+                    _ => {
+                        error(format!(
+                            "choix non supportés pour le type {}",
+                            self.value_type_str()
+                        ));
+                        break;
+                    }
+                };
+                if !valid {
+                    error(format!(
+                        "choix {} invalide pour le type {}",
+                        choice,
+                        self.value_type_str()
+                    ));
+                } else if choice.is_empty() || choice.chars().count() > limits::CHOICE_LENGTH {
+                    error(format!(
+                        "choix {} de 1 à {} caractères attendu",
+                        choice,
+                        limits::CHOICE_LENGTH
+                    ));
+                }
+            }
+        }
+        let numeric = matches!(self.value_type, ValueType::Integer | ValueType::Number);
+        if self.autocomplete.is_some() {
+            if !numeric && self.value_type != ValueType::String {
+                error(format!(
+                    "autocomplétion non supportée pour le type {}",
+                    self.value_type_str()
+                ));
+            }
+            if !self.choices.is_empty() {
+                error("autocomplétion incompatible avec une liste de choix".to_string());
+            }
+        }
+        if (self.min.is_some() || self.max.is_some()) && !numeric {
+            error(format!(
+                "bornes non supportées pour le type {}",
+                self.value_type_str()
+            ));
+        }
+        if self.value_type == ValueType::Integer {
+            for bound in [self.min, self.max].iter().flatten() {
+                if let Bound::Number(value) = bound {
+                    error(format!("borne {} non entière pour le type entier", value));
+                }
+            }
+        }
+        if let (Some(min), Some(max)) = (self.min, self.max) {
+            if min > max {
+                error(format!(
+                    "borne minimale {} supérieure à la borne maximale {}",
+                    min, max
+                ));
+            }
+        }
+        let length =
+            self.min_length.is_some() || self.max_length.is_some() || self.pattern.is_some();
+        if length && self.value_type != ValueType::String {
+            error(format!(
+                "longueur et motif non supportés pour le type {}",
+                self.value_type_str()
+            ));
+        }
+        if let Some(Err(e)) = &self.pattern {
+            error(e.clone());
+        }
+        if !self.channel_types.is_empty() && self.value_type != ValueType::Channel {
+            error(format!(
+                "types de salon non supportés pour le type {}",
+                self.value_type_str()
+            ));
+        }
+        if let (Some(min), Some(max)) = (self.min_length, self.max_length) {
+            if min > max {
+                error(format!(
+                    "longueur minimale {} supérieure à la longueur maximale {}",
+                    min, max
+                ));
+            }
+        }
+        if let Some(default) = &self.default {
+            if let Err(e) = self
+                .check_choice(default)
+                .and_then(|_| self.check_value(default))
+            {
+                error(format!("valeur par défaut invalide. {}", e.to_string()));
+            }
+        }
+    }
+    /// Vérifie que la valeur fait partie des choix de l'argument
+    fn check_choice<'a>(&'a self, value: &'a str) -> Result<(), ParseError<'a>> {
+        if self.choices.is_empty() || self.choices.iter().any(|choice| choice == value) {
+            Ok(())
+        } else {
+            Err(ParseError::InvalidChoice {
+                name: &self.name,
+                value,
+                choices: self.choices.join(", "),
+            })
+        }
+    }
 }
+/// Fournisseur des suggestions d'autocomplétion d'un argument.
+///
+/// Lorsqu'un utilisateur saisit la valeur de l'argument d'une slash commande, Discord demande des suggestions au bot.
+/// Le [manager] retourne les valeurs du fournisseur qui correspondent à la saisie de l'utilisateur.
+///
+/// [manager]: crate::component_system::manager::Manager::run_autocomplete
+#[async_trait]
+pub trait AutocompleteProvider: Send + Sync {
+    /// Retourne les valeurs possibles de l'argument sur le serveur `guild_id`
+    async fn values(&self, ctx: &Context, guild_id: Option<GuildId>) -> Vec<String>;
+}
+#[async_trait]
+impl AutocompleteProvider for Vec<String> {
+    async fn values(&self, _: &Context, _: Option<GuildId>) -> Vec<String> {
+        self.clone()
+    }
+}
+
+/// Autocomplétion d'un argument, voir [`AutocompleteProvider`]
+#[derive(Clone)]
+pub struct Autocomplete(Arc<dyn AutocompleteProvider>);
+
+impl Autocomplete {
+    /// Retourne les suggestions correspondant à la saisie partielle `input` de l'utilisateur.
+    ///
+    /// Les valeurs contenant la saisie sont retenues, sans tenir compte de la casse, celles qui commencent par
+    /// la saisie en premier. Discord limite le nombre de suggestions à [`limits::CHOICES`].
+    pub async fn suggestions(
+        &self,
+        ctx: &Context,
+        guild_id: Option<GuildId>,
+        input: &str,
+    ) -> Vec<String> {
+        let input = input.to_lowercase();
+        let mut values = self
+            .0
+            .values(ctx, guild_id)
+            .await
+            .into_iter()
+            .filter(|value| value.to_lowercase().contains(&input))
+            .collect::<Vec<_>>();
+        values.sort_by_key(|value| !value.to_lowercase().starts_with(&input));
+        values.truncate(limits::CHOICES);
+        values
+    }
+}
+impl fmt::Debug for Autocomplete {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Autocomplete")
+    }
+}
+
+/// Valeur de retour d'un [`Handler`]
+///
+/// En cas d'erreur, le message est renvoyé à l'utilisateur en tant que message d'erreur éphémère.
+pub type HandlerResult = Result<message::Message, String>;
+/// Future retournée par un [`Handler`]
+pub type HandlerFuture<'a> = BoxFuture<'a, HandlerResult>;
+type HandlerFn =
+    dyn for<'a> Fn(&'a Context, &'a ApplicationCommandEmbed<'a>) -> HandlerFuture<'a> + Send + Sync;
+
+/// Handler asynchrone d'une commande
+///
+/// Le handler est appelé par le [manager] lorsqu'une slash commande dont l'id correspond à
+/// celui de la [`Command`] est reçue. Le message retourné est envoyé en réponse à l'interaction.
+///
+/// [manager]: crate::component_system::manager::Manager
+#[derive(Clone)]
+pub struct Handler(Arc<HandlerFn>);
+
+impl Handler {
+    pub fn new<F>(f: F) -> Handler
+    where
+        F: for<'a> Fn(&'a Context, &'a ApplicationCommandEmbed<'a>) -> HandlerFuture<'a>
+            + Send
+            + Sync
+            + 'static,
+    {
+        Handler(Arc::new(f))
+    }
+    /// Appelle le handler
+    pub fn call<'a>(
+        &self,
+        ctx: &'a Context,
+        app_cmd: &'a ApplicationCommandEmbed<'a>,
+    ) -> HandlerFuture<'a> {
+        (self.0)(ctx, app_cmd)
+    }
+}
+impl fmt::Debug for Handler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Handler")
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Command {
     /// Nom de la commande
@@ -244,6 +858,8 @@ pub struct Command {
     pub params: Vec<Argument>,
     /// ID de la commande
     pub id: Option<String>,
+    /// Handler de la commande
+    pub handler: Option<Handler>,
 }
 impl Named for Command {
     fn name(&self) -> &str {
@@ -259,6 +875,7 @@ impl Command {
             help: None,
             params: Vec::new(),
             id: None,
+            handler: None,
         }
     }
     pub fn set_permission<S: Into<String>>(mut self, permission: S) -> Self {
@@ -297,18 +914,61 @@ impl Command {
         self.arguments = Some(arg);
         self
     }
+    /// Assigne le handler appelé lorsque la commande est lancée
+    pub fn set_handler<F>(mut self, f: F) -> Command
+    where
+        F: for<'a> Fn(&'a Context, &'a ApplicationCommandEmbed<'a>) -> HandlerFuture<'a>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.handler = Some(Handler::new(f));
+        self
+    }
+    pub fn handler(&self) -> Option<&Handler> {
+        self.handler.as_ref()
+    }
     pub fn generate_id(&mut self, groups: Option<&[&str]>) {
         self.id = match groups {
-            Some(g) => {
+            Some(g) if !g.is_empty() => {
                 let nameid = format!("{}.{}", g.join("."), self.name);
                 Some(nameid)
             }
-            None => Some(self.name.clone()),
+            _ => Some(self.name.clone()),
         };
     }
     pub fn id(&self) -> Option<&str> {
         self.id.as_ref().map(|x| x as _)
     }
+    /// Vérifie la définition de la commande et de ses arguments. `path` est le chemin de la commande.
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        validate_name(path, &self.name, errors);
+        validate_help(path, self.help(), errors);
+        validate_options(path, self.params.len(), errors);
+        let mut optional = None;
+        for (idx, param) in self.params.iter().enumerate() {
+            if self.params[..idx].iter().any(|p| p.name == param.name) {
+                errors.push(ValidationError {
+                    path: format!("{} -{}", path, param.name),
+                    reason: "argument en double".to_string(),
+                });
+            }
+            validate_name(&format!("{} -{}", path, param.name), &param.name, errors);
+            validate_help(&format!("{} -{}", path, param.name), param.help(), errors);
+            param.validate(path, errors);
+            // Discord impose les arguments requis avant les arguments optionnels
+            if param.required && param.default.is_none() {
+                if let Some(optional) = optional {
+                    errors.push(ValidationError {
+                        path: format!("{} -{}", path, param.name),
+                        reason: format!("argument requis après l'argument optionnel {}", optional),
+                    });
+                }
+            } else if optional.is_none() {
+                optional = Some(&param.name);
+            }
+        }
+    }
     pub fn try_match_slash<'a>(&'a self, args: &[&'a str]) -> Result<(), ()> {
         if args.is_empty() || args[0] != self.name {
             Err(())
@@ -347,13 +1007,31 @@ impl Command {
                 Some(v) => v,
                 None => return Err(ParseError::UnknownParameter(name)),
             };
-            match iter_args.next() {
-                Some(value) => params.push(matching::Parameter {
-                    name: &name[1..],
-                    value,
-                    kind: param.value_type,
-                }),
-                None => return Err(ParseError::MissingParameterValue(name)),
+            let value = if param.flag {
+                "true"
+            } else {
+                match iter_args.next() {
+                    Some(value) => value,
+                    None => return Err(ParseError::MissingParameterValue(name)),
+                }
+            };
+            param.check_choice(value)?;
+            param.check_value(value)?;
+            params.push(matching::Parameter {
+                name: &param.name,
+                value,
+                kind: param.value_type,
+            });
+        }
+        for param in &self.params {
+            if let Some(default) = &param.default {
+                if !params.iter().any(|p| p.name == param.name) {
+                    params.push(matching::Parameter {
+                        name: &param.name,
+                        value: default,
+                        kind: param.value_type,
+                    });
+                }
             }
         }
         let it_req = self.params.iter().filter(|p| p.required);
@@ -374,6 +1052,87 @@ impl Command {
         })
     }
 }
+/// Cible d'une [`ContextCommand`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ContextTarget {
+    /// Clic droit sur un membre
+    User,
+    /// Clic droit sur un message
+    Message,
+}
+impl ContextTarget {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ContextTarget::User => "user",
+            ContextTarget::Message => "message",
+        }
+    }
+}
+/// Commande de menu contextuel
+///
+/// Elle apparaît dans le menu *Applications* du clic droit sur un membre ou un message et n'a pas d'arguments.
+/// Le handler récupère la cible avec [`ApplicationCommandEmbed::target_user`] ou [`ApplicationCommandEmbed::target_message`].
+/// Seule la racine d'un composant peut en déclarer.
+#[derive(Debug, Clone)]
+pub struct ContextCommand {
+    /// Nom affiché dans le menu. Peut contenir des majuscules et des espaces.
+    pub name: String,
+    /// Type de cible de la commande
+    pub target: ContextTarget,
+    /// Role pouvant lancer la commande. Tout le monde si None.
+    pub permission: Option<String>,
+    /// Handler de la commande
+    pub handler: Option<Handler>,
+}
+impl Named for ContextCommand {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+impl ContextCommand {
+    pub fn new<S: Into<String>>(name: S, target: ContextTarget) -> ContextCommand {
+        ContextCommand {
+            name: name.into(),
+            target,
+            permission: None,
+            handler: None,
+        }
+    }
+    pub fn target(&self) -> ContextTarget {
+        self.target
+    }
+    pub fn set_permission<S: Into<String>>(mut self, permission: S) -> Self {
+        self.permission = Some(permission.into());
+        self
+    }
+    pub fn permission(&self) -> Option<&str> {
+        self.permission.as_deref()
+    }
+    /// Assigne le handler appelé lorsque la commande est lancée
+    pub fn set_handler<F>(mut self, f: F) -> ContextCommand
+    where
+        F: for<'a> Fn(&'a Context, &'a ApplicationCommandEmbed<'a>) -> HandlerFuture<'a>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.handler = Some(Handler::new(f));
+        self
+    }
+    pub fn handler(&self) -> Option<&Handler> {
+        self.handler.as_ref()
+    }
+    /// Vérifie le nom de la commande. Contrairement aux slash commandes, les majuscules et espaces sont permis.
+    fn validate(&self, errors: &mut Vec<ValidationError>) {
+        let len = self.name.chars().count();
+        if len == 0 || len > limits::NAME_LENGTH {
+            errors.push(ValidationError {
+                path: format!("[{}] {}", self.target.name(), self.name),
+                reason: format!("nom invalide, 1 à {} caractères", limits::NAME_LENGTH),
+            });
+        }
+    }
+}
 #[derive(Debug, Clone)]
 pub struct Group {
     /// Nom du groupe
@@ -487,12 +1246,15 @@ pub struct Node {
     pub commands: Container<Command>,
     /// Liste des sous groupes
     pub groups: Container<Group>,
+    /// Liste des commandes de menu contextuel. Uniquement à la racine d'un composant.
+    pub context_commands: Container<ContextCommand>,
 }
 impl Node {
     pub fn new() -> Node {
         Node {
             commands: Container::new(),
             groups: Container::new(),
+            context_commands: Container::new(),
         }
     }
     pub fn generate_ids(&mut self, groups: &[&str]) {
@@ -513,6 +1275,89 @@ impl Node {
         self.commands.add(command);
         self
     }
+    pub fn groups(&self) -> &Container<Group> {
+        &self.groups
+    }
+    pub fn commands(&self) -> &Container<Command> {
+        &self.commands
+    }
+    pub fn add_context_command(mut self, command: ContextCommand) -> Node {
+        self.context_commands.add(command);
+        self
+    }
+    pub fn context_commands(&self) -> &Container<ContextCommand> {
+        &self.context_commands
+    }
+    /// Cherche une commande de menu contextuel par son nom et sa cible
+    pub fn find_context_command(
+        &self,
+        name: &str,
+        target: ContextTarget,
+    ) -> Option<&ContextCommand> {
+        self.context_commands
+            .list()
+            .find(|command| command.name == name && command.target == target)
+    }
+    /// Vérifie que l'arbre de commandes respecte les limites de Discord.
+    ///
+    /// Retourne toutes les violations, avec le chemin complet de l'élément fautif : noms invalides,
+    /// descriptions manquantes ou trop longues, trop d'options ou de choix, trop de niveaux de sous groupes,
+    /// arguments requis après un argument optionnel, contraintes incohérentes...
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let mut errors = Vec::new();
+        self.validate_node(None, &mut errors);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+    /// Vérifie les groupes et commandes du noeud. `path` est le chemin du groupe parent, None pour la racine.
+    fn validate_node(&self, path: Option<&str>, errors: &mut Vec<ValidationError>) {
+        let child_path = |name: &str| match path {
+            Some(path) => format!("{}.{}", path, name),
+            None => name.to_string(),
+        };
+        for group in self.groups.list() {
+            let group_path = child_path(group.name());
+            validate_name(&group_path, group.name(), errors);
+            validate_help(&group_path, group.help(), errors);
+            let node = group.node();
+            validate_options(
+                &group_path,
+                node.groups.0.len() + node.commands.0.len(),
+                errors,
+            );
+            // Discord autorise un seul niveau de sous groupes
+            if path.is_some() && node.groups.list().next().is_some() {
+                errors.push(ValidationError {
+                    path: group_path.clone(),
+                    reason: "un sous groupe ne peut pas contenir de groupe".to_string(),
+                });
+            }
+            if self.commands.find(group.name()).is_some() {
+                errors.push(ValidationError {
+                    path: group_path.clone(),
+                    reason: "nom de groupe déjà utilisé par une commande".to_string(),
+                });
+            }
+            // Discord n'accepte les commandes de menu contextuel qu'au premier niveau
+            if node.context_commands.list().next().is_some() {
+                errors.push(ValidationError {
+                    path: group_path.clone(),
+                    reason: "un groupe ne peut pas contenir de commande de menu contextuel"
+                        .to_string(),
+                });
+            }
+            node.validate_node(Some(&group_path), errors);
+        }
+        for command in self.commands.list() {
+            command.validate(&child_path(command.name()), errors);
+        }
+        for command in self.context_commands.list() {
+            command.validate(errors);
+        }
+    }
     pub fn list_commands(&self) -> Vec<(String, &Command)> {
         self.groups
             .list()
@@ -656,9 +1501,168 @@ impl Node {
     pub fn iter(&self) -> Iter {
         Iter::new(self)
     }
+    /// Cherche une commande par son id.
+    ///
+    /// Les ids doivent avoir été générés au préalable avec [`Node::generate_ids`].
+    pub fn find_command(&self, id: &str) -> Option<&Command> {
+        self.iter().find(|cmd| cmd.id() == Some(id))
+    }
+    /// Cherche une commande par son id et retourne la permission qui s'y applique.
+    ///
+    /// Une commande sans permission hérite de celle du groupe parent le plus proche qui en a une.
+    pub fn find_command_permission(&self, id: &str) -> Option<(&Command, Option<&str>)> {
+        self.find_inherited(id, None)
+    }
+    fn find_inherited<'a>(
+        &'a self,
+        id: &str,
+        inherited: Option<&'a str>,
+    ) -> Option<(&'a Command, Option<&'a str>)> {
+        if let Some(cmd) = self.commands.list().find(|cmd| cmd.id() == Some(id)) {
+            return Some((cmd, cmd.permission().or(inherited)));
+        }
+        self.groups
+            .list()
+            .find_map(|grp| grp.node.find_inherited(id, grp.permission().or(inherited)))
+    }
 }
 impl Group {
     pub fn iter(&self) -> Iter {
         self.node.iter()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn boolean_values() {
+        let node = Node::new().add_command(
+            Command::new("cmd").add_param(
+                Argument::new("force")
+                    .set_value_type(ValueType::Boolean)
+                    .set_default("faux"),
+            ),
+        );
+        let cmd = node.try_match(None, &["cmd", "-force", "vrai"]).unwrap();
+        assert_eq!(cmd.get_parameter("force").unwrap().value, "vrai");
+        assert!(matches!(
+            node.try_match(None, &["cmd", "-force", "ture"]),
+            Err(ParseError::InvalidValue { name: "force", .. })
+        ));
+        assert_eq!(parse_bool("0"), Some(false));
+        assert_eq!(parse_bool("ture"), None);
+    }
+
+    #[test]
+    fn invalid_boolean_default() {
+        let node = Node::new().add_command(
+            Command::new("cmd").add_param(
+                Argument::new("force")
+                    .set_value_type(ValueType::Boolean)
+                    .set_default("oui"),
+            ),
+        );
+        let errors = node.validate().unwrap_err();
+        assert!(errors
+            .iter()
+            .any(|e| e.path == "cmd -force" && e.reason.starts_with("valeur par défaut invalide")));
+    }
+
+    #[test]
+    fn integer_bounds_keep_precision() {
+        // 2^53 + 1 n'est pas représentable en f64
+        let min = (1i64 << 53) + 1;
+        let arg = Argument::new("id")
+            .set_value_type(ValueType::Integer)
+            .set_min(min);
+        assert_eq!(arg.min(), Some(Bound::Integer(min)));
+        assert!(arg.check_number(min).is_ok());
+        assert!(arg.check_number(min - 1).is_err());
+        assert!(arg.check_value(&(min - 1).to_string()).is_err());
+    }
+
+    #[test]
+    fn fractional_integer_bound() {
+        let node = Node::new().add_command(
+            Command::new("cmd").add_param(
+                Argument::new("nombre")
+                    .set_value_type(ValueType::Integer)
+                    .set_max(2.5),
+            ),
+        );
+        let errors = node.validate().unwrap_err();
+        assert!(errors
+            .iter()
+            .any(|e| e.path == "cmd -nombre" && e.reason.starts_with("borne 2.5 non entière")));
+    }
+
+    #[test]
+    fn invalid_pattern() {
+        let arg = Argument::new("code").set_pattern("[a-z");
+        assert_eq!(arg.pattern(), None);
+        // Un motif invalide ne refuse aucune valeur : l'arbre est déjà refusé par la validation
+        assert!(arg.check_string("abc").is_ok());
+        let node = Node::new().add_command(Command::new("cmd").add_param(arg));
+        let errors = node.validate().unwrap_err();
+        assert!(errors
+            .iter()
+            .any(|e| e.path == "cmd -code" && e.reason.starts_with("motif [a-z invalide")));
+    }
+
+    #[test]
+    fn mentionable_requires_mention() {
+        let arg = Argument::new("qui").set_value_type(ValueType::Mentionable);
+        assert!(arg.check_value("<@&42>").is_ok());
+        assert!(arg.check_value("<@!42>").is_ok());
+        assert!(matches!(
+            arg.check_value("42"),
+            Err(ParseError::InvalidValue { name: "qui", .. })
+        ));
+    }
+
+    fn ticket_node() -> Node {
+        Node::new().add_command(
+            Command::new("ticket")
+                .add_param(
+                    Argument::new("categorie")
+                        .set_value_type(ValueType::String)
+                        .set_choices(["bug", "question"])
+                        .set_default("question"),
+                )
+                .add_param(Argument::new("prive").set_flag()),
+        )
+    }
+
+    #[test]
+    fn missing_argument_takes_default() {
+        let node = ticket_node();
+        let cmd = node.try_match(None, &["ticket"]).unwrap();
+        assert_eq!(cmd.get_parameter("categorie").unwrap().value, "question");
+        assert_eq!(cmd.get_parameter("prive").unwrap().value, "false");
+    }
+
+    #[test]
+    fn bare_flag() {
+        let node = ticket_node();
+        let cmd = node
+            .try_match(None, &["ticket", "-prive", "-categorie", "bug"])
+            .unwrap();
+        assert_eq!(cmd.get_parameter("prive").unwrap().value, "true");
+        assert_eq!(cmd.get_parameter("categorie").unwrap().value, "bug");
+    }
+
+    #[test]
+    fn value_outside_choices() {
+        let node = ticket_node();
+        assert_eq!(
+            node.try_match(None, &["ticket", "-categorie", "autre"]),
+            Err(ParseError::InvalidChoice {
+                name: "categorie",
+                value: "autre",
+                choices: "bug, question".to_string(),
+            })
+        );
+    }
+}
@@ -0,0 +1,301 @@
+//! Le composant admin expose une API HTTP locale d'administration du bot.
+//!
+//! L'API est désactivée par défaut : elle est activée en renseignant `admin_api` dans le fichier de configuration.
+//! Chaque requête doit porter le jeton de la configuration dans l'en-tête `Authorization: Bearer <jeton>`.
+//! Les réponses sont en JSON ; une erreur est renvoyée sous la forme `{"error": "message"}`.
+//!
+//! Les routes `/components/<nom>/...` sont traitées par le composant `<nom>` (voir [`Component::api`]).
+//!
+//! [`Component::api`]: crate::component_system::Component::api
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{header, Body, Request, Response, Server, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use serenity::{async_trait, client::Context};
+use tokio::sync::oneshot;
+
+use crate::component_system::{
+    self as cmp,
+    api::{ApiError, ApiRequest, ApiResult, Method},
+    command_parser::{self as cmd, Named},
+    manager::{ArcManager, SYSTEM_COMPONENTS},
+};
+
+/// Configuration de l'API d'administration
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AdminConfig {
+    /// Adresse d'écoute du serveur HTTP, par exemple `127.0.0.1:9200`
+    pub addr: SocketAddr,
+    /// Jeton à fournir dans l'en-tête `Authorization: Bearer <jeton>`
+    pub token: String,
+}
+impl AdminConfig {
+    /// Longueur minimale du jeton
+    pub const MIN_TOKEN_LEN: usize = 16;
+
+    /// Vérifie que le jeton n'est pas trop court pour protéger l'API
+    pub fn validate(&self) -> Result<(), String> {
+        if self.token.chars().count() < Self::MIN_TOKEN_LEN {
+            return Err(format!(
+                "admin_api.token: must be at least {} characters long",
+                Self::MIN_TOKEN_LEN
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Compare deux jetons en temps constant, pour ne pas révéler par la durée de la comparaison
+/// la longueur du préfixe commun
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Composant de l'API d'administration
+pub struct AdminApi {
+    inner: Arc<AdminInner>,
+}
+struct AdminInner {
+    manager: ArcManager,
+    config: AdminConfig,
+    /// Canal d'arrêt du serveur HTTP, une fois démarré
+    stop: Mutex<Option<oneshot::Sender<()>>>,
+}
+
+#[async_trait]
+impl cmp::Component for AdminApi {
+    fn name(&self) -> &'static str {
+        "admin"
+    }
+    async fn event(&self, _: &cmp::Context, _: &cmp::Event) -> Result<(), String> {
+        Ok(())
+    }
+    fn subscriptions(&self) -> cmp::EventKinds {
+        cmp::EventKinds::empty()
+    }
+    async fn init(&self, ctx: &Context) -> Result<(), String> {
+        AdminInner::start(&self.inner, ctx.clone())
+    }
+    async fn shutdown(&self) {
+        if let Some(stop) = self.inner.stop.lock().unwrap().take() {
+            stop.send(()).unwrap_or(());
+        }
+    }
+}
+
+impl AdminApi {
+    pub fn new(manager: ArcManager, config: AdminConfig) -> Self {
+        AdminApi {
+            inner: Arc::new(AdminInner {
+                manager,
+                config,
+                stop: Mutex::new(None),
+            }),
+        }
+    }
+}
+
+/// Convertit un noeud de commandes en JSON
+fn node_json(node: &cmd::Node) -> Value {
+    json!({
+        "groups": node.groups().list().map(group_json).collect::<Vec<_>>(),
+        "commands": node.commands().list().map(command_json).collect::<Vec<_>>(),
+        "context_commands": node.context_commands().list().map(|command| json!({
+            "name": command.name,
+            "target": command.target().name(),
+            "permission": command.permission(),
+        })).collect::<Vec<_>>(),
+    })
+}
+fn group_json(group: &cmd::Group) -> Value {
+    let mut value = node_json(group.node());
+    value["name"] = json!(group.name());
+    value["help"] = json!(group.help());
+    value["permission"] = json!(group.permission());
+    value
+}
+fn command_json(command: &cmd::Command) -> Value {
+    json!({
+        "name": command.name,
+        "id": command.id(),
+        "help": command.help(),
+        "permission": command.permission(),
+        "params": command.params().iter().map(|arg| json!({
+            "name": arg.name,
+            "help": arg.help(),
+            "type": arg.value_type_str(),
+            "required": arg.required(),
+            "flag": arg.is_flag(),
+            "default": arg.default(),
+            "choices": arg.choices(),
+            "autocomplete": arg.autocomplete().is_some(),
+            "min": arg.min(),
+            "max": arg.max(),
+            "min_length": arg.min_length(),
+            "max_length": arg.max_length(),
+            "pattern": arg.pattern(),
+            "channel_types": arg.channel_types().iter().map(|kind| kind.name()).collect::<Vec<_>>(),
+        })).collect::<Vec<_>>(),
+    })
+}
+
+impl AdminInner {
+    /// Démarre le serveur HTTP de l'API
+    fn start(inner: &Arc<AdminInner>, ctx: Context) -> Result<(), String> {
+        let addr = inner.config.addr;
+        let service_inner = Arc::clone(inner);
+        let make_service = make_service_fn(move |_| {
+            let inner = Arc::clone(&service_inner);
+            let ctx = ctx.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let inner = Arc::clone(&inner);
+                    let ctx = ctx.clone();
+                    async move { Ok::<_, Infallible>(inner.handle(&ctx, req).await) }
+                }))
+            }
+        });
+        let (stop, stopped) = oneshot::channel::<()>();
+        let server = Server::try_bind(&addr)
+            .map_err(|e| format!("Unable to listen on {}: {}", addr, e))?
+            .serve(make_service)
+            .with_graceful_shutdown(async {
+                stopped.await.unwrap_or(());
+            });
+        *inner.stop.lock().unwrap() = Some(stop);
+        log::info!(target: "admin", "Admin API available on http://{}", addr);
+        tokio::spawn(async move {
+            if let Err(e) = server.await {
+                log::error!(target: "admin", "Admin API server error: {}", e);
+            }
+        });
+        Ok(())
+    }
+    /// Vérifie le jeton de la requête
+    fn authorized(&self, req: &Request<Body>) -> bool {
+        req.headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|token| constant_time_eq(token.as_bytes(), self.config.token.as_bytes()))
+    }
+    /// Traite une requête HTTP et retourne la réponse JSON
+    async fn handle(&self, ctx: &Context, req: Request<Body>) -> Response<Body> {
+        let result = if !self.authorized(&req) {
+            Err(ApiError::new(401, "Jeton invalide"))
+        } else {
+            match Self::parse_request(&req) {
+                Ok(request) => self.route(ctx, request).await,
+                Err(e) => Err(e),
+            }
+        };
+        let (status, body) = match result {
+            Ok(value) => (StatusCode::OK, value),
+            Err(e) => {
+                log::debug!(target: "admin", "Request error {}: {}", e.status, e.message);
+                (
+                    StatusCode::from_u16(e.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+                    json!({ "error": e.message }),
+                )
+            }
+        };
+        Response::builder()
+            .status(status)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+    /// Lit la méthode et les segments du chemin de la requête
+    fn parse_request(req: &Request<Body>) -> Result<ApiRequest, ApiError> {
+        let method = match *req.method() {
+            hyper::Method::GET => Method::Get,
+            hyper::Method::POST => Method::Post,
+            hyper::Method::DELETE => Method::Delete,
+            _ => return Err(ApiError::new(405, "Méthode non supportée")),
+        };
+        let path = req
+            .uri()
+            .path()
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(str::to_string)
+            .collect();
+        Ok(ApiRequest { method, path })
+    }
+    /// Route la requête vers l'API ou vers le composant concerné
+    async fn route(&self, ctx: &Context, request: ApiRequest) -> ApiResult {
+        match (request.method, request.segments().as_slice()) {
+            (Method::Get, ["guilds"]) => Ok(self.guilds(ctx).await),
+            (Method::Get, ["components"]) => Ok(self.components(None).await),
+            (Method::Get, ["components", name]) => self
+                .components(Some(name))
+                .await
+                .as_array_mut()
+                .and_then(|components| components.pop())
+                .ok_or_else(|| ApiError::not_found(format!("Composant {} inconnu", name))),
+            (method, ["components", name, ..]) => {
+                let request = ApiRequest {
+                    method,
+                    path: request.path[2..].to_vec(),
+                };
+                self.component_request(ctx, name, &request).await
+            }
+            _ => Err(ApiError::not_found("Route inconnue")),
+        }
+    }
+    /// `GET /guilds` : serveurs sur lesquels le bot est présent
+    async fn guilds(&self, ctx: &Context) -> Value {
+        let mut guilds = Vec::new();
+        for guild_id in ctx.cache.guilds().await {
+            guilds.push(json!({
+                "id": guild_id.to_string(),
+                "name": guild_id.name(ctx).await,
+            }));
+        }
+        Value::Array(guilds)
+    }
+    /// `GET /components` et `GET /components/<nom>` : composants du bot et leur arbre de commandes
+    async fn components(&self, name: Option<&str>) -> Value {
+        let components = self.manager.read().await.get_components().clone();
+        let mut list = Vec::new();
+        for compo in components {
+            let compo = compo.read().await;
+            if name.is_some_and(|name| name != compo.name()) {
+                continue;
+            }
+            list.push(json!({
+                "name": compo.name(),
+                "system": SYSTEM_COMPONENTS.contains(&compo.name()),
+                "commands": compo.node().map(node_json),
+            }));
+        }
+        Value::Array(list)
+    }
+    /// `/components/<nom>/...` : requête traitée par le composant
+    async fn component_request(
+        &self,
+        ctx: &Context,
+        name: &str,
+        request: &ApiRequest,
+    ) -> ApiResult {
+        let components = self.manager.read().await.get_components().clone();
+        for compo in components {
+            let compo = compo.read().await;
+            if compo.name() == name {
+                return compo
+                    .api(ctx, request)
+                    .await
+                    .unwrap_or_else(|| Err(ApiError::not_found("Route inconnue")));
+            }
+        }
+        Err(ApiError::not_found(format!("Composant {} inconnu", name)))
+    }
+}
@@ -0,0 +1,136 @@
+//! Le composant component permet d'activer ou de désactiver les composants du bot sur un serveur.
+//!
+//! Un composant désactivé ne reçoit plus les évènements du serveur, ses commandes ne sont plus
+//! enregistrées sur le serveur et n'apparaissent plus dans l'aide.
+
+use std::sync::Arc;
+
+use serenity::{async_trait, client::Context};
+
+use super::utils::{
+    app_command::{handler, ApplicationCommandEmbed},
+    message,
+};
+use crate::component_system::{
+    self as cmp,
+    bus::BusEvent,
+    command_parser as cmd,
+    manager::{ArcManager, SYSTEM_COMPONENTS},
+};
+
+/// Composant de gestion des composants du bot sur chaque serveur.
+pub struct ComponentCommands {
+    node: cmd::Node,
+}
+/// Données et actions du composant, partagées avec les handlers des commandes
+struct ComponentInner {
+    manager: ArcManager,
+}
+
+#[async_trait]
+impl cmp::Component for ComponentCommands {
+    fn name(&self) -> &'static str {
+        "component"
+    }
+    async fn event(&self, _: &cmp::Context, _: &cmp::Event) -> Result<(), String> {
+        Ok(())
+    }
+    fn subscriptions(&self) -> cmp::EventKinds {
+        cmp::EventKinds::empty()
+    }
+    fn node(&self) -> Option<&cmd::Node> {
+        Some(&self.node)
+    }
+}
+
+impl ComponentCommands {
+    pub fn new(manager: ArcManager) -> Self {
+        use serenity::model::interactions::application_command::ApplicationCommandOptionType;
+        let inner = Arc::new(ComponentInner { manager });
+        let arg_name = cmd::Argument::new("name")
+            .set_value_type(ApplicationCommandOptionType::String)
+            .set_required(true)
+            .set_help("Nom du composant");
+        let mut node = cmd::Node::new().add_group(
+            cmd::Group::new("component")
+                .set_help("Gestion des composants du bot sur le serveur")
+                .set_permission("owners")
+                .add_command(
+                    cmd::Command::new("enable")
+                        .set_help("Active un composant sur le serveur")
+                        .add_param(arg_name.clone())
+                        .set_handler(handler!(inner, set_enabled, true)),
+                )
+                .add_command(
+                    cmd::Command::new("disable")
+                        .set_help("Désactive un composant sur le serveur")
+                        .add_param(arg_name)
+                        .set_handler(handler!(inner, set_enabled, false)),
+                )
+                .add_command(
+                    cmd::Command::new("list")
+                        .set_help("Liste les composants et leur état sur le serveur")
+                        .set_handler(handler!(inner, list)),
+                ),
+        );
+        node.generate_ids(&[]);
+        ComponentCommands { node }
+    }
+}
+
+impl ComponentInner {
+    /// Méthode appelée sur les commandes component.enable et component.disable
+    ///
+    /// Active ou désactive un composant sur le serveur. Le changement est publié sur le bus
+    /// pour que les slash commandes du serveur soient mises à jour.
+    async fn set_enabled(
+        &self,
+        _: &Context,
+        app_cmd: &ApplicationCommandEmbed<'_>,
+        enabled: bool,
+    ) -> cmd::HandlerResult {
+        let guild_id = app_cmd.require_guild_id()?;
+        let name = app_cmd.command().get::<String>("name")?;
+        let state = if enabled { "activé" } else { "désactivé" };
+        let manager = self.manager.read().await;
+        if !manager.set_enabled(guild_id, &name, enabled).await? {
+            return Ok(message::success(format!(
+                "Le composant `{}` est déjà {}.",
+                name, state
+            )));
+        }
+        manager.bus().publish(
+            "component",
+            BusEvent::ComponentToggled {
+                guild_id,
+                name: name.clone(),
+                enabled,
+            },
+        );
+        Ok(message::success(format!(
+            "Le composant `{}` a été {}.",
+            name, state
+        )))
+    }
+    /// Méthode appelée sur la commande component.list
+    ///
+    /// Affiche les composants du bot et leur état sur le serveur
+    async fn list(&self, _: &Context, app_cmd: &ApplicationCommandEmbed<'_>) -> cmd::HandlerResult {
+        let guild_id = app_cmd.require_guild_id()?;
+        let manager = self.manager.read().await;
+        let disabled = manager.disabled_components(guild_id).await;
+        let mut list = String::new();
+        for compo in manager.get_components() {
+            let name = compo.read().await.name().to_string();
+            let state = if SYSTEM_COMPONENTS.contains(&name.as_str()) {
+                "système"
+            } else if disabled.contains(&name) {
+                "désactivé"
+            } else {
+                "activé"
+            };
+            list.push_str(&format!("**{}** - {}\n", name, state));
+        }
+        Ok(message::success(list))
+    }
+}
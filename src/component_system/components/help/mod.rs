@@ -1,19 +1,7 @@
 //! Le composant help permet d'afficher une aide en fonction de la commande.
 //! Il se repose sur le groupe de commande retournée par la fonction [`Component::group_parser`].
 
-use serenity::{
-    async_trait,
-    builder::CreateEmbed,
-    client::Context,
-    model::{
-        event::InteractionCreateEvent,
-        interactions::{
-            application_command::ApplicationCommandInteraction,
-            InteractionApplicationCommandCallbackDataFlags,
-        },
-    },
-    utils::Colour,
-};
+use serenity::{async_trait, builder::CreateEmbed, model::id::GuildId, utils::Colour};
 
 use crate::component_system::{
     self as cmp,
@@ -21,12 +9,9 @@ use crate::component_system::{
     manager::ArcManager,
 };
 
-use super::utils::{self, commands, message};
-
-use super::utils::commands::*;
+use super::utils::{commands, message};
 
 pub struct Help {
-    manager: ArcManager,
     node: cmd::Node,
 }
 #[async_trait]
@@ -35,17 +20,11 @@ impl cmp::Component for Help {
         "help"
     }
 
-    async fn command(
-        &self,
-        fw_config: &cmp::FrameworkConfig,
-        ctx: &cmp::Context,
-        msg: &cmp::Message,
-    ) -> cmp::CommandMatch {
-        self.r_command(fw_config, ctx, msg).await
+    async fn event(&self, _: &cmp::Context, _: &cmp::Event) -> Result<(), String> {
+        Ok(())
     }
-
-    async fn event(&self, ctx: &cmp::Context, event: &cmp::Event) -> Result<(), String> {
-        self.r_event(ctx, event).await
+    fn subscriptions(&self) -> cmp::EventKinds {
+        cmp::EventKinds::empty()
     }
     fn node(&self) -> Option<&cmd::Node> {
         Some(&self.node)
@@ -69,98 +48,44 @@ struct HelpInfo {
     components: Option<Vec<String>>,
 }
 
+/// Autocomplétion des noms des commandes des composants activés sur le serveur
+struct HelpTopics(ArcManager);
+
+#[async_trait]
+impl cmd::AutocompleteProvider for HelpTopics {
+    async fn values(&self, _: &cmp::Context, guild_id: Option<GuildId>) -> Vec<String> {
+        let comps = self.0.read().await.get_enabled_components(guild_id).await;
+        let mut topics = Vec::new();
+        for comp in comps {
+            if let Some(node) = comp.read().await.node() {
+                topics.extend(node.list_commands_names());
+            }
+        }
+        topics
+    }
+}
+
 impl Help {
     pub fn new(manager: ArcManager) -> Help {
-        let node = cmd::Node::new().add_command(
+        let mut node = cmd::Node::new().add_command(
             cmd::Command::new("help")
                 .set_help("Affiche l'aide d'une commanded ou du bot.")
                 .add_param(
-                    cmd::Argument::new("commande").set_help("Nom de la commande ou du groupe"),
-                ),
+                    cmd::Argument::new("commande")
+                        .set_help("Nom de la commande ou du groupe")
+                        .set_autocomplete(HelpTopics(manager.clone())),
+                )
+                .set_handler(move |_, app_cmd| {
+                    let manager = manager.clone();
+                    Box::pin(async move {
+                        Self::help(&manager, app_cmd.get_guild_id(), app_cmd.command()).await
+                    })
+                }),
         );
-        Help { manager, node }
+        node.generate_ids(&[]);
+        Help { node }
     }
 
-    /// Helper pour le language server.
-    /// rust-analyzer n'aime pas les fonctions async dans les traits
-    async fn r_command(
-        &self,
-        _: &cmp::FrameworkConfig,
-        ctx: &cmp::Context,
-        msg: &cmp::Message,
-    ) -> cmp::CommandMatch {
-        let args = cmd::split_shell(&msg.content[1..]);
-        let matched = match utils::try_match(ctx, msg, &self.node, args).await {
-            Ok(v) => v,
-            Err(e) => return e,
-        };
-        let command = match self.commands(matched.to_command()).await {
-            Ok(v) => v,
-            Err(None) => return cmp::CommandMatch::NotMatched,
-            Err(Some(e)) => return cmp::CommandMatch::Error(e),
-        };
-
-        match msg
-            .channel_id
-            .send_message(ctx, |m| {
-                let message::Message { message, embed, .. } = command;
-                m.content(message);
-                m.embed(|e| {
-                    *e = embed.unwrap();
-                    e
-                })
-            })
-            .await
-        {
-            Ok(_) => cmp::CommandMatch::Matched,
-            Err(e) => {
-                cmp::CommandMatch::Error(format!("Impossible d'envoyer le message d'aide: {}", e))
-            }
-        }
-    }
-    async fn r_event(&self, ctx: &cmp::Context, event: &cmp::Event) -> Result<(), String> {
-        match event {
-            cmp::Event::InteractionCreate(InteractionCreateEvent {
-                interaction: serenity::model::interactions::Interaction::ApplicationCommand(c),
-                ..
-            }) => self.on_applications_command(ctx, c).await,
-            _ => Ok(()),
-        }
-    }
-    async fn on_applications_command(
-        &self,
-        ctx: &Context,
-        app_command: &ApplicationCommandInteraction,
-    ) -> Result<(), String> {
-        let message::Message {
-            message,
-            embed,
-            ephemeral,
-        } = match self.commands(app_command.to_command()).await {
-            Ok(v) => v,
-            Err(Some(e)) => return Err(e),
-            Err(None) => return Ok(()),
-        };
-        app_command
-            .create_interaction_response(ctx, |resp| {
-                resp.interaction_response_data(|data| {
-                    data.content(message);
-                    if let Some(e) = embed {
-                        data.add_embed(e);
-                    }
-                    if ephemeral {
-                        data.flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL);
-                    }
-                    data
-                })
-            })
-            .await
-            .map_err(|e| {
-                eprintln!("Cannot create response: {}", e);
-                e.to_string()
-            })?;
-        Ok(())
-    }
     fn make_help_embed(info: HelpInfo) -> CreateEmbed {
         let mut embed = CreateEmbed::default();
         embed.color(Colour::from_rgb(0, 204, 0));
@@ -213,51 +138,45 @@ impl Help {
         }
         embed
     }
-    async fn commands(
-        &self,
-        command: commands::Command,
-    ) -> Result<message::Message, Option<String>> {
-        match command.fullname().as_str() {
-            "help" => {
-                let words = match command.get_argument("commande") {
-                    Some(Argument {
-                        value: Value::String(v),
-                        ..
-                    }) => Some(v),
-                    Some(_) => return Err(Some("Argument `commande` invalide".to_string())),
-                    None => None,
-                };
-                let help_info = match words {
-                    Some(words) => self
-                        .help_components(words)
-                        .await
-                        .map_err(|_| "Aucune aide trouvé.".to_string()),
-                    None => self.list_commands().await,
-                };
+    /// Handler de la commande help
+    ///
+    /// Les commandes des composants désactivés sur le serveur `guild_id` ne sont pas affichées.
+    async fn help(
+        manager: &ArcManager,
+        guild_id: Option<GuildId>,
+        command: &commands::Command,
+    ) -> cmd::HandlerResult {
+        let words = command.opt::<String>("commande")?;
+        let help_info = match words {
+            Some(words) => Self::help_components(manager, guild_id, &words)
+                .await
+                .map_err(|_| "Aucune aide trouvé.".to_string()),
+            None => Self::list_commands(manager, guild_id).await,
+        };
 
-                let (msg_to_send, ephemeral) = match help_info {
-                    Ok(v) => (Self::make_help_embed(v), false),
-                    Err(e) => {
-                        let mut embed = CreateEmbed::default();
-                        embed.color(Colour::from_rgb(204, 0, 0));
-                        embed.title("Erreur");
-                        embed.description(e);
-                        (embed, true)
-                    }
-                };
-                Ok(message::Message {
-                    message: String::new(),
-                    embed: Some(msg_to_send),
-                    ephemeral,
-                    ..Default::default()
-                })
+        let (msg_to_send, ephemeral) = match help_info {
+            Ok(v) => (Self::make_help_embed(v), false),
+            Err(e) => {
+                let mut embed = CreateEmbed::default();
+                embed.color(Colour::from_rgb(204, 0, 0));
+                embed.title("Erreur");
+                embed.description(e);
+                (embed, true)
             }
-            _ => Err(None),
-        }
+        };
+        Ok(message::Message {
+            message: String::new(),
+            embed: Some(msg_to_send),
+            ephemeral,
+            ..Default::default()
+        })
     }
-    async fn help_components<'a, 'b>(&'a self, command_name: &'b str) -> Result<HelpInfo, ()> {
-        let comps = self.manager.read().await;
-        let comps = comps.get_components();
+    async fn help_components(
+        manager: &ArcManager,
+        guild_id: Option<GuildId>,
+        command_name: &str,
+    ) -> Result<HelpInfo, ()> {
+        let comps = manager.read().await.get_enabled_components(guild_id).await;
 
         for cmp in comps {
             let cmp = cmp.read().await;
@@ -285,10 +204,46 @@ impl Help {
             .params
             .iter()
             .map(|param| {
-                (
-                    format!("{} <{}>", param.name(), param.value_type_str()),
-                    param.help.clone(),
-                )
+                let name = if param.is_flag() {
+                    format!("-{}", param.name())
+                } else {
+                    format!("{} <{}>", param.name(), param.value_type_str())
+                };
+                let mut details = Vec::new();
+                if !param.choices().is_empty() {
+                    details.push(format!("choix : {}", param.choices().join(", ")));
+                }
+                if let Some(min) = param.min() {
+                    details.push(format!("min : {}", min));
+                }
+                if let Some(max) = param.max() {
+                    details.push(format!("max : {}", max));
+                }
+                match (param.min_length(), param.max_length()) {
+                    (Some(min), Some(max)) => details.push(format!("{} à {} caractères", min, max)),
+                    (Some(min), None) => details.push(format!("{} caractères minimum", min)),
+                    (None, Some(max)) => details.push(format!("{} caractères maximum", max)),
+                    (None, None) => (),
+                }
+                if !param.channel_types().is_empty() {
+                    let types = param.channel_types().iter().map(|kind| kind.name());
+                    details.push(format!("salons : {}", types.collect::<Vec<_>>().join(", ")));
+                }
+                if let Some(pattern) = param.pattern() {
+                    details.push(format!("motif : {}", pattern));
+                }
+                match param.default() {
+                    Some(default) if !param.is_flag() => {
+                        details.push(format!("défaut : {}", default))
+                    }
+                    _ => (),
+                }
+                let help = match (param.help.clone(), details.is_empty()) {
+                    (help, true) => help,
+                    (Some(help), false) => Some(format!("{} ({})", help, details.join(", "))),
+                    (None, false) => Some(details.join(", ")),
+                };
+                (name, help)
             })
             .collect::<Vec<(String, Option<String>)>>();
         Ok(HelpInfo {
@@ -304,9 +259,11 @@ impl Help {
         })
     }
 
-    async fn list_commands(&self) -> Result<HelpInfo, String> {
-        let comps = self.manager.read().await;
-        let comps = comps.get_components();
+    async fn list_commands(
+        manager: &ArcManager,
+        guild_id: Option<GuildId>,
+    ) -> Result<HelpInfo, String> {
+        let comps = manager.read().await.get_enabled_components(guild_id).await;
         let mut commands = Vec::new();
         for comp in comps {
             let comp = comp.read().await;
@@ -3,18 +3,18 @@
 //! **Attention toutefois** : le composant misc ne doit rien enregistrer et ne doit pas posséder de configuration.
 //! Une action (commande ou événement) dans ce composant doit se suffire à elle-même.
 
-use super::super::{CommandMatch, Component, FrameworkConfig};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use super::super::bus::{Bus, BusEvent};
+use super::super::{CommandMatch, Component, EventKinds, FrameworkConfig};
 use super::utils;
-use super::utils::app_command::ApplicationCommandEmbed;
 use super::utils::message;
 use crate::component_system::command_parser::{self as cmd};
 use serde::{Deserialize, Serialize};
 use serenity::async_trait;
 use serenity::client::Context;
 use serenity::model::channel::Message;
-use serenity::model::event::InteractionCreateEvent;
-use serenity::model::id::ApplicationId;
-use serenity::model::interactions::application_command::ApplicationCommandInteraction;
 use serenity::model::{
     event::{Event, ReadyEvent},
     Permissions,
@@ -22,8 +22,9 @@ use serenity::model::{
 
 pub struct Misc {
     node: cmd::Node,
-    app_id: ApplicationId,
-    bot_permissions: u64,
+    /// Permissions demandées par le bot, mises à jour lorsque la configuration est rechargée
+    bot_permissions: Arc<AtomicU64>,
+    bus: Bus,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -71,45 +72,63 @@ impl Component for Misc {
     async fn event(&self, ctx: &Context, evt: &Event) -> Result<(), String> {
         match evt {
             Event::Ready(ReadyEvent { ready, .. }) => {
-                println!("{} is connected!", ready.user.name);
-                let perms = Permissions::from_bits(self.bot_permissions)
+                log::info!(target: "misc", "{} is connected!", ready.user.name);
+                let perms = Permissions::from_bits(self.bot_permissions.load(Ordering::Relaxed))
                     .map(|v| {
-                        println!("Permission(s) demandé par le bot: {}", v);
+                        log::info!(target: "misc", "Permission(s) demandé par le bot: {}", v);
                         v
                     })
                     .unwrap_or_else(|| {
-                        println!("Permission du bot dans la configuration invalide. Utilisation de la permission par défaut.");
+                        log::warn!(target: "misc", "Permission du bot dans la configuration invalide. Utilisation de la permission par défaut.");
                         Permissions::empty()
                     });
                 let invite = ready.user.invite_url(&ctx.http, perms).await;
 
                 match invite {
-                    Ok(v) => println!("Invitation: {}", v),
+                    Ok(v) => log::info!(target: "misc", "Invitation: {}", v),
                     Err(e) => return Err(e.to_string()),
                 }
 
                 Ok(())
             }
-            Event::InteractionCreate(InteractionCreateEvent {
-                interaction: serenity::model::interactions::Interaction::ApplicationCommand(c),
-                ..
-            }) => self.on_applications_command(ctx, c).await,
             _ => Ok(()),
         }
     }
+    fn subscriptions(&self) -> EventKinds {
+        EventKinds::READY
+    }
+    async fn init(&self, _: &Context) -> Result<(), String> {
+        let bot_permissions = Arc::clone(&self.bot_permissions);
+        self.bus.subscribe("misc", move |evt| {
+            if let BusEvent::ConfigReloaded { permissions, .. } = evt {
+                bot_permissions.store(permissions, Ordering::Relaxed);
+            }
+            async { Ok(()) }
+        });
+        Ok(())
+    }
     fn node(&self) -> Option<&cmd::Node> {
         Some(&self.node)
     }
 }
 
 impl Misc {
-    pub fn new(app_id: ApplicationId, bot_permissions: u64) -> Misc {
+    pub fn new(bot_permissions: u64, bus: Bus) -> Misc {
+        let mut node = cmd::Node::new().add_command(
+            cmd::Command::new("ping")
+                .set_help("Permet d'avoir une réponse du bot")
+                .set_handler(|_, app_cmd| {
+                    Box::pin(async move {
+                        app_cmd.require_guild_id()?;
+                        Ok(message::success("Pong!"))
+                    })
+                }),
+        );
+        node.generate_ids(&[]);
         Misc {
-            node: cmd::Node::new().add_command(
-                cmd::Command::new("ping").set_help("Permet d'avoir une réponse du bot"),
-            ),
-            app_id,
-            bot_permissions,
+            node,
+            bot_permissions: Arc::new(AtomicU64::new(bot_permissions)),
+            bus,
         }
     }
     pub async fn send_message(ctx: &Context, msg: &Message, txt: &str) -> CommandMatch {
@@ -118,33 +137,4 @@ impl Misc {
             Err(e) => CommandMatch::Error(e.to_string()),
         }
     }
-    async fn on_applications_command(
-        &self,
-        ctx: &Context,
-        app_command: &ApplicationCommandInteraction,
-    ) -> Result<(), String> {
-        if app_command.application_id != self.app_id {
-            // La commande n'est pas destiné à ce bot
-            return Ok(());
-        }
-        let app_cmd = ApplicationCommandEmbed::new(app_command);
-        if app_cmd.get_guild_id().is_none() {
-            return Err("Vous devez être dans un serveur pour utiliser cette commande.".into());
-        };
-        let command_name = app_cmd.fullname();
-        let msg = match command_name.as_str() {
-            "ping" => message::success("Pong!"),
-            _ => return Ok(()),
-        };
-        app_command
-            .create_interaction_response(ctx, |resp| {
-                *resp = msg.into();
-                resp
-            })
-            .await
-            .map_err(|e| {
-                eprintln!("Cannot create response: {}", e);
-                e.to_string()
-            })
-    }
 }
@@ -10,6 +10,10 @@ mod slash;
 pub use slash::*;
 mod modo;
 pub use modo::*;
+mod component;
+pub use component::*;
+mod admin;
+pub use admin::*;
 
 // Fonctions utiles pour les composants
-mod utils;
+pub(crate) mod utils;
@@ -1,20 +1,20 @@
 mod time;
 use super::utils;
 use super::utils::{
-    app_command::{get_argument, ApplicationCommandEmbed},
-    message, Data,
+    app_command::{handler, ApplicationCommandEmbed},
+    message, Data, GuildData, Schema,
 };
+use crate::component_system::api::{self, ApiError, ApiRequest, ApiResult, Method};
+use crate::component_system::bus::{Bus, BusEvent, Sanction};
 use crate::component_system::{self as cmp, command_parser as cmd};
+use crate::metrics;
 use chrono::{DateTime, Utc};
 use futures_locks::RwLock;
 use serde::{Deserialize, Serialize};
-use serenity::model::{
-    event::ReadyEvent,
-    id::{ApplicationId, GuildId},
-    interactions::application_command::ApplicationCommandInteraction,
-    prelude::*,
-};
+use serde_json::json;
+use serenity::model::{event::ReadyEvent, id::GuildId, prelude::*};
 use serenity::{async_trait, client::Context};
+use std::sync::Arc;
 use tokio::sync::oneshot::Sender;
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
@@ -32,12 +32,28 @@ impl std::fmt::Display for TypeModeration {
     }
 }
 impl TypeModeration {
+    /// Sanction publiée sur le bus
+    fn sanction(&self, disable: bool) -> Sanction {
+        match (self, disable) {
+            (TypeModeration::Ban, false) => Sanction::Ban,
+            (TypeModeration::Ban, true) => Sanction::Unban,
+            (TypeModeration::Mute, false) => Sanction::Mute,
+            (TypeModeration::Mute, true) => Sanction::Unmute,
+        }
+    }
     fn as_str(&self) -> &'static str {
         match self {
             TypeModeration::Ban => "ban",
             TypeModeration::Mute => "mute",
         }
     }
+    fn from_str(what: &str) -> Option<Self> {
+        match what {
+            "ban" => Some(TypeModeration::Ban),
+            "mute" => Some(TypeModeration::Mute),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -62,13 +78,30 @@ struct ModerationData {
     mod_until: Vec<Action>,
     muted_role: u64,
 }
+impl ModerationData {
+    /// Modèle des données de modération
+    ///
+    /// La version 1 est identique aux données enregistrées sans version ou en version 0.
+    fn schema() -> Schema {
+        Schema::new(1).add_migration(0, |data: ModerationData| data)
+    }
+}
+/// Sanction temporaire en cours : serveur, membre, type de sanction et canal d'arrêt de la tâche
+type ModerationTask = (GuildId, UserId, TypeModeration, Sender<()>);
 #[derive(Debug)]
 pub struct Moderation {
     node: cmd::Node,
-    owners: Vec<UserId>,
-    app_id: ApplicationId,
-    data: RwLock<Data<ModerationData>>,
-    tasks: RwLock<Vec<(UserId, TypeModeration, Sender<()>)>>,
+    inner: Arc<ModerationInner>,
+}
+/// Données et actions du composant
+///
+/// Partagé entre le composant et les handlers de ses commandes.
+#[derive(Debug)]
+struct ModerationInner {
+    data: GuildData<ModerationData>,
+    tasks: RwLock<Vec<ModerationTask>>,
+    /// Bus de messages entre composants, sur lequel les sanctions sont publiées
+    bus: Bus,
 }
 
 #[async_trait]
@@ -87,16 +120,31 @@ impl cmp::Component for Moderation {
     }
 
     async fn event(&self, ctx: &cmp::Context, evt: &cmp::Event) -> Result<(), String> {
-        self.r_event(ctx, evt).await
+        self.inner.r_event(ctx, evt).await
+    }
+    fn subscriptions(&self) -> cmp::EventKinds {
+        cmp::EventKinds::READY
+    }
+    async fn shutdown(&self) {
+        self.inner.stop_tasks().await
     }
     fn node(&self) -> Option<&cmd::Node> {
         Some(&self.node)
     }
+    async fn api(&self, ctx: &cmp::Context, request: &ApiRequest) -> Option<ApiResult> {
+        self.inner.api(ctx, request).await
+    }
 }
 
 impl Moderation {
-    pub fn new(app_id: ApplicationId, owners: Vec<UserId>) -> Moderation {
+    pub fn new(bus: Bus) -> Moderation {
+        let inner = Arc::new(ModerationInner {
+            data: GuildData::new("moderation").set_schema(ModerationData::schema()),
+            tasks: RwLock::new(Vec::new()),
+            bus,
+        });
         let ban = cmd::Command::new("ban")
+            .set_permission(utils::STAFF_PERMISSION)
             .set_help(
                 "Bannir un membre du serveur. Temporaire si le parametre *pendant* est renseigné.",
             )
@@ -121,6 +169,7 @@ impl Moderation {
             .set_name("mute")
             .set_help("Attribue le rôle *muted* à un membre. Temporaire si le parametre *pendant* est renseigné.");
         let unban = cmd::Command::new("unban")
+            .set_permission(utils::STAFF_PERMISSION)
             .set_help("Unban un membre")
             .add_param(
                 cmd::Argument::new("qui")
@@ -132,32 +181,27 @@ impl Moderation {
             .clone()
             .set_name("unmute")
             .set_help("Retire le rôle *muted* à un membre.");
-        let node = cmd::Node::new()
-            .add_command(ban)
-            .add_command(mute)
-            .add_command(unban)
-            .add_command(unmute);
-        Moderation {
-            node,
-            app_id,
-            data: match Data::from_file_default("moderation") {
-                Ok(data) => RwLock::new(data),
-                Err(e) => panic!("Data moderation: {:?}", e),
-            },
-            owners,
-            tasks: RwLock::new(Vec::new()),
-        }
+        let mut node = cmd::Node::new()
+            .add_command(ban.set_handler(handler!(inner, moderate, TypeModeration::Ban, false)))
+            .add_command(mute.set_handler(handler!(inner, moderate, TypeModeration::Mute, false)))
+            .add_command(unban.set_handler(handler!(inner, moderate, TypeModeration::Ban, true)))
+            .add_command(unmute.set_handler(handler!(inner, moderate, TypeModeration::Mute, true)))
+            .add_context_command(
+                cmd::ContextCommand::new("Mute", cmd::ContextTarget::User)
+                    .set_permission(utils::STAFF_PERMISSION)
+                    .set_handler(handler!(inner, moderate, TypeModeration::Mute, false)),
+            );
+        node.generate_ids(&[]);
+        Moderation { node, inner }
     }
+}
+
+impl ModerationInner {
     // region: discord interface
     async fn r_event(&self, ctx: &cmp::Context, evt: &cmp::Event) -> Result<(), String> {
         use cmp::Event::*;
-        use serenity::model::interactions::Interaction::*;
         match evt {
             Ready(ReadyEvent { ready, .. }) => self.on_ready(ctx, ready).await,
-            InteractionCreate(InteractionCreateEvent {
-                interaction: ApplicationCommand(c),
-                ..
-            }) => self.on_applications_command(ctx, c).await,
             _ => Ok(()),
         }
     }
@@ -166,84 +210,173 @@ impl Moderation {
         ctx: &cmp::Context,
         ready: &serenity::model::gateway::Ready,
     ) -> Result<(), String> {
-        let (mod_until, muted_role, guild_id) = {
-            let data = self.data.read().await;
+        let guilds = ready.guilds.iter().map(|g| g.id()).collect::<Vec<_>>();
+        self.data
+            .migrate_legacy(&guilds)
+            .map_err(|e| format!("Data moderation: {}", e))?;
+        let errors = futures::future::join_all(
+            guilds
+                .into_iter()
+                .map(|guild_id| self.on_guild_ready(ctx, guild_id)),
+        )
+        .await
+        .into_iter()
+        .filter_map(Result::err)
+        .collect::<Vec<_>>();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("\n"))
+        }
+    }
+    /// Initialise le rôle muted et relance les sanctions temporaires d'un serveur
+    async fn on_guild_ready(&self, ctx: &cmp::Context, guild_id: GuildId) -> Result<(), String> {
+        let data = self
+            .data
+            .get(guild_id)
+            .await
+            .map_err(|e| format!("Data moderation du serveur {}: {}", guild_id, e))?;
+        let (mod_until, muted_role) = {
+            let data = data.read().await;
             let data = data.read();
-
-            let guild_id = ready
-                .guilds
-                .iter()
-                .map(|g| g.id())
-                .next()
-                .ok_or_else(|| "No guild found".to_string())?;
-            (data.mod_until.clone(), data.muted_role, guild_id)
+            (data.mod_until.clone(), data.muted_role)
         };
         if muted_role == 0 {
             let role = guild_id
                 .roles(ctx)
                 .await
-                .map_err(|e| format!("Impossible d'obtenir la liste des roles du serveur: {}", e))?
+                .map_err(|e| {
+                    format!(
+                        "Impossible d'obtenir la liste des roles du serveur {}: {}",
+                        guild_id, e
+                    )
+                })?
                 .into_iter()
                 .find(|(_, role)| role.name == "muted")
-                .ok_or_else(|| "Impossible de trouver le role muted".to_string())?;
-            self.data.write().await.write().muted_role = role.0 .0;
+                .ok_or_else(|| {
+                    format!(
+                        "Impossible de trouver le role muted du serveur {}",
+                        guild_id
+                    )
+                })?;
+            data.write().await.write().muted_role = role.0 .0;
         }
         futures::future::join_all(
             mod_until
                 .into_iter()
-                .map(|act| self.make_task(ctx.clone(), guild_id, act)),
+                .map(|act| self.make_task(ctx.clone(), guild_id, act, data.clone())),
         )
         .await;
         Ok(())
     }
-    async fn on_applications_command(
+    // endregion: discord interface
+    // region: admin api
+    /// Routes de l'API d'administration
+    ///
+    /// * `GET /components/mod/guilds/<serveur>/actions` : sanctions temporaires en cours du serveur
+    /// * `DELETE /components/mod/guilds/<serveur>/actions/<membre>/<ban|mute>` : lève immédiatement la sanction
+    async fn api(&self, ctx: &cmp::Context, request: &ApiRequest) -> Option<ApiResult> {
+        Some(match (request.method, request.segments().as_slice()) {
+            (Method::Get, ["guilds", guild_id, "actions"]) => match api::parse_id(guild_id) {
+                Ok(guild_id) => self.api_actions(GuildId(guild_id)).await,
+                Err(e) => Err(e),
+            },
+            (Method::Delete, ["guilds", guild_id, "actions", user_id, what]) => {
+                let what = match TypeModeration::from_str(what) {
+                    Some(what) => what,
+                    None => {
+                        return Some(Err(ApiError::bad_request("Sanction attendue: ban ou mute")))
+                    }
+                };
+                match (api::parse_id(guild_id), api::parse_id(user_id)) {
+                    (Ok(guild_id), Ok(user_id)) => {
+                        self.api_cancel(ctx, GuildId(guild_id), UserId(user_id), what)
+                            .await
+                    }
+                    (Err(e), _) | (_, Err(e)) => Err(e),
+                }
+            }
+            _ => return None,
+        })
+    }
+    /// Liste les sanctions temporaires enregistrées du serveur et si leur tâche est en cours
+    async fn api_actions(&self, guild_id: GuildId) -> ApiResult {
+        let data = self.data.get(guild_id).await.map_err(|e| {
+            ApiError::internal(format!("Données de modération indisponibles: {}", e))
+        })?;
+        let mod_until = data.read().await.read().mod_until.clone();
+        let tasks = self.tasks.read().await;
+        Ok(mod_until
+            .iter()
+            .map(|action| {
+                let until = DateTime::<Utc>::from_utc(
+                    chrono::NaiveDateTime::from_timestamp(action.time, 0),
+                    Utc,
+                );
+                let active = tasks.iter().any(|(guild, user_id, what, _)| {
+                    *guild == guild_id && user_id.0 == action.user_id && *what == action.type_mod
+                });
+                json!({
+                    "user_id": action.user_id.to_string(),
+                    "sanction": action.type_mod.as_str(),
+                    "until": until.to_rfc3339(),
+                    "active": active,
+                })
+            })
+            .collect())
+    }
+    /// Lève immédiatement la sanction temporaire `what` du membre `user_id`
+    async fn api_cancel(
         &self,
         ctx: &Context,
-        app_command: &ApplicationCommandInteraction,
-    ) -> Result<(), String> {
-        if app_command.application_id != self.app_id {
-            // La commande n'est pas destiné à ce bot
-            return Ok(());
-        }
-        let app_cmd = ApplicationCommandEmbed::new(app_command);
-        let guild_id = match app_cmd.get_guild_id() {
-            Some(v) => v,
-            None => {
-                return Err("Vous devez être dans un serveur pour utiliser cette commande.".into())
+        guild_id: GuildId,
+        user_id: UserId,
+        what: TypeModeration,
+    ) -> ApiResult {
+        let data = self.data.get(guild_id).await.map_err(|e| {
+            ApiError::internal(format!("Données de modération indisponibles: {}", e))
+        })?;
+        let muted_role = {
+            let data = data.read().await;
+            let data = data.read();
+            if !data
+                .mod_until
+                .iter()
+                .any(|a| a.user_id == user_id.0 && a.type_mod == what)
+            {
+                return Err(ApiError::not_found("Sanction temporaire non trouvée"));
             }
+            Some(RoleId(data.muted_role)).filter(|_| what == TypeModeration::Mute)
         };
-        let command_name = app_cmd.fullname();
-        let msg = match command_name.as_str() {
-            "ban" => {
-                self.moderate(ctx, guild_id, &app_cmd, TypeModeration::Ban, false)
-                    .await
-            }
-            "mute" => {
-                self.moderate(ctx, guild_id, &app_cmd, TypeModeration::Mute, false)
-                    .await
-            }
-            "unban" => {
-                self.moderate(ctx, guild_id, &app_cmd, TypeModeration::Ban, true)
-                    .await
-            }
-            "unmute" => {
-                self.moderate(ctx, guild_id, &app_cmd, TypeModeration::Mute, true)
-                    .await
-            }
-            _ => return Ok(()),
-        }
-        .or_else(|e| -> Result<message::Message, ()> { Ok(message::error(e).set_ephemeral(true)) })
-        .unwrap();
-
-        app_command
-            .create_interaction_response(ctx, |resp| {
-                *resp = msg.into();
-                resp
-            })
+        Self::do_action(ctx, guild_id, user_id, what, true, None, muted_role)
             .await
-            .map_err(|e| format!("Cannot create response: {}", e))
+            .map_err(|e| ApiError::internal(format!("Impossible de lever la sanction: {}", e)))?;
+        self.bus.publish(
+            "mod",
+            BusEvent::MemberSanctioned {
+                guild_id,
+                user_id,
+                moderator: ctx.cache.current_user_id().await,
+                sanction: what.sanction(true),
+                reason: Some("Sanction levée depuis l'API d'administration".to_string()),
+            },
+        );
+        tokio::join!(
+            self.remove_task(guild_id, user_id, what),
+            Self::remove_until(&data, user_id.0, what)
+        );
+        let what_str = format!("un{}", what.as_str());
+        Self::write_log(
+            &format!("<@{}>", user_id),
+            "admin API",
+            &what_str,
+            None,
+            None,
+        )
+        .await;
+        Ok(json!({ "user_id": user_id.to_string(), "sanction": what_str }))
     }
-    // endregion: discord interface
+    // endregion: admin api
     // region: tasks
     async fn task(
         ctx: Context,
@@ -262,7 +395,7 @@ impl Moderation {
                 let mut member = match guild_id.member(&ctx, action.user_id).await {
                     Ok(v) => v,
                     Err(e) => {
-                        eprintln!("Impossible d'avoir le membre {}: {}", action.user_id, e);
+                        log::error!(target: "mod", "Impossible d'avoir le membre {}: {}", action.user_id, e);
                         return;
                     }
                 };
@@ -278,9 +411,9 @@ impl Moderation {
             .map(|user| format!("{}#{} ({})", user.name, user.discriminator, action.user_id))
             .unwrap_or_else(|_| action.user_id.to_string());
         if let Err(e) = action_done {
-            eprintln!("modo::task erreur {}: {}", username, e);
+            log::error!(target: "mod", "task erreur {}: {}", username, e);
         } else {
-            println!("modo::task: Sanction contre {} retiré", username);
+            log::info!(target: "mod", "task: Sanction contre {} retiré", username);
             let mut data = data.write().await;
             let mut data = data.write();
             let mod_until = &mut data.mod_until;
@@ -291,55 +424,82 @@ impl Moderation {
                 .map(|idx| mod_until.remove(idx))
             {
                 Some(_) => (),
-                None => eprintln!(
-                    "modo::task: sanction non trouvée dans les données pour l'utilisateur {}",
+                None => log::error!(
+                    target: "mod",
+                    "task: sanction non trouvée dans les données pour l'utilisateur {}",
                     username
                 ),
             };
         }
     }
-    async fn make_task(&self, ctx: Context, guild_id: GuildId, action: Action) {
+    /// Lance la tâche qui lève la sanction temporaire `action` à son échéance.
+    ///
+    /// La sanction est comptée dans les [métriques](metrics::ACTIVE_SANCTIONS) tant que la tâche est en cours.
+    async fn make_task(
+        &self,
+        ctx: Context,
+        guild_id: GuildId,
+        action: Action,
+        data: RwLock<Data<ModerationData>>,
+    ) {
         let who = match guild_id.member(&ctx, action.user_id).await {
             Ok(v) => v,
             Err(e) => {
-                eprintln!("Impossible d'avoir le membre {}: {}", action.user_id, e);
+                log::error!(target: "mod", "Impossible d'avoir le membre {}: {}", action.user_id, e);
                 return;
             }
         };
-        let task = Self::task(ctx, guild_id, action.clone(), self.data.clone());
+        let task = Self::task(ctx, guild_id, action.clone(), data);
         let (stop_task, stop_me) = tokio::sync::oneshot::channel();
+        let (guild, sanction) = (guild_id.to_string(), action.type_mod.as_str());
+        metrics::ACTIVE_SANCTIONS.add(&[&guild, sanction], 1);
         tokio::spawn(async move {
             tokio::select! {
-                _ = task => println!("{} du membre {} fini", action.type_mod, who.display_name()),
-                _ = stop_me => println!("Arrêt {} temporaire de {}", action.type_mod, who.display_name()),
+                _ = task => log::info!(target: "mod", "{} du membre {} fini", action.type_mod, who.display_name()),
+                _ = stop_me => log::info!(target: "mod", "Arrêt {} temporaire de {}", action.type_mod, who.display_name()),
             }
+            metrics::ACTIVE_SANCTIONS.add(&[&guild, sanction], -1);
         });
-        self.tasks
-            .write()
-            .await
-            .push((UserId(action.user_id), action.type_mod, stop_task));
+        self.tasks.write().await.push((
+            guild_id,
+            UserId(action.user_id),
+            action.type_mod,
+            stop_task,
+        ));
     }
-    async fn remove_task(&self, who: UserId, type_mod: TypeModeration) {
+    /// Arrête toutes les tâches de sanctions temporaires.
+    ///
+    /// Les sanctions restent enregistrées et seront relancées au prochain démarrage du bot.
+    async fn stop_tasks(&self) {
+        for (_, _, _, stop_task) in self.tasks.write().await.drain(..) {
+            stop_task.send(()).unwrap_or(());
+        }
+    }
+    async fn remove_task(&self, guild_id: GuildId, who: UserId, type_mod: TypeModeration) {
         let mut tasks = self.tasks.write().await;
-        let idx = match tasks
-            .iter()
-            .position(|(user_id, t, _)| user_id == &who && t == &type_mod)
-        {
+        let idx = match tasks.iter().position(|(guild, user_id, t, _)| {
+            guild == &guild_id && user_id == &who && t == &type_mod
+        }) {
             Some(idx) => idx,
             None => return,
         };
-        let (_, _, stop_task) = tasks.remove(idx);
+        let (_, _, _, stop_task) = tasks.remove(idx);
         stop_task.send(()).unwrap_or(());
     }
-    async fn add_until(&self, who: u64, when: i64, what: TypeModeration) -> Action {
-        let mut data = self.data.write().await;
+    async fn add_until(
+        data: &RwLock<Data<ModerationData>>,
+        who: u64,
+        when: i64,
+        what: TypeModeration,
+    ) -> Action {
+        let mut data = data.write().await;
         let mut data = data.write();
         let result = Action::new(what, who, when);
         data.mod_until.push(result.clone());
         result
     }
-    async fn remove_until(&self, who: u64, what: TypeModeration) {
-        let mut data = self.data.write().await;
+    async fn remove_until(data: &RwLock<Data<ModerationData>>, who: u64, what: TypeModeration) {
+        let mut data = data.write().await;
         let mut data = data.write();
         data.mod_until
             .iter()
@@ -351,36 +511,43 @@ impl Moderation {
     }
     // endregion
     // region: actions
+    /// Applique ou lève une sanction.
+    ///
+    /// Depuis le menu contextuel d'un membre, le membre ciblé remplace l'argument *qui*
+    /// et la sanction est permanente, sans raison précisée.
     async fn moderate(
         &self,
         ctx: &Context,
-        guild_id: GuildId,
         app_cmd: &ApplicationCommandEmbed<'_>,
         what: TypeModeration,
         disable: bool,
-    ) -> Result<message::Message, String> {
+    ) -> cmd::HandlerResult {
+        let guild_id = app_cmd.require_guild_id()?;
         let user_cmd = &app_cmd.0.member.as_ref().unwrap().user;
         let what_str = format!("{}{}", if disable { "un" } else { "" }, what.as_str());
-        let user = get_argument!(app_cmd, "qui", User)
-            .map(|v| v.0)
-            .ok_or_else(|| "Vous devez mentionner un membre.".to_string())
-            .and_then(|user| {
-                if user.id != user_cmd.id {
-                    Ok(user)
-                } else {
-                    Err(format!("Vous ne pouvez pas vous {} vous-même.", &what_str))
-                }
-            })?;
-        let reason = if !disable {
-            Some(
-                get_argument!(app_cmd, "pourquoi", String)
-                    .map(|v| v)
-                    .ok_or_else(|| "Raison non specifiée.".to_string())?,
-            )
-        } else {
-            None
+        let command = app_cmd.command();
+        let user = match app_cmd.target_user() {
+            Some(target) => Ok(target.id),
+            None => command.get::<UserId>("qui"),
+        };
+        let user = user.and_then(|user| {
+            if user != user_cmd.id {
+                Ok(user)
+            } else {
+                Err(format!("Vous ne pouvez pas vous {} vous-même.", &what_str))
+            }
+        })?;
+        let user = user
+            .to_user(ctx)
+            .await
+            .map_err(|e| format!("Impossible d'obtenir le membre: {}", e))?;
+        let reason = match (disable, app_cmd.target_user()) {
+            (true, _) => None,
+            (false, Some(_)) => Some("Aucune raison précisée".to_string()),
+            (false, None) => Some(command.get::<String>("pourquoi")?),
         };
-        let time = match (disable, get_argument!(app_cmd, "pendant", String)) {
+        let pendant = command.opt::<String>("pendant")?;
+        let time = match (disable, pendant.as_ref()) {
             (false, Some(v)) => {
                 let duration_second = match time::parse(v) {
                     Ok(v) => v as _,
@@ -392,8 +559,13 @@ impl Moderation {
             }
             _ => None,
         };
+        let data = self
+            .data
+            .get(guild_id)
+            .await
+            .map_err(|e| format!("Données de modération indisponibles: {}", e))?;
         let muted_role = if what == TypeModeration::Mute {
-            let muted_role = self.data.read().await.read().muted_role;
+            let muted_role = data.read().await.read().muted_role;
             if muted_role == 0 {
                 return Err("Le rôle de mute n'est pas défini.".into());
             }
@@ -406,25 +578,43 @@ impl Moderation {
             match self
                 .warn_member(
                     ctx,
-                    user,
+                    &user,
                     &what_str,
                     when.as_deref(),
-                    reason.map(|v| v.as_str()).unwrap(),
+                    reason.as_deref().unwrap(),
                     guild_id.name(ctx).await.unwrap().as_str(),
                 )
                 .await
             {
-                Err(e) => println!("[WARN] Impossible d'avertir le membre: {}", e),
+                Err(e) => log::warn!(target: "mod", "Impossible d'avertir le membre: {}", e),
                 _ => (),
             }
         }
-        Self::do_action(ctx, guild_id, user.id, what, disable, reason, muted_role)
-            .await
-            .map_err(|e| format!("Impossible de {} le membre: {}", what_str, e))?;
+        Self::do_action(
+            ctx,
+            guild_id,
+            user.id,
+            what,
+            disable,
+            reason.as_ref(),
+            muted_role,
+        )
+        .await
+        .map_err(|e| format!("Impossible de {} le membre: {}", what_str, e))?;
+        self.bus.publish(
+            "mod",
+            BusEvent::MemberSanctioned {
+                guild_id,
+                user_id: user.id,
+                moderator: user_cmd.id,
+                sanction: what.sanction(disable),
+                reason: reason.clone(),
+            },
+        );
 
         tokio::join!(
-            self.remove_task(user.id, what),
-            self.remove_until(user.id.0, what)
+            self.remove_task(guild_id, user.id, what),
+            Self::remove_until(&data, user.id.0, what)
         );
 
         let username = format!("{}#{} (<@{}>)", user.name, user.discriminator, user.id);
@@ -434,20 +624,21 @@ impl Moderation {
             &username,
             &who_did,
             &what_str,
-            reason.map(|v| v.as_str()),
+            reason.as_deref(),
             time.map(|v| v.2.as_str()),
         )
         .await;
 
         let mut msg = message::success(format!("{} a été {}.", username, what_str));
-        if let Some(reason) = reason {
+        if let Some(reason) = &reason {
             msg.embed.as_mut().unwrap().field("Raison", reason, false);
         }
         if let Some((timestamp, datetime, duration)) = time {
             self.make_task(
                 ctx.clone(),
                 guild_id,
-                self.add_until(user.id.0, timestamp, what).await,
+                Self::add_until(&data, user.id.0, timestamp, what).await,
+                data.clone(),
             )
             .await;
             msg.embed
@@ -535,7 +726,7 @@ impl Moderation {
         {
             Ok(v) => v,
             Err(e) => {
-                println!("Impossible d'ouvrir le fichier de log: {}", e);
+                log::error!(target: "mod", "Impossible d'ouvrir le fichier de log: {}", e);
                 return;
             }
         };
@@ -562,7 +753,7 @@ impl Moderation {
         })() {
             Ok(_) => (),
             Err(e) => {
-                println!("Impossible d'écrire dans le fichier de log: {}", e);
+                log::error!(target: "mod", "Impossible d'écrire dans le fichier de log: {}", e);
             }
         }
     }
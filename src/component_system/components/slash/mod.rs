@@ -1,29 +1,148 @@
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 
 use super::utils::message;
 use crate::component_system::slash;
 use crate::component_system::{
     self as cmp,
+    api::{self, ApiError, ApiRequest, ApiResult, Method},
+    bus::BusEvent,
     command_parser::{self as cmd, Named},
-    components::utils::app_command::{get_argument, ApplicationCommandEmbed},
+    components::utils::{
+        app_command::{handler, ApplicationCommandEmbed},
+        commands::Mentionable,
+    },
     manager::ArcManager,
 };
 use futures_locks::RwLock;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use serenity::{
     async_trait,
-    builder::CreateApplicationCommands,
+    builder::CreateApplicationCommand,
     client::Context,
+    http::{
+        request::{Request, RequestBuilder},
+        routing::RouteInfo,
+    },
     model::{
-        event::InteractionCreateEvent,
-        id::{ApplicationId, GuildId, UserId},
+        event::{GuildCreateEvent, GuildDeleteEvent},
+        id::{ApplicationId, ChannelId, GuildId, MessageId},
         interactions::application_command::{
-            ApplicationCommand, ApplicationCommandInteraction,
-            ApplicationCommandInteractionDataOption, ApplicationCommandInteractionDataOptionValue,
-            ApplicationCommandPermissionType,
+            ApplicationCommand, ApplicationCommandPermissionData, ApplicationCommandPermissionType,
         },
     },
 };
 
+/// Portée d'enregistrement des commandes d'un composant
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CommandScope {
+    /// Commandes globales, disponibles sur tous les serveurs
+    Global,
+    /// Commandes enregistrées sur chaque serveur, ou seulement sur les serveurs listés
+    Guild(Option<Vec<u64>>),
+}
+impl Default for CommandScope {
+    fn default() -> Self {
+        CommandScope::Guild(None)
+    }
+}
+impl CommandScope {
+    /// Indique si la portée inclut le serveur `guild_id`, ou les commandes globales si None
+    pub fn includes(&self, guild_id: Option<GuildId>) -> bool {
+        match (self, guild_id) {
+            (CommandScope::Global, None) => true,
+            (CommandScope::Guild(None), Some(_)) => true,
+            (CommandScope::Guild(Some(guilds)), Some(guild_id)) => guilds.contains(&guild_id.0),
+            _ => false,
+        }
+    }
+}
+
+/// Configuration de l'enregistrement des slash commandes
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SlashConfig {
+    /// Portée par défaut des commandes des composants. Sur chaque serveur par défaut.
+    #[serde(default)]
+    pub scope: CommandScope,
+    /// Portée des commandes de certains composants, par nom de composant
+    #[serde(default)]
+    pub components: HashMap<String, CommandScope>,
+}
+impl SlashConfig {
+    /// Portée des commandes du composant `component`
+    pub fn scope(&self, component: &str) -> &CommandScope {
+        self.components.get(component).unwrap_or(&self.scope)
+    }
+}
+
+/// Cible d'une permission de commande
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+enum PermissionTarget {
+    Role,
+    User,
+}
+impl From<PermissionTarget> for ApplicationCommandPermissionType {
+    fn from(target: PermissionTarget) -> Self {
+        match target {
+            PermissionTarget::Role => ApplicationCommandPermissionType::Role,
+            PermissionTarget::User => ApplicationCommandPermissionType::User,
+        }
+    }
+}
+/// Permission d'un rôle ou d'un membre sur une commande, telle qu'exportée dans un fichier
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+struct PermissionEntry {
+    kind: PermissionTarget,
+    id: u64,
+    allow: bool,
+}
+impl PermissionEntry {
+    /// Convertit une permission retournée par Discord. Retourne None si la cible est inconnue.
+    fn from_data(data: &ApplicationCommandPermissionData) -> Option<Self> {
+        let kind = match data.kind {
+            ApplicationCommandPermissionType::Role => PermissionTarget::Role,
+            ApplicationCommandPermissionType::User => PermissionTarget::User,
+            _ => return None,
+        };
+        Some(PermissionEntry {
+            kind,
+            id: data.id.0,
+            allow: data.permission,
+        })
+    }
+}
+impl std::fmt::Display for PermissionEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mention = match self.kind {
+            PermissionTarget::Role => "&",
+            PermissionTarget::User => "",
+        };
+        let permission = match self.allow {
+            true => "autorisé",
+            false => "refusé",
+        };
+        write!(f, "<@{}{}> {}", mention, self.id, permission)
+    }
+}
+/// Contenu d'un fichier de permissions : les permissions de chaque commande, par nom de commande
+type PermissionsFile = BTreeMap<String, Vec<PermissionEntry>>;
+
+/// Nombre maximum de permissions d'une commande accepté par Discord
+const MAX_COMMAND_PERMISSIONS: usize = 10;
+/// Taille maximale d'un fichier de permissions importé, en octets
+const MAX_PERMISSIONS_FILE_SIZE: u64 = 1024 * 1024;
+
+/// Lit un lien vers un message Discord, ou un identifiant de message seul
+///
+/// Retourne le salon du message s'il est précisé dans le lien, et l'identifiant du message.
+fn parse_message_link(link: &str) -> Option<(Option<ChannelId>, MessageId)> {
+    let mut ids = link.trim().rsplit('/');
+    let message_id = ids.next()?.parse().ok()?;
+    let channel_id = ids.next().and_then(|id| id.parse().ok()).map(ChannelId);
+    Some((channel_id, MessageId(message_id)))
+}
+
 /// Composant de gestion des commandes de l'application.
 ///
 /// S'occupe d'assigner les slashs commandes à discord et de gérer leur permissions.
@@ -31,12 +150,29 @@ use serenity::{
 /// Au lancement du bot, le composant parcours les différents composant du bot et
 /// génère les slashs commandes associés en se reposant sur notre API de *command parser*
 /// pour les envoyer à Discord.
+///
+/// Les commandes d'un composant sont enregistrées globalement ou par serveur selon sa [portée](CommandScope).
+/// Seules les différences avec les commandes déjà enregistrées sont envoyées (voir [`slash::CommandsDiff`]).
+/// En mode *dry run*, les modifications prévues sont affichées dans les logs sans être appliquées.
+///
+/// Les permissions des commandes d'un serveur peuvent être exportées dans un fichier RON ou JSON,
+/// puis importées sur un serveur depuis un message auquel le fichier est joint.
 pub struct SlashCommands {
-    manager: ArcManager,
-    owners: Vec<UserId>,
     group_match: cmd::Node,
-    commands: RwLock<Vec<(GuildId, Vec<ApplicationCommand>)>>,
+    /// Données et actions du composant, partagées avec les handlers des commandes
+    inner: Arc<SlashInner>,
+}
+struct SlashInner {
+    manager: ArcManager,
+    /// Commandes enregistrées de chaque serveur, None pour les commandes globales
+    commands: RwLock<Vec<(Option<GuildId>, Vec<ApplicationCommand>)>>,
     app_id: ApplicationId,
+    /// Portée des commandes de chaque composant
+    config: SlashConfig,
+    /// Si vrai, les modifications des commandes sont affichées sans être envoyées à Discord
+    dry_run: RwLock<bool>,
+    /// Serveurs dont les commandes ont été enregistrées, ou sont en cours d'enregistrement, depuis le démarrage
+    synced_guilds: RwLock<Vec<GuildId>>,
 }
 #[async_trait]
 impl cmp::Component for SlashCommands {
@@ -54,11 +190,46 @@ impl cmp::Component for SlashCommands {
     }
 
     async fn event(&self, ctx: &cmp::Context, evt: &cmp::Event) -> Result<(), String> {
-        self.r_event(ctx, evt).await
+        self.inner.r_event(ctx, evt).await
+    }
+    fn subscriptions(&self) -> cmp::EventKinds {
+        cmp::EventKinds::READY | cmp::EventKinds::GUILD
+    }
+    async fn init(&self, ctx: &Context) -> Result<(), String> {
+        let inner = Arc::clone(&self.inner);
+        let ctx = ctx.clone();
+        let bus = self.inner.manager.read().await.bus().clone();
+        bus.subscribe("slash", move |evt| {
+            let inner = Arc::clone(&inner);
+            let ctx = ctx.clone();
+            async move { inner.on_bus_event(&ctx, evt).await }
+        });
+        Ok(())
     }
     fn node(&self) -> Option<&cmd::Node> {
         Some(&self.group_match)
     }
+    async fn api(&self, ctx: &Context, request: &ApiRequest) -> Option<ApiResult> {
+        self.inner.api(ctx, request).await
+    }
+}
+
+/// Autocomplétion des noms des slash commandes utilisables sur le serveur
+struct CommandNames(Arc<SlashInner>);
+
+#[async_trait]
+impl cmd::AutocompleteProvider for CommandNames {
+    async fn values(&self, _: &Context, guild_id: Option<GuildId>) -> Vec<String> {
+        let commands = match guild_id {
+            Some(guild_id) => self.0.available_commands(guild_id).await,
+            None => None,
+        };
+        commands
+            .unwrap_or_default()
+            .into_iter()
+            .map(|command| command.name)
+            .collect()
+    }
 }
 
 /// Helper pour la lecture des différents arguments d'une commande du group `slash`.
@@ -66,42 +237,29 @@ impl cmp::Component for SlashCommands {
 /// Cette macro existe pour simplifier le code et éviter de répéter le code.
 macro_rules! slash_argument {
     ($app_cmd:ident, command: ($self: ident, $in_guild_id:ident, $out_opt_command: ident, $out_command_id:ident)) => {
-        let $out_opt_command = match get_argument!($app_cmd, "command", String) {
-            Some(v) => v,
-            None => return message::error("L'identifiant de la commande est requis.")
-        };
+        let $out_opt_command = $app_cmd.command().get::<String>("command")?;
         let $out_command_id = {
-            let commands = $self.commands.read().await;
-            let (_, commands) = match commands.iter().find(|(g, _)| *g == $in_guild_id) {
-                Some(list_commands) => list_commands,
-                None => return message::error("Le serveur n'est pas reconnu.")
+            let commands = match $self.available_commands($in_guild_id).await {
+                Some(commands) => commands,
+                None => return Err("Le serveur n'est pas reconnu.".into())
             };
-            match commands.iter().find(|c| &c.name == $out_opt_command) {
+            match commands.iter().find(|c| c.name == $out_opt_command) {
                 Some(command) => command.id,
-                None => return message::error("Commande non trouvé.")
+                None => return Err("Commande non trouvé.".into())
             }
         };
     };
     ($app_cmd:ident, who: $opt_name:ident) => {
-        let $opt_name = match $app_cmd.get_argument("who") {
-            Some(ApplicationCommandInteractionDataOption{
-                resolved: Some(ApplicationCommandInteractionDataOptionValue::User(user, _)),
-                ..
-            }) => (user.id.0, ApplicationCommandPermissionType::User),
-            Some(ApplicationCommandInteractionDataOption{
-                resolved: Some(ApplicationCommandInteractionDataOptionValue::Role(role)),
-                ..
-            }) => (role.id.0, ApplicationCommandPermissionType::Role),
-            None => return message::error("L'identifiant de l'utilisateur ou du rôle est requis."),
-            _ => return message::error("L'identifiant de l'utilisateur ou du rôle n'est pas reconnu."),
+        let $opt_name = match $app_cmd.command().get::<Mentionable>("who")? {
+            Mentionable::User(user) => (user.0, ApplicationCommandPermissionType::User),
+            Mentionable::Role(role) => (role.0, ApplicationCommandPermissionType::Role),
         };
     };
     ($app_cmd:ident, type: $opt_name:ident) => {
-        let $opt_name = match get_argument!($app_cmd, "type", String).and_then(|v| Some(v.as_str())) {
-            Some("allow") => true,
-            Some("deny") => false,
-            Some(s) => return message::error(format!("Type: mot clé `{}` non reconnu. `allow` ou `deny` attendus.", s)),
-            None => return message::error("Le type de permission est requis."),
+        let $opt_name = match $app_cmd.command().get::<String>("type")?.as_str() {
+            "allow" => true,
+            "deny" => false,
+            s => return Err(format!("Type: mot clé `{}` non reconnu. `allow` ou `deny` attendus.", s)),
         };
     };
     ($app_cmd:ident, $($name:ident: $var_name:tt),+) => {
@@ -112,20 +270,32 @@ macro_rules! slash_argument {
 }
 
 impl SlashCommands {
-    pub fn new(manager: ArcManager, owners: Vec<UserId>, app_id: ApplicationId) -> Self {
+    pub fn new(
+        manager: ArcManager,
+        app_id: ApplicationId,
+        dry_run: bool,
+        config: SlashConfig,
+    ) -> Self {
         use serenity::model::interactions::application_command::ApplicationCommandOptionType;
-        let autocomplete_commands = Arc::new(Vec::new());
+        let inner = Arc::new(SlashInner {
+            commands: RwLock::new(Vec::new()),
+            manager,
+            app_id,
+            config,
+            dry_run: RwLock::new(dry_run),
+            synced_guilds: RwLock::new(Vec::new()),
+        });
         let arg_command = cmd::Argument::new("command")
             .set_value_type(ApplicationCommandOptionType::String)
             .set_required(true)
             .set_help("Quel commande est affecté")
-            .set_autocomplete(autocomplete_commands);
+            .set_autocomplete(CommandNames(Arc::clone(&inner)));
         let arg_who = cmd::Argument::new("who")
             .set_value_type(ApplicationCommandOptionType::Mentionable)
             .set_required(true)
             .set_help("Qui est affecté");
 
-        let group_match = cmd::Node::new().add_group(
+        let mut group_match = cmd::Node::new().add_group(
             cmd::Group::new("slash")
                 .set_help("Gestion des commandes slash")
                 .set_permission("owners")
@@ -144,16 +314,15 @@ impl SlashCommands {
                                         .set_value_type(ApplicationCommandOptionType::String)
                                         .set_required(true)
                                         .set_help(r#"Type d'autorisation. "allow" ou "deny""#)
-                                        .set_autocomplete(Arc::new(vec![
-                                            "allow".to_string(),
-                                            "deny".to_string(),
-                                        ])),
-                                ),
+                                        .set_choices(["allow", "deny"]),
+                                )
+                                .set_handler(handler!(inner, slash_perms_add)),
                         )
                         .add_command(
                             cmd::Command::new("reset")
                                 .set_help("Retire toutes les permissions d'une commande.")
-                                .add_param(arg_command.clone()),
+                                .add_param(arg_command.clone())
+                                .set_handler(handler!(inner, slash_perms_reset)),
                         )
                         .add_command(
                             cmd::Command::new("remove")
@@ -161,116 +330,372 @@ impl SlashCommands {
                                     "Efface la permission d'un membre ou d'un rôle à une commande.",
                                 )
                                 .add_param(arg_command)
-                                .add_param(arg_who),
+                                .add_param(arg_who)
+                                .set_handler(handler!(inner, slash_perms_remove)),
                         )
                         .add_command(
                             cmd::Command::new("list")
-                                .set_help("Liste les permissions des commandes sur le serveur."),
+                                .set_help("Liste les permissions des commandes sur le serveur.")
+                                .set_handler(handler!(inner, slash_perms_list)),
+                        )
+                        .add_command(
+                            cmd::Command::new("export")
+                                .set_help("Exporte les permissions des commandes dans un fichier.")
+                                .add_param(
+                                    cmd::Argument::new("format")
+                                        .set_value_type(ApplicationCommandOptionType::String)
+                                        .set_help(r#"Format du fichier. "ron" par défaut"#)
+                                        .set_choices(["ron", "json"])
+                                        .set_default("ron"),
+                                )
+                                .set_handler(handler!(inner, slash_perms_export)),
+                        )
+                        .add_command(
+                            cmd::Command::new("import")
+                                .set_help("Importe les permissions du fichier joint à un message.")
+                                .add_param(
+                                    cmd::Argument::new("message")
+                                        .set_value_type(ApplicationCommandOptionType::String)
+                                        .set_required(true)
+                                        .set_help(
+                                            "Lien ou identifiant du message contenant le fichier",
+                                        ),
+                                )
+                                .add_param(
+                                    cmd::Argument::new("apply").set_flag().set_help(
+                                        "Applique l'import au lieu d'en afficher le résumé",
+                                    ),
+                                )
+                                .set_handler(handler!(inner, slash_perms_import)),
                         ),
                 ),
         );
-        SlashCommands {
-            commands: RwLock::new(Vec::new()),
-            group_match,
-            manager,
-            owners,
-            app_id,
-        }
+        group_match.generate_ids(&[]);
+        SlashCommands { group_match, inner }
     }
+}
+
+impl SlashInner {
+    /// Dispatch un évènement reçu par le bot
+    ///
+    /// Les commandes globales et celles des serveurs du `Ready` sont enregistrées au démarrage, puis celles
+    /// de chaque serveur rejoint pendant que le bot tourne. Elles sont retirées des serveurs quittés.
+    ///
+    /// Le `Ready` n'est transmis qu'après l'initialisation des composants : les `GuildCreate` des mêmes serveurs
+    /// peuvent donc arriver avant ou pendant son traitement. Chaque serveur n'est synchronisé qu'une fois
+    /// (voir [`SlashInner::claim_guild`]).
     async fn r_event(&self, ctx: &cmp::Context, evt: &cmp::Event) -> Result<(), String> {
         match evt {
             cmp::Event::Ready(ready) => {
-                let manager = self.manager.read().await;
-                let components = manager.get_components();
-                let guilds = &ready.ready.guilds;
-                let mut app_commands = CreateApplicationCommands::default();
-                for compo in components {
-                    let compo = compo.read().await;
-                    let node = match compo.node() {
-                        Some(group) => group,
-                        None => continue,
-                    };
-                    let commands = slash::register_root_with_perm(node, compo.name() == "slash");
-                    commands.iter().for_each(|c| {
-                        println!(
-                            "Name: {:?}, Perm: {:?}",
-                            c.0.get("name"),
-                            c.0.get("default_permission")
-                        )
-                    });
-                    commands.into_iter().for_each(|command| {
-                        app_commands.add_application_command(command);
-                    });
-                }
-                let mut commands = self.commands.write().await;
-                for guild in guilds {
-                    let guild_id = guild.id();
-                    match guild_id
-                        .set_application_commands(ctx, |v| {
-                            *v = app_commands.clone();
-                            v
-                        })
-                        .await
-                    {
-                        Ok(v) => commands.push((guild_id, v)),
-                        Err(why) => {
-                            let name = guild
-                                .id()
-                                .name(ctx)
-                                .await
-                                .unwrap_or_else(|| guild.id().to_string());
-                            eprintln!(
-                                "Could not set application commands for guild {}: {:?}",
-                                name, why
-                            );
-                        }
+                let guilds = ready
+                    .ready
+                    .guilds
+                    .iter()
+                    .map(|guild| guild.id())
+                    .collect::<Vec<_>>();
+                self.sync_commands(ctx, None).await;
+                for guild_id in guilds {
+                    if self.claim_guild(guild_id).await {
+                        self.sync_commands(ctx, Some(guild_id)).await;
                     }
                 }
-                println!("Slash commands setted.");
+                log::info!(target: "slash", "Slash commands setted.");
+            }
+            cmp::Event::GuildCreate(GuildCreateEvent { guild, .. }) => {
+                // Les serveurs du Ready sont aussi reçus en GuildCreate au démarrage
+                let joined = self.claim_guild(guild.id).await;
+                if joined {
+                    log::info!(target: "slash", "Guild {} joined", guild.name);
+                    self.sync_commands(ctx, Some(guild.id)).await;
+                }
+            }
+            // Un serveur indisponible n'a pas été quitté
+            cmp::Event::GuildDelete(GuildDeleteEvent { guild, .. }) if !guild.unavailable => {
+                self.synced_guilds.write().await.retain(|g| *g != guild.id);
+                self.commands
+                    .write()
+                    .await
+                    .retain(|(g, _)| *g != Some(guild.id));
+                // Les commandes restent sur le serveur tant que l'application y est autorisée
+                if let Err(why) = guild.id.set_application_commands(ctx, |c| c).await {
+                    log::debug!(
+                        target: "slash",
+                        "Could not remove application commands of left guild {}: {:?}",
+                        guild.id, why
+                    );
+                }
+                log::info!(target: "slash", "Guild {} left", guild.id);
             }
-            cmp::Event::InteractionCreate(InteractionCreateEvent {
-                interaction: serenity::model::interactions::Interaction::ApplicationCommand(c),
-                ..
-            }) => self.on_applications_command(ctx, c).await.unwrap_or(()),
             _ => (),
         }
         Ok(())
     }
-    /// Méthode appelée sur événement de création d'une application command
+    /// Réserve la synchronisation des commandes du serveur `guild_id`.
     ///
-    /// Dispatch les commandes de permission aux fonctions correspondantes
-    /// Voir les fonctions `slash_perms_*` pour plus de détails sur leur fonctionnement
-    async fn on_applications_command(
-        &self,
-        ctx: &Context,
-        app_command: &ApplicationCommandInteraction,
-    ) -> Result<(), String> {
-        if app_command.application_id != self.app_id {
-            // La commande n'est pas destiné à ce bot
-            return Ok(());
+    /// Retourne faux si le serveur a déjà été réservé, par le `Ready` ou par un `GuildCreate`. La vérification et
+    /// l'ajout sont faits sous le même verrou, pour que deux évènements concurrents ne synchronisent pas le serveur.
+    async fn claim_guild(&self, guild_id: GuildId) -> bool {
+        let mut synced = self.synced_guilds.write().await;
+        if synced.contains(&guild_id) {
+            false
+        } else {
+            synced.push(guild_id);
+            true
         }
-        let app_cmd = ApplicationCommandEmbed::new(app_command);
-        let guild_id = match app_cmd.get_guild_id() {
-            Some(v) => v,
-            None => {
-                return Err("Vous devez être dans un serveur pour utiliser cette commande.".into())
+    }
+    /// Enregistre les slash commandes du serveur `guild_id`, ou les commandes globales si None, et log les erreurs
+    async fn sync_commands(&self, ctx: &Context, guild_id: Option<GuildId>) {
+        if let Err(why) = self.register_commands(ctx, guild_id).await {
+            let name = match guild_id {
+                Some(guild_id) => guild_id
+                    .name(ctx)
+                    .await
+                    .unwrap_or_else(|| guild_id.to_string()),
+                None => "global scope".to_string(),
+            };
+            log::error!(
+                target: "slash",
+                "Could not set application commands for {}: {:?}",
+                name, why
+            );
+        }
+    }
+    /// Réagit aux évènements des autres composants
+    ///
+    /// Les slash commandes d'un serveur sont mises à jour lorsqu'un composant y est activé ou désactivé.
+    /// Le mode *dry run* est mis à jour lorsque la configuration est rechargée.
+    async fn on_bus_event(&self, ctx: &Context, evt: BusEvent) -> Result<(), String> {
+        match evt {
+            BusEvent::ConfigReloaded { slash_dry_run, .. } => {
+                *self.dry_run.write().await = slash_dry_run;
+                Ok(())
             }
+            BusEvent::ComponentToggled { guild_id, .. } => self
+                .register_commands(ctx, Some(guild_id))
+                .await
+                .map(|_| ())
+                .map_err(|why| format!("Could not set application commands: {:?}", why)),
+            _ => Ok(()),
+        }
+    }
+    /// Routes de l'API d'administration
+    ///
+    /// * `POST /components/slash/sync` : enregistre à nouveau les commandes globales et les slash commandes de tous les serveurs
+    /// * `POST /components/slash/global/sync` : enregistre à nouveau les commandes globales
+    /// * `POST /components/slash/guilds/<serveur>/sync` : enregistre à nouveau les slash commandes du serveur
+    /// * `GET /components/slash/global/diff` : modifications que la synchronisation des commandes globales apporterait, sans les appliquer
+    /// * `GET /components/slash/guilds/<serveur>/diff` : modifications que la synchronisation apporterait, sans les appliquer
+    async fn api(&self, ctx: &Context, request: &ApiRequest) -> Option<ApiResult> {
+        let scopes = match (request.method, request.segments().as_slice()) {
+            (Method::Get, ["global", "diff"]) => {
+                return Some(
+                    self.plan_commands(ctx, None)
+                        .await
+                        .map(|(_, diff)| diff.to_json())
+                        .map_err(ApiError::internal),
+                )
+            }
+            (Method::Get, ["guilds", guild_id, "diff"]) => {
+                return Some(match api::parse_id(guild_id) {
+                    Ok(guild_id) => self
+                        .plan_commands(ctx, Some(GuildId(guild_id)))
+                        .await
+                        .map(|(_, diff)| diff.to_json())
+                        .map_err(ApiError::internal),
+                    Err(e) => Err(e),
+                })
+            }
+            (Method::Post, ["sync"]) => std::iter::once(None)
+                .chain(ctx.cache.guilds().await.into_iter().map(Some))
+                .collect(),
+            (Method::Post, ["global", "sync"]) => vec![None],
+            (Method::Post, ["guilds", guild_id, "sync"]) => match api::parse_id(guild_id) {
+                Ok(guild_id) => vec![Some(GuildId(guild_id))],
+                Err(e) => return Some(Err(e)),
+            },
+            _ => return None,
         };
-        let command_name = app_cmd.fullname();
-        let msg = match command_name.as_str() {
-            "slash.permissions.set" => self.slash_perms_add(ctx, guild_id, app_cmd).await,
-            "slash.permissions.remove" => self.slash_perms_remove(ctx, guild_id, app_cmd).await,
-            "slash.permissions.reset" => self.slash_perms_reset(ctx, guild_id, app_cmd).await,
-            "slash.permissions.list" => self.slash_perms_list(ctx, guild_id).await,
-            _ => return Ok(()),
-        };
-        app_command
-            .create_interaction_response(ctx, |resp| {
-                *resp = msg.into();
-                resp
-            })
+        let mut synced = Vec::new();
+        let mut errors = Vec::new();
+        for guild_id in scopes {
+            let scope = guild_id.map_or("global".to_string(), |g| g.to_string());
+            match self.register_commands(ctx, guild_id).await {
+                Ok(_) => synced.push(scope),
+                Err(why) => errors.push(json!({
+                    "guild_id": scope,
+                    "error": why.to_string(),
+                })),
+            }
+        }
+        log::info!(
+            target: "slash",
+            "Slash commands synced from the admin API: {} scope(s)",
+            synced.len()
+        );
+        Some(Ok(json!({
+            "synced": synced,
+            "errors": errors,
+            "dry_run": *self.dry_run.read().await,
+        })))
+    }
+    /// Génère les slash commandes du serveur `guild_id`, ou les commandes globales si None
+    ///
+    /// Seuls les composants dont la [portée](CommandScope) inclut le serveur, ou les commandes globales, sont pris en compte.
+    /// Pour un serveur, les composants doivent aussi y être activés.
+    async fn app_commands(&self, guild_id: Option<GuildId>) -> Vec<CreateApplicationCommand> {
+        let components = self
+            .manager
+            .read()
             .await
-            .map_err(|e| format!("Cannot create response: {}", e))
+            .get_enabled_components(guild_id)
+            .await;
+        let mut app_commands = Vec::new();
+        for compo in components {
+            let compo = compo.read().await;
+            if !self.config.scope(compo.name()).includes(guild_id) {
+                continue;
+            }
+            let node = match compo.node() {
+                Some(group) => group,
+                None => continue,
+            };
+            let commands =
+                slash::register_root_with_perm(node, matches!(compo.name(), "slash" | "component"));
+            commands.iter().for_each(|c| {
+                log::debug!(
+                    target: "slash",
+                    "Name: {:?}, Perm: {:?}",
+                    c.0.get("name"),
+                    c.0.get("default_permission")
+                )
+            });
+            app_commands.extend(commands);
+        }
+        app_commands
+    }
+    /// Retourne les commandes enregistrées sur le serveur `guild_id`, ou les commandes globales si None,
+    /// telles que retournées par Discord, et les modifications à leur apporter pour correspondre aux composants.
+    ///
+    /// Les commandes sont lues sans passer par [`ApplicationCommand`], qui ne conserve pas tous les champs des options.
+    async fn plan_commands(
+        &self,
+        ctx: &Context,
+        guild_id: Option<GuildId>,
+    ) -> serenity::Result<(Vec<Value>, slash::CommandsDiff)> {
+        let application_id = self.app_id.0;
+        let route = match guild_id {
+            Some(guild_id) => RouteInfo::GetGuildApplicationCommands {
+                application_id,
+                guild_id: guild_id.0,
+            },
+            None => RouteInfo::GetGlobalApplicationCommands { application_id },
+        };
+        let existing: Vec<Value> = ctx
+            .http
+            .fire(Request::new(RequestBuilder::new(route)))
+            .await?;
+        let diff = slash::CommandsDiff::new(&existing, self.app_commands(guild_id).await);
+        Ok((existing, diff))
+    }
+    /// Synchronise les slash commandes du serveur `guild_id`, ou les commandes globales si None, avec les composants
+    ///
+    /// Seules les commandes ajoutées, modifiées ou retirées sont envoyées à Discord. En mode *dry run*,
+    /// les modifications sont seulement affichées. Retourne les modifications prévues.
+    async fn register_commands(
+        &self,
+        ctx: &Context,
+        guild_id: Option<GuildId>,
+    ) -> serenity::Result<slash::CommandsDiff> {
+        let (existing, diff) = self.plan_commands(ctx, guild_id).await?;
+        let mut registered = existing
+            .into_iter()
+            .map(serde_json::from_value)
+            .collect::<Result<Vec<ApplicationCommand>, _>>()?;
+        let scope = guild_id.map_or("global scope".to_string(), |g| format!("guild {}", g));
+        let result = if diff.is_empty() {
+            log::debug!(target: "slash", "Slash commands of {} are up to date", scope);
+            Ok(())
+        } else if *self.dry_run.read().await {
+            log::info!(
+                target: "slash",
+                "Dry run, slash commands changes of {} not applied:\n{}",
+                scope, diff
+            );
+            Ok(())
+        } else {
+            let result = Self::apply_diff(ctx, guild_id, &diff, &mut registered).await;
+            log::info!(target: "slash", "Slash commands changes of {}:\n{}", scope, diff);
+            result
+        };
+        let mut commands = self.commands.write().await;
+        commands.retain(|(g, _)| *g != guild_id);
+        commands.push((guild_id, registered));
+        result.map(|_| diff)
+    }
+    /// Applique les modifications `diff` sur le serveur `guild_id`, ou aux commandes globales si None,
+    /// et les reporte dans `registered`
+    ///
+    /// Les commandes modifiées conservent leur id, et donc leurs permissions.
+    async fn apply_diff(
+        ctx: &Context,
+        guild_id: Option<GuildId>,
+        diff: &slash::CommandsDiff,
+        registered: &mut Vec<ApplicationCommand>,
+    ) -> serenity::Result<()> {
+        let http = &ctx.http;
+        for (id, _) in &diff.delete {
+            match guild_id {
+                Some(guild_id) => {
+                    http.delete_guild_application_command(guild_id.0, *id)
+                        .await?
+                }
+                None => http.delete_global_application_command(*id).await?,
+            }
+            registered.retain(|command| command.id.0 != *id);
+        }
+        for (id, command) in &diff.edit {
+            let edited = match guild_id {
+                Some(guild_id) => {
+                    http.edit_guild_application_command(guild_id.0, *id, command)
+                        .await?
+                }
+                None => http.edit_global_application_command(*id, command).await?,
+            };
+            if let Some(old) = registered.iter_mut().find(|command| command.id.0 == *id) {
+                *old = edited;
+            }
+        }
+        for command in &diff.create {
+            registered.push(match guild_id {
+                Some(guild_id) => {
+                    http.create_guild_application_command(guild_id.0, command)
+                        .await?
+                }
+                None => http.create_global_application_command(command).await?,
+            });
+        }
+        Ok(())
+    }
+    /// Commandes utilisables sur le serveur `guild_id` : ses commandes puis les commandes globales
+    ///
+    /// Retourne None si aucune commande n'a encore été enregistrée pour le serveur ni globalement.
+    async fn available_commands(&self, guild_id: GuildId) -> Option<Vec<ApplicationCommand>> {
+        let commands = self.commands.read().await;
+        let mut scopes = commands
+            .iter()
+            .filter(|(g, _)| *g == Some(guild_id) || g.is_none())
+            .collect::<Vec<_>>();
+        if scopes.is_empty() {
+            return None;
+        }
+        // Commandes du serveur en premier
+        scopes.sort_by_key(|(g, _)| g.is_none());
+        Some(
+            scopes
+                .into_iter()
+                .flat_map(|(_, commands)| commands.iter().cloned())
+                .collect(),
+        )
     }
     /// Méthode appelée sur la commande slash.permissions.set
     ///
@@ -285,13 +710,9 @@ impl SlashCommands {
     async fn slash_perms_add(
         &self,
         ctx: &Context,
-        guild_id: GuildId,
-        app_cmd: ApplicationCommandEmbed<'_>,
-    ) -> message::Message {
-        let user_id = app_cmd.0.member.as_ref().unwrap().user.id;
-        if !self.owners.contains(&user_id) {
-            return message::error("Cette commande est reservée aux owners");
-        }
+        app_cmd: &ApplicationCommandEmbed<'_>,
+    ) -> cmd::HandlerResult {
+        let guild_id = app_cmd.require_guild_id()?;
         slash_argument!(app_cmd, command: (self, guild_id, opt_command, command_id), who: opt_who, type: opt_type);
         let mut old_perms = match guild_id
             .get_application_command_permissions(ctx, command_id)
@@ -309,7 +730,7 @@ impl SlashCommands {
         {
             Some(v) => {
                 if v.2 == opt_type {
-                    return message::success("La permission est déjà attribué tel quel.");
+                    return Ok(message::success("La permission est déjà attribué tel quel."));
                 }
                 v.2 = opt_type;
                 true
@@ -328,7 +749,7 @@ impl SlashCommands {
                 perm
             })
             .await;
-        match (updated, result) {
+        Ok(match (updated, result) {
             (true, Ok(_)) => message::success(format!(
                 "La permission de la commande `{}` a été mise a jour.",
                 opt_command
@@ -341,7 +762,7 @@ impl SlashCommands {
                 "La permission pour la commande {} n'a pas pu être assigné: {:?}",
                 opt_command, why
             )),
-        }
+        })
     }
     /// Méthode appelée sur la commande slash.permissions.remove
     ///
@@ -355,13 +776,9 @@ impl SlashCommands {
     async fn slash_perms_remove(
         &self,
         ctx: &Context,
-        guild_id: GuildId,
-        app_cmd: ApplicationCommandEmbed<'_>,
-    ) -> message::Message {
-        let user_id = app_cmd.0.member.as_ref().unwrap().user.id;
-        if !self.owners.contains(&user_id) {
-            return message::error("Cette commande est reservée aux owners");
-        }
+        app_cmd: &ApplicationCommandEmbed<'_>,
+    ) -> cmd::HandlerResult {
+        let guild_id = app_cmd.require_guild_id()?;
         slash_argument!(
             app_cmd,
             command: (self, guild_id, opt_command, command_id),
@@ -385,7 +802,7 @@ impl SlashCommands {
         .map(|v| (v.id.0, v.kind, v.permission))
         .collect::<Vec<_>>();
         if !found {
-            return message::error("La permission n'a pas été trouvé.");
+            return Err("La permission n'a pas été trouvé.".into());
         }
         let result = guild_id
             .create_application_command_permission(ctx, command_id, |perm| {
@@ -395,7 +812,7 @@ impl SlashCommands {
                 perm
             })
             .await;
-        match result {
+        Ok(match result {
             Ok(_) => message::success(format!(
                 "La permission de <@{}{}> pour la commande `{}` a été retirée.",
                 if opt_who.1 == ApplicationCommandPermissionType::Role {
@@ -410,7 +827,7 @@ impl SlashCommands {
                 "Une erreur s'est produite lors de la suppression de la permission: {:?}",
                 why
             )),
-        }
+        })
     }
     /// Méthode appelée sur la commande slash.permissions.reset
     ///
@@ -422,15 +839,11 @@ impl SlashCommands {
     async fn slash_perms_reset(
         &self,
         ctx: &Context,
-        guild_id: GuildId,
-        app_cmd: ApplicationCommandEmbed<'_>,
-    ) -> message::Message {
-        let user_id = app_cmd.0.member.as_ref().unwrap().user.id;
-        if !self.owners.contains(&user_id) {
-            return message::error("Cette commande est reservée aux owners");
-        }
+        app_cmd: &ApplicationCommandEmbed<'_>,
+    ) -> cmd::HandlerResult {
+        let guild_id = app_cmd.require_guild_id()?;
         slash_argument!(app_cmd, command: (self, guild_id, opt_command, command_id));
-        match guild_id
+        Ok(match guild_id
             .create_application_command_permission(ctx, command_id, |perm| perm)
             .await
         {
@@ -442,18 +855,18 @@ impl SlashCommands {
                 "Une erreur s'est produite lors de la réinitialisation des permissions: {:?}",
                 why
             )),
-        }
+        })
     }
     /// Méthode appelée sur la commande slash.permissions.list
     ///
     /// Affiche la liste des permissions des commandes du bot
-    async fn slash_perms_list(&self, ctx: &Context, guild_id: GuildId) -> message::Message {
-        let mut commands = match guild_id.get_application_commands(ctx).await {
-            Ok(v) => v,
-            Err(_) => Vec::new(),
-        }
-        .into_iter()
-        .filter(|c| c.application_id == self.app_id);
+    async fn slash_perms_list(
+        &self,
+        ctx: &Context,
+        app_cmd: &ApplicationCommandEmbed<'_>,
+    ) -> cmd::HandlerResult {
+        let guild_id = app_cmd.require_guild_id()?;
+        let commands = self.available_commands(guild_id).await.unwrap_or_default();
         let perms = match guild_id.get_application_commands_permissions(ctx).await {
             Ok(v) => v,
             Err(_) => Vec::new(),
@@ -464,8 +877,9 @@ impl SlashCommands {
         let perms = perms
             .filter_map(|v| {
                 commands
+                    .iter()
                     .find(|c| c.id == v.id)
-                    .map(|command| (command.name, v.permissions))
+                    .map(|command| (command.name.clone(), v.permissions))
             })
             .map(|info_perms| {
                 let list_perm = info_perms
@@ -487,6 +901,238 @@ impl SlashCommands {
                 format!("*Commande __{}__*\n\n{}", info_perms.0, list_perm)
             })
             .collect::<String>();
-        message::success(perms)
+        Ok(message::success(perms))
+    }
+    /// Permissions des commandes de l'application sur le serveur `guild_id`, par nom de commande
+    async fn guild_permissions(
+        &self,
+        ctx: &Context,
+        guild_id: GuildId,
+    ) -> Result<PermissionsFile, String> {
+        let commands = match self.available_commands(guild_id).await {
+            Some(commands) => commands,
+            None => return Err("Le serveur n'est pas reconnu.".into()),
+        };
+        let perms = guild_id
+            .get_application_commands_permissions(ctx)
+            .await
+            .map_err(|why| format!("Impossible d'obtenir les permissions: {:?}", why))?;
+        Ok(perms
+            .into_iter()
+            .filter(|perm| perm.application_id == self.app_id)
+            .filter_map(|perm| {
+                let command = commands.iter().find(|c| c.id == perm.id)?;
+                let mut entries = perm
+                    .permissions
+                    .iter()
+                    .filter_map(PermissionEntry::from_data)
+                    .collect::<Vec<_>>();
+                entries.sort();
+                Some((command.name.clone(), entries))
+            })
+            .collect())
+    }
+    /// Méthode appelée sur la commande slash.permissions.export
+    ///
+    /// Joint un fichier associant le nom de chaque commande à ses permissions sur le serveur
+    ///
+    /// # Arguments
+    ///
+    /// * format: Le format du fichier. "ron" ou "json" attendu.
+    async fn slash_perms_export(
+        &self,
+        ctx: &Context,
+        app_cmd: &ApplicationCommandEmbed<'_>,
+    ) -> cmd::HandlerResult {
+        let guild_id = app_cmd.require_guild_id()?;
+        let format = app_cmd.command().get::<String>("format")?;
+        let perms = self.guild_permissions(ctx, guild_id).await?;
+        let content = match format.as_str() {
+            "json" => serde_json::to_string_pretty(&perms).map_err(|why| why.to_string())?,
+            "ron" => ron::ser::to_string_pretty(&perms, ron::ser::PrettyConfig::default())
+                .map_err(|why| why.to_string())?,
+            s => {
+                return Err(format!(
+                    "Format: mot clé `{}` non reconnu. `ron` ou `json` attendus.",
+                    s
+                ))
+            }
+        };
+        let count = perms.values().map(Vec::len).sum::<usize>();
+        Ok(message::success(format!(
+            "{} permission(s) de {} commande(s) exportée(s).",
+            count,
+            perms.len()
+        ))
+        .set_ephemeral(true)
+        .add_file(
+            content.into_bytes(),
+            format!("permissions-{}.{}", guild_id, format),
+        ))
+    }
+    /// Méthode appelée sur la commande slash.permissions.import
+    ///
+    /// Lit le fichier de permissions joint à un message et affiche les commandes dont les permissions changeraient.
+    /// Le fichier est lu en JSON si son nom se termine par `.json`, en RON sinon.
+    /// Les commandes inconnues du serveur sont ignorées.
+    ///
+    /// # Arguments
+    ///
+    /// * message: Le lien ou l'identifiant du message auquel le fichier est joint.
+    ///   Un identifiant seul désigne un message du salon courant.
+    /// * apply: Si présent, les permissions sont remplacées par celles du fichier.
+    async fn slash_perms_import(
+        &self,
+        ctx: &Context,
+        app_cmd: &ApplicationCommandEmbed<'_>,
+    ) -> cmd::HandlerResult {
+        let guild_id = app_cmd.require_guild_id()?;
+        let command = app_cmd.command();
+        let apply = command.get::<bool>("apply")?;
+        let (channel_id, message_id) = match parse_message_link(&command.get::<String>("message")?)
+        {
+            Some((channel_id, message_id)) => {
+                (channel_id.unwrap_or(app_cmd.0.channel_id), message_id)
+            }
+            None => return Err("Lien ou identifiant de message invalide.".into()),
+        };
+        let msg = channel_id
+            .message(ctx, message_id)
+            .await
+            .map_err(|why| format!("Impossible d'obtenir le message: {:?}", why))?;
+        let attachment = match msg.attachments.first() {
+            Some(attachment) => attachment,
+            None => return Err("Le message ne contient pas de fichier.".into()),
+        };
+        if attachment.size > MAX_PERMISSIONS_FILE_SIZE {
+            return Err("Le fichier est trop volumineux.".into());
+        }
+        let content = attachment
+            .download()
+            .await
+            .map_err(|why| format!("Impossible de télécharger le fichier: {:?}", why))?;
+        let content = String::from_utf8(content)
+            .map_err(|_| "Le fichier n'est pas un fichier texte.".to_string())?;
+        let imported: PermissionsFile = if attachment.filename.ends_with(".json") {
+            serde_json::from_str(&content)
+                .map_err(|why| format!("Fichier JSON invalide: {}", why))?
+        } else {
+            ron::from_str(&content).map_err(|why| format!("Fichier RON invalide: {}", why))?
+        };
+
+        let commands = self.available_commands(guild_id).await.unwrap_or_default();
+        let current = self.guild_permissions(ctx, guild_id).await?;
+        let mut unknown = Vec::new();
+        let mut changes = Vec::new();
+        for (name, mut entries) in imported {
+            let command = match commands.iter().find(|c| c.name == name) {
+                Some(command) => command,
+                None => {
+                    unknown.push(name);
+                    continue;
+                }
+            };
+            if entries.len() > MAX_COMMAND_PERMISSIONS {
+                return Err(format!(
+                    "La commande `{}` a plus de {} permissions.",
+                    name, MAX_COMMAND_PERMISSIONS
+                ));
+            }
+            entries.sort();
+            entries.dedup();
+            if entries != current.get(&name).cloned().unwrap_or_default() {
+                changes.push((command.id, name, entries));
+            }
+        }
+
+        let mut summary = changes
+            .iter()
+            .map(|(_, name, entries)| {
+                let entries = match entries.is_empty() {
+                    true => "aucune permission".to_string(),
+                    false => entries
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                };
+                format!("*Commande __{}__* : {}\n", name, entries)
+            })
+            .collect::<String>();
+        if changes.is_empty() {
+            summary.push_str("Aucune permission à modifier.\n");
+        }
+        if !unknown.is_empty() {
+            summary.push_str(&format!(
+                "\nCommandes inconnues ignorées : {}\n",
+                unknown.join(", ")
+            ));
+        }
+        if !apply || changes.is_empty() {
+            if !changes.is_empty() {
+                summary.push_str("\nRelancez la commande avec `apply` pour appliquer l'import.");
+            }
+            return Ok(
+                message::custom_embed("Import des permissions", summary, 0x1ed760)
+                    .set_ephemeral(true),
+            );
+        }
+
+        let mut errors = Vec::new();
+        for (command_id, name, entries) in &changes {
+            let result = guild_id
+                .create_application_command_permission(ctx, *command_id, |perm| {
+                    entries.iter().for_each(|entry| {
+                        perm.create_permission(|new_perm| {
+                            new_perm
+                                .id(entry.id)
+                                .kind(entry.kind.into())
+                                .permission(entry.allow)
+                        });
+                    });
+                    perm
+                })
+                .await;
+            if let Err(why) = result {
+                errors.push(format!("`{}`: {:?}", name, why));
+            }
+        }
+        Ok(match errors.is_empty() {
+            true => message::success(format!(
+                "Les permissions de {} commande(s) ont été importées.\n\n{}",
+                changes.len(),
+                summary
+            )),
+            false => message::error(format!(
+                "Certaines permissions n'ont pas pu être importées:\n{}",
+                errors.join("\n")
+            )),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::component_system::manager::Manager;
+    use crate::util::ArcRw;
+
+    #[tokio::test]
+    async fn guild_is_claimed_once() {
+        let manager = ArcRw::new(Manager::new(ApplicationId(1), Vec::new()));
+        let slash = SlashCommands::new(manager, ApplicationId(1), false, SlashConfig::default());
+        let inner = &slash.inner;
+        // Ready et GuildCreate concurrents du même serveur
+        let (ready, guild_create) =
+            tokio::join!(inner.claim_guild(GuildId(1)), inner.claim_guild(GuildId(1)));
+        assert!(ready != guild_create);
+        assert!(inner.claim_guild(GuildId(2)).await);
+        // Un serveur quitté est de nouveau synchronisé s'il est rejoint
+        inner
+            .synced_guilds
+            .write()
+            .await
+            .retain(|g| *g != GuildId(1));
+        assert!(inner.claim_guild(GuildId(1)).await);
     }
 }
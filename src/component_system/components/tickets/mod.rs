@@ -1,36 +1,40 @@
 use std::collections::HashMap;
 use std::io::Write;
 use std::path::PathBuf;
+use std::sync::Arc;
 
 use futures::StreamExt;
 use futures_locks::RwLock;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use serenity::async_trait;
 use serenity::client::Context;
-use serenity::model::channel::{Message, ReactionType};
-use serenity::model::event::Event;
-use serenity::model::id::{ChannelId, GuildId};
-use serenity::model::interactions::application_command::ApplicationCommandInteraction;
+use serenity::model::channel::{ChannelType, GuildChannel, Message, ReactionType};
+use serenity::model::event::{Event, ReadyEvent};
+use serenity::model::guild::Member;
+use serenity::model::id::{ChannelId, GuildId, UserId};
 use serenity::model::interactions::message_component::{ButtonStyle, MessageComponentInteraction};
 use serenity::model::interactions::Interaction;
 use serenity::prelude::Mentionable;
 
 use super::utils;
-use super::utils::commands;
+use super::utils::app_command::{handler, ApplicationCommandEmbed};
 use super::utils::message;
-use super::utils::Data;
-use crate::component_system::components::utils::commands::ToCommand;
-use crate::component_system::{self as cmp, command_parser as cmd, FrameworkConfig};
+use super::utils::{Data, GuildData, Schema};
+use crate::component_system::api::{self, ApiError, ApiRequest, ApiResult, Method};
+use crate::component_system::bus::{Bus, BusEvent, Sanction};
+use crate::component_system::command_parser as cmd;
+use crate::metrics;
 
-macro_rules! err_println {
+macro_rules! err_log {
     (send_error($ctx: ident, $msg: ident, $txt:expr)) => {
-        err_println!(
+        err_log!(
             utils::send::error_message($ctx, $msg, $txt).await,
             "Error sending error message: {}"
         )
     };
     (send_success($ctx: ident, $msg: ident, $txt:expr)) => {
-        err_println!(
+        err_log!(
             utils::send::success_message($ctx, $msg, $txt).await,
             "Error sending success message: {}"
         )
@@ -38,21 +42,27 @@ macro_rules! err_println {
     ($result:expr,$msg_format:expr) => {{
         match $result {
             Ok(_) => (),
-            Err(e) => eprintln!($msg_format, e),
+            Err(e) => log::error!(target: "tickets", $msg_format, e),
         }
     }};
 }
 
 /// Le composant de gestion des tickets
 pub struct Tickets {
-    /// Données persistantes
-    data: RwLock<Data<DataTickets>>,
     /// Configuration des commandes
     node: cmd::Node,
+    /// Données et actions du composant, partagées avec les handlers des commandes
+    inner: Arc<TicketsInner>,
+}
+struct TicketsInner {
+    /// Données persistantes de chaque serveur
+    data: GuildData<DataTickets>,
     /// Dossier de sauvegarde des tickets
     ///
     /// Dès que les tickets sont supprimés, ils sont enregistrés dans ce dossier.
     archives_folder: PathBuf,
+    /// Bus de messages entre composants
+    bus: Bus,
 }
 /// Données persistantes du composant
 ///
@@ -68,6 +78,15 @@ struct DataTickets {
     categories: Vec<CategoryTicket>,
 }
 
+impl DataTickets {
+    /// Modèle des données de tickets
+    ///
+    /// La version 1 est identique aux données enregistrées sans version ou en version 0.
+    fn schema() -> Schema {
+        Schema::new(1).add_migration(0, |data: DataTickets| data)
+    }
+}
+
 /// Catégorie de tickets
 #[derive(Serialize, Deserialize, Default, Debug)]
 struct CategoryTicket {
@@ -90,32 +109,68 @@ struct CategoryTicket {
     tickets: Vec<String>,
 }
 
+/// Autocomplétion des noms des catégories de tickets du serveur
+struct CategoryNames(Arc<TicketsInner>);
+
+#[async_trait]
+impl cmd::AutocompleteProvider for CategoryNames {
+    async fn values(&self, _: &Context, guild_id: Option<GuildId>) -> Vec<String> {
+        let data = match guild_id {
+            Some(guild_id) => match self.0.guild_data(guild_id).await {
+                Ok(data) => data,
+                Err(_) => return Vec::new(),
+            },
+            None => return Vec::new(),
+        };
+        let data = data.read().await;
+        data.read()
+            .categories
+            .iter()
+            .map(|cat| cat.name.clone())
+            .collect()
+    }
+}
+
 #[async_trait]
 impl crate::component_system::Component for Tickets {
     fn name(&self) -> &str {
         "tickets"
     }
-    async fn command(
-        &self,
-        fw_config: &FrameworkConfig,
-        ctx: &Context,
-        msg: &Message,
-    ) -> cmp::CommandMatch {
-        self.r_command(fw_config, ctx, msg).await
-    }
     async fn event(&self, ctx: &Context, evt: &Event) -> Result<(), String> {
-        self.r_event(ctx, evt).await
+        self.inner.r_event(ctx, evt).await
+    }
+    fn subscriptions(&self) -> crate::component_system::EventKinds {
+        use crate::component_system::EventKinds;
+        EventKinds::READY | EventKinds::INTERACTION
+    }
+    async fn init(&self, ctx: &Context) -> Result<(), String> {
+        let inner = Arc::clone(&self.inner);
+        let ctx = ctx.clone();
+        self.inner.bus.subscribe("tickets", move |evt| {
+            let inner = Arc::clone(&inner);
+            let ctx = ctx.clone();
+            async move { inner.on_bus_event(&ctx, evt).await }
+        });
+        Ok(())
     }
     fn node(&self) -> Option<&cmd::Node> {
         Some(&self.node)
     }
+    async fn api(&self, _: &Context, request: &ApiRequest) -> Option<ApiResult> {
+        self.inner.api(request).await
+    }
 }
 
 impl Tickets {
-    pub fn new() -> Self {
+    pub fn new(bus: Bus) -> Self {
         use serenity::model::interactions::application_command::ApplicationCommandOptionType;
 
-        let node = cmd::Node::new().add_group(
+        let inner = Arc::new(TicketsInner {
+            data: GuildData::new("tickets").set_schema(DataTickets::schema()),
+            archives_folder: utils::DATA_DIR.join("archives"),
+            bus,
+        });
+        let mut node = cmd::Node::new().add_group(
             cmd::Group::new("tickets")
                 .set_help("Gestion des tickets")
                 .set_permission("owners")
@@ -125,9 +180,11 @@ impl Tickets {
                         .set_help("Change le salon")
                         .add_param(cmd::Argument::new("id")
                             .set_value_type(ApplicationCommandOptionType::Channel)
+                            .set_channel_types([ChannelType::Text, ChannelType::News])
                             .set_required(true)
                             .set_help("Identifiant du message")
                         )
+                        .set_handler(handler!(inner, on_channel_set))
                     )
                 )
                 .add_group(cmd::Group::new("categories")
@@ -141,6 +198,7 @@ impl Tickets {
                         )
                         .add_param(cmd::Argument::new("id")
                             .set_value_type(ApplicationCommandOptionType::Channel)
+                            .set_channel_types([ChannelType::Category])
                             .set_required(true)
                             .set_help("Identifiant de la catégorie Discord")
                         )
@@ -156,191 +214,196 @@ impl Tickets {
                         //     .set_required(false)
                         //     .set_help("Emoji décoration")
                         // )
+                        .set_handler(handler!(inner, on_category_add))
                     )
                     .add_command(cmd::Command::new("remove")
                         .set_help("Supprime une catégorie de ticket")
                         .add_param(cmd::Argument::new("name")
                             .set_required(true)
                             .set_help("Nom de la catégorie")
+                            .set_autocomplete(CategoryNames(Arc::clone(&inner)))
                         )
+                        .set_handler(handler!(inner, on_category_remove))
                     )
                     .add_command(cmd::Command::new("list")
                         .set_help("Liste les catégories de ticket")
+                        .set_handler(handler!(inner, on_categories_list))
                     )
                 )
 
+            )
+            .add_context_command(cmd::ContextCommand::new("Ouvrir un ticket", cmd::ContextTarget::Message)
+                .set_permission(utils::STAFF_PERMISSION)
+                .set_handler(handler!(inner, on_ticket_about_message))
+            )
+            .add_context_command(cmd::ContextCommand::new("Archiver le message", cmd::ContextTarget::Message)
+                .set_permission(utils::STAFF_PERMISSION)
+                .set_handler(handler!(inner, on_message_archive))
             );
-        Tickets {
-            node,
-            data: match Data::from_file_default("tickets") {
-                Ok(data) => RwLock::new(data),
-                Err(e) => panic!("Data tickets: {:?}", e),
-            },
-            archives_folder: utils::DATA_DIR.join("archives"),
-        }
+        node.generate_ids(&[]);
+        Tickets { node, inner }
     }
-    /// Execute les commandes du composant __non slash__
-    async fn r_command(
-        &self,
-        _: &FrameworkConfig,
-        ctx: &Context,
-        msg: &Message,
-    ) -> cmp::CommandMatch {
-        let args = cmd::split_shell(&msg.content[1..]);
+}
 
-        let matched = match utils::try_match(ctx, msg, &self.node, args).await {
-            Ok(v) => v,
-            Err(e) => return e,
-        };
-        println!("matched: {:?}", matched);
-        let guild_id = match msg.guild_id {
-            Some(id) => id,
-            None => {
-                return cmp::CommandMatch::Error(
-                    "Cette commande n'est pas disponible en message privé.".to_string(),
-                )
-            }
-        };
-        let command = matched.to_command();
-        println!("{:?}", command);
-        let c_msg = match self.commands(ctx, guild_id, command).await {
-            Ok(v) => v,
-            Err(Some(e)) => return cmp::CommandMatch::Error(e),
-            Err(None) => return cmp::CommandMatch::NotMatched,
-        };
-        match utils::send::custom(ctx, msg.channel_id, c_msg).await {
-            Ok(_) => cmp::CommandMatch::Matched,
-            Err(e) => cmp::CommandMatch::Error(format!(
-                "Erreur lors de l'envoi du message de réponse: {}",
-                e
-            )),
-        }
-    }
+impl TicketsInner {
     /// Dispatch un enevement reçu par le bot
     async fn r_event(&self, ctx: &Context, evt: &Event) -> Result<(), String> {
         use serenity::model::event::Event::*;
 
         match evt {
-            Ready(_) => self.on_ready(ctx).await,
+            Ready(ReadyEvent { ready, .. }) => self.on_ready(ctx, ready).await,
             InteractionCreate(evt) => {
                 self.on_interaction(ctx, &evt.interaction)
                     .await
                     .map_err(|e| {
-                        eprintln!("Erreur lors de la création d'une interaction: {}", e);
+                        log::error!(target: "tickets", "Erreur lors de la création d'une interaction: {}", e);
                         e
                     })
             }
             _ => Ok(()),
         }
     }
+    /// Réagit aux évènements des autres composants
+    ///
+    /// Les tickets d'un membre banni sont fermés.
+    async fn on_bus_event(&self, ctx: &Context, evt: BusEvent) -> Result<(), String> {
+        match evt {
+            BusEvent::MemberSanctioned {
+                guild_id,
+                user_id,
+                sanction: Sanction::Ban,
+                ..
+            } => self.close_member_tickets(ctx, guild_id, user_id).await,
+            _ => Ok(()),
+        }
+    }
     /// Dispatch une intération reçue par le bot
     async fn on_interaction(&self, ctx: &Context, interaction: &Interaction) -> Result<(), String> {
         match interaction {
             Interaction::Ping(_) => Ok(()),
-            Interaction::ApplicationCommand(v) => self.on_app_command(ctx, v).await,
+            Interaction::ApplicationCommand(_) | Interaction::Autocomplete(_) => Ok(()),
             Interaction::MessageComponent(v) => self.on_msg_component(ctx, v).await,
         }
     }
-    async fn commands(
+    /// Retourne les données du serveur `guild_id`
+    async fn guild_data(&self, guild_id: GuildId) -> Result<RwLock<Data<DataTickets>>, String> {
+        self.data
+            .get(guild_id)
+            .await
+            .map_err(|e| format!("Données des tickets du serveur {}: {}", guild_id, e))
+    }
+    /// Handler de la commande `tickets channel set`
+    async fn on_channel_set(
         &self,
         ctx: &Context,
-        guild_id: GuildId,
-        command: commands::Command,
-    ) -> Result<message::Message, Option<String>> {
-        match command.fullname().as_str() {
-            "tickets.channel.set" => {
-                let channel = match command.get_argument("id") {
-                    Some(&commands::Argument {
-                        value: commands::Value::Channel(v),
-                        ..
-                    }) => v,
-                    Some(_) => return Err(Some("id: paramètre mal formé".to_string())),
-                    None => return Err(Some("id: paramètre manquant".to_string())),
-                };
-                Ok(self.set_channel(ctx, guild_id, channel.0).await)
-            }
-            "tickets.categories.add" => {
-                let name = match command.get_argument("name") {
-                    Some(&commands::Argument {
-                        value: commands::Value::String(ref v),
-                        ..
-                    }) => v.clone(),
-                    Some(_) => return Err(Some("name: paramètre mal formé".to_string())),
-                    None => return Err(Some("name: paramètre manquant".to_string())),
-                };
-                let prefix = match command.get_argument("prefix") {
-                    Some(&commands::Argument {
-                        value: commands::Value::String(ref v),
-                        ..
-                    }) => v.clone(),
-                    Some(_) => return Err(Some("prefix: paramètre mal formé".to_string())),
-                    None => return Err(Some("prefix: paramètre manquant".to_string())),
-                };
-                let desc = match command.get_argument("desc") {
-                    Some(&commands::Argument {
-                        value: commands::Value::String(ref v),
-                        ..
-                    }) => Some(v.clone()),
-                    Some(_) => return Err(Some("desc: paramètre mal formé".to_string())),
-                    None => None,
-                };
-                let channel = match command.get_argument("id") {
-                    Some(&commands::Argument {
-                        value: commands::Value::Channel(v),
-                        ..
-                    }) => v,
-                    Some(_) => return Err(Some("id: paramètre mal formé".to_string())),
-                    None => return Err(Some("id: paramètre manquant".to_string())),
-                };
-                Ok(self
-                    .category_add(ctx, guild_id, name, desc, channel.0, prefix)
-                    .await)
-            }
-            "tickets.categories.remove" => {
-                let name = match command.get_argument("name") {
-                    Some(&commands::Argument {
-                        value: commands::Value::String(ref v),
-                        ..
-                    }) => v.clone(),
-                    Some(_) => return Err(Some("name: paramètre mal formé".to_string())),
-                    None => return Err(Some("name: paramètre manquant".to_string())),
-                };
-                Ok(self.category_remove(ctx, name).await)
-            }
-            "tickets.categories.list" => Ok(self.categories_list(ctx, guild_id).await),
-            _ => Err(None),
-        }
+        app_cmd: &ApplicationCommandEmbed<'_>,
+    ) -> cmd::HandlerResult {
+        let guild_id = app_cmd.require_guild_id()?;
+        let channel = app_cmd.command().get::<ChannelId>("id")?;
+        Ok(self.set_channel(ctx, guild_id, channel.0).await)
     }
-    /// Dispatch les commandes slash reçu par le bot
-    async fn on_app_command(
+    /// Handler de la commande `tickets categories add`
+    async fn on_category_add(
         &self,
         ctx: &Context,
-        app_command: &ApplicationCommandInteraction,
-    ) -> Result<(), String> {
-        let app_cmd = utils::app_command::ApplicationCommandEmbed::new(app_command);
-        let guild_id = match app_cmd.get_guild_id() {
-            Some(v) => v,
-            None => {
-                return Err("Vous devez être dans un serveur pour utiliser cette commande.".into())
-            }
-        };
-        let command = app_command.to_command();
-        println!("{:?}", command);
-        let c_msg = match self.commands(ctx, guild_id, command).await {
-            Ok(v) => v,
-            Err(None) => return Ok(()),
-            Err(Some(e)) => return Err(e),
-        };
-        app_command
-            .create_interaction_response(ctx, |resp| {
-                *resp = c_msg.into();
-                resp
-            })
+        app_cmd: &ApplicationCommandEmbed<'_>,
+    ) -> cmd::HandlerResult {
+        let guild_id = app_cmd.require_guild_id()?;
+        let command = app_cmd.command();
+        let name = command.get::<String>("name")?;
+        let prefix = command.get::<String>("prefix")?;
+        let desc = command.opt::<String>("desc")?;
+        let channel = command.get::<ChannelId>("id")?;
+        Ok(self
+            .category_add(ctx, guild_id, name, desc, channel.0, prefix)
+            .await)
+    }
+    /// Handler de la commande `tickets categories remove`
+    async fn on_category_remove(
+        &self,
+        ctx: &Context,
+        app_cmd: &ApplicationCommandEmbed<'_>,
+    ) -> cmd::HandlerResult {
+        let guild_id = app_cmd.require_guild_id()?;
+        let name = app_cmd.command().get::<String>("name")?;
+        Ok(self.category_remove(ctx, guild_id, name).await)
+    }
+    /// Handler de la commande `tickets categories list`
+    async fn on_categories_list(
+        &self,
+        ctx: &Context,
+        app_cmd: &ApplicationCommandEmbed<'_>,
+    ) -> cmd::HandlerResult {
+        let guild_id = app_cmd.require_guild_id()?;
+        Ok(self.categories_list(ctx, guild_id).await)
+    }
+    /// Handler de la commande de menu contextuel `Ouvrir un ticket`
+    ///
+    /// Ouvre un ticket avec l'auteur du message ciblé dans la première catégorie de tickets du serveur,
+    /// et y cite le message.
+    /// Réservée au staff.
+    async fn on_ticket_about_message(
+        &self,
+        ctx: &Context,
+        app_cmd: &ApplicationCommandEmbed<'_>,
+    ) -> cmd::HandlerResult {
+        let guild_id = app_cmd.require_guild_id()?;
+        let target = app_cmd
+            .target_message()
+            .ok_or("Cette commande doit cibler un message.")?;
+        let member = guild_id
+            .member(ctx, target.author.id)
+            .await
+            .map_err(|e| format!("Impossible d'obtenir l'auteur du message: {}", e))?;
+        let data = self.guild_data(guild_id).await?;
+        let channel = self
+            .open_ticket(ctx, guild_id, &data, None, &member)
             .await
-            .map_err(|e| {
-                eprintln!("Cannot create response: {}", e);
-                e.to_string()
+            .map_err(|e| format!("Impossible de créer le ticket: {}", e))?
+            .ok_or("Aucune catégorie de ticket ou aucun rôle Modérateur sur le serveur.")?;
+        let link = target.id.link(target.channel_id, Some(guild_id));
+        if let Err(e) = channel
+            .send_message(ctx, |msg| {
+                msg.content(format!("Ticket ouvert à propos de ce message : {}", link))
+                    .embed(|embed| {
+                        embed
+                            .author(|author| {
+                                author
+                                    .name(format!(
+                                        "{}#{:04}",
+                                        target.author.name, target.author.discriminator
+                                    ))
+                                    .icon_url(target.author.face())
+                            })
+                            .description(&target.content)
+                            .timestamp(target.timestamp)
+                    })
             })
+            .await
+        {
+            log::error!(target: "tickets", "Error quoting message in new ticket: {}", e);
+        }
+        Ok(message::success(format!(
+            "Le ticket {} a été ouvert avec {}.",
+            channel.mention(),
+            member.mention()
+        ))
+        .set_ephemeral(true))
+    }
+    /// Handler de la commande de menu contextuel `Archiver le message`
+    ///
+    /// Enregistre le message ciblé dans le dossier d'archives des tickets.
+    /// Réservée au staff.
+    async fn on_message_archive(
+        &self,
+        _: &Context,
+        app_cmd: &ApplicationCommandEmbed<'_>,
+    ) -> cmd::HandlerResult {
+        let target = app_cmd
+            .target_message()
+            .ok_or("Cette commande doit cibler un message.")?;
+        let file = Self::archive_message(target)?;
+        Ok(message::success(format!("Le message a été archivé dans {}.", file)).set_ephemeral(true))
     }
     /// Dispatch les composants de message (boutons, menu déroulant) reçu par le bot
     async fn on_msg_component(
@@ -357,19 +420,37 @@ impl Tickets {
             Ok(_) => Ok(()),
             Err(e) => {
                 let err = format!("Error on message component: {}", e);
-                eprintln!("{}", err);
+                log::error!(target: "tickets", "{}", err);
                 Err(err)
             }
         }
     }
     /// Méthode éxécuté sur l'evenement Ready du bot
     ///
-    /// Met à jour le menu déroulant des catégories
-    async fn on_ready(&self, ctx: &Context) -> Result<(), String> {
-        match self.update_select_menu(ctx).await {
-            Ok(_) => (),
-            Err(e) => eprintln!("ticket: Error updating message components: {}", e),
-        };
+    /// Met à jour le menu déroulant des catégories de chaque serveur
+    async fn on_ready(
+        &self,
+        ctx: &Context,
+        ready: &serenity::model::gateway::Ready,
+    ) -> Result<(), String> {
+        let guilds = ready.guilds.iter().map(|g| g.id()).collect::<Vec<_>>();
+        self.data
+            .migrate_legacy(&guilds)
+            .map_err(|e| format!("Data tickets: {}", e))?;
+        for guild_id in guilds {
+            let data = match self.guild_data(guild_id).await {
+                Ok(data) => data,
+                Err(e) => {
+                    log::error!(target: "tickets", "{}", e);
+                    continue;
+                }
+            };
+            match self.update_select_menu(ctx, &data).await {
+                Ok(_) => (),
+                Err(e) => log::error!(target: "tickets", "Error updating message components: {}", e),
+            };
+            self.update_open_tickets(ctx, guild_id).await;
+        }
         Ok(())
     }
     /// Créer un ticket
@@ -383,25 +464,67 @@ impl Tickets {
     ) -> serenity::Result<()> {
         use serenity::model::prelude::*;
         let value = &msg_cmp.data.values[0];
-        let data = self.data.read().await;
-        let data = data.read();
-        let cat = match data.categories.iter().find(|cat| cat.name == *value) {
-            Some(v) => v,
+        let guild_id = match &msg_cmp.guild_id {
+            Some(guild) => guild,
             None => return Ok(()),
         };
+        let data_lock = match self.guild_data(*guild_id).await {
+            Ok(data) => data,
+            Err(e) => {
+                log::error!(target: "tickets", "{}", e);
+                return Ok(());
+            }
+        };
         let member = match &msg_cmp.member {
             Some(v) => v,
             None => return Ok(()),
         };
-        let username = member.display_name().to_string();
-        let guild_id = match &msg_cmp.guild_id {
-            Some(guild) => guild,
+        let new_channel = match self
+            .open_ticket(ctx, *guild_id, &data_lock, Some(value), member)
+            .await?
+        {
+            Some(v) => v,
             None => return Ok(()),
         };
-        let roles = guild_id.roles(ctx).await.unwrap();
+        msg_cmp.create_interaction_response(ctx, |resp|
+            resp
+                .interaction_response_data(|resp_data|
+                    resp_data
+                        .content(format!("Le ticket a bien été créé.\n\nVous pouvez le rejoindre en cliquant sur le lien suivant: {}", new_channel.mention()))
+                        .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+                )
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+        ).await?;
+        Ok(())
+    }
+    /// Ouvre un ticket pour `member` dans la catégorie `category`, ou la première catégorie si None
+    ///
+    /// Le salon n'est visible que par les modérateurs et le membre, qui y est accueilli avec le bouton de fermeture.
+    /// Retourne None si la catégorie ou le rôle *Modérateur* n'existe pas sur le serveur.
+    async fn open_ticket(
+        &self,
+        ctx: &Context,
+        guild_id: GuildId,
+        data_lock: &RwLock<Data<DataTickets>>,
+        category: Option<&str>,
+        member: &Member,
+    ) -> serenity::Result<Option<GuildChannel>> {
+        use serenity::model::prelude::*;
+        let data = data_lock.read().await;
+        let data = data.read();
+        let cat = match category {
+            Some(name) => data.categories.iter().find(|cat| cat.name == name),
+            None => data.categories.first(),
+        };
+        let cat = match cat {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+        let username = member.display_name().to_string();
+        let roles = guild_id.roles(ctx).await?;
         let modo = match roles.iter().find(|role| role.1.name == "Modérateur") {
             Some(v) => *v.0,
-            None => return Ok(()),
+            None => return Ok(None),
         };
         let everyone = RoleId(guild_id.0);
         let new_channel = guild_id
@@ -433,7 +556,17 @@ impl Tickets {
                 ch
             })
             .await?;
-        self.update_select_menu(ctx).await?;
+        self.update_select_menu(ctx, data_lock).await?;
+        self.bus.publish(
+            "tickets",
+            BusEvent::TicketOpened {
+                guild_id,
+                channel_id: new_channel.id,
+                user_id: member.user.id,
+                category: cat.name.clone(),
+            },
+        );
+        metrics::OPEN_TICKETS.add(&[&guild_id.to_string(), &cat.name], 1);
         match new_channel
             .send_message(ctx, |msg|
                 msg
@@ -444,18 +577,9 @@ impl Tickets {
                     })
             ).await {
                 Ok(_)=>(),
-                Err(e) => eprintln!("Error sending message to new channel: {}", e)
+                Err(e) => log::error!(target: "tickets", "Error sending message to new channel: {}", e)
             }
-        msg_cmp.create_interaction_response(ctx, |resp|
-            resp
-                .interaction_response_data(|resp_data|
-                    resp_data
-                        .content(format!("Le ticket a bien été créé.\n\nVous pouvez le rejoindre en cliquant sur le lien suivant: {}", new_channel.mention()))
-                        .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
-                )
-                .kind(InteractionResponseType::ChannelMessageWithSource)
-        ).await?;
-        Ok(())
+        Ok(Some(new_channel))
     }
     /// Fermer un ticket
     ///
@@ -468,20 +592,195 @@ impl Tickets {
         msg_cmp: &MessageComponentInteraction,
     ) -> serenity::Result<()> {
         match Self::archive_channel(ctx, msg_cmp.channel_id).await {
-            Ok(_) => msg_cmp.channel_id.delete(ctx).await.and(Ok(()))?,
-            Err(e) => eprintln!("Error archiving channel: {}", e),
+            Ok(_) => {
+                msg_cmp.channel_id.delete(ctx).await?;
+                if let Some(guild_id) = msg_cmp.guild_id {
+                    self.bus.publish(
+                        "tickets",
+                        BusEvent::TicketClosed {
+                            guild_id,
+                            channel_id: msg_cmp.channel_id,
+                            closed_by: Some(msg_cmp.user.id),
+                        },
+                    );
+                    self.update_open_tickets(ctx, guild_id).await;
+                }
+            }
+            Err(e) => log::error!(target: "tickets", "Error archiving channel: {}", e),
         }
         Ok(())
     }
+    /// Archive puis ferme les tickets ouverts par le membre `user_id`
+    ///
+    /// Un ticket appartient au membre si le salon est dans une catégorie de tickets et que le membre y a une permission dédiée.
+    async fn close_member_tickets(
+        &self,
+        ctx: &Context,
+        guild_id: GuildId,
+        user_id: UserId,
+    ) -> Result<(), String> {
+        use serenity::model::channel::PermissionOverwriteType;
+        let tickets = self.ticket_channels(ctx, guild_id).await?;
+        let tickets = tickets.iter().filter(|(_, channel)| {
+            channel.permission_overwrites.iter().any(|perm| {
+                matches!(perm.kind, PermissionOverwriteType::Member(id) if id == user_id)
+            })
+        });
+        for (_, ticket) in tickets {
+            Self::archive_channel(ctx, ticket.id).await?;
+            ticket
+                .delete(ctx)
+                .await
+                .map_err(|e| format!("Impossible de supprimer le ticket {}: {}", ticket.name, e))?;
+            self.bus.publish(
+                "tickets",
+                BusEvent::TicketClosed {
+                    guild_id,
+                    channel_id: ticket.id,
+                    closed_by: None,
+                },
+            );
+        }
+        self.update_open_tickets(ctx, guild_id).await;
+        Ok(())
+    }
+    /// Retourne les tickets ouverts du serveur `guild_id`, avec le nom de leur catégorie
+    ///
+    /// Un ticket est un salon d'une catégorie de tickets sur lequel un membre a une permission dédiée.
+    async fn ticket_channels(
+        &self,
+        ctx: &Context,
+        guild_id: GuildId,
+    ) -> Result<Vec<(String, GuildChannel)>, String> {
+        use serenity::model::channel::PermissionOverwriteType;
+        let categories = {
+            let data = self.guild_data(guild_id).await?;
+            let data = data.read().await;
+            data.read()
+                .categories
+                .iter()
+                .map(|cat| (ChannelId(cat.id), cat.name.clone()))
+                .collect::<HashMap<_, _>>()
+        };
+        let channels = guild_id
+            .channels(ctx)
+            .await
+            .map_err(|e| format!("Impossible d'obtenir les salons du serveur: {}", e))?;
+        Ok(channels
+            .into_values()
+            .filter_map(|channel| {
+                let category = categories.get(&channel.category_id?)?;
+                channel
+                    .permission_overwrites
+                    .iter()
+                    .any(|perm| matches!(perm.kind, PermissionOverwriteType::Member(_)))
+                    .then(|| (category.clone(), channel))
+            })
+            .collect())
+    }
+    /// Met à jour la [métrique](metrics::OPEN_TICKETS) des tickets ouverts du serveur `guild_id`
+    async fn update_open_tickets(&self, ctx: &Context, guild_id: GuildId) {
+        let tickets = match self.ticket_channels(ctx, guild_id).await {
+            Ok(tickets) => tickets,
+            Err(e) => {
+                log::error!(target: "tickets", "Unable to count the open tickets: {}", e);
+                return;
+            }
+        };
+        let mut counts = HashMap::new();
+        for (category, _) in tickets {
+            *counts.entry(category).or_insert(0) += 1;
+        }
+        metrics::OPEN_TICKETS.replace(
+            &[&guild_id.to_string()],
+            counts
+                .into_iter()
+                .map(|(category, count)| (vec![category], count))
+                .collect(),
+        );
+    }
+    /// Routes de l'API d'administration
+    ///
+    /// * `GET /components/tickets/guilds/<serveur>/categories` : catégories de tickets du serveur
+    /// * `GET /components/tickets/archives` : tickets archivés
+    /// * `GET /components/tickets/archives/<fichier>` : contenu d'un ticket archivé
+    async fn api(&self, request: &ApiRequest) -> Option<ApiResult> {
+        Some(match (request.method, request.segments().as_slice()) {
+            (Method::Get, ["guilds", guild_id, "categories"]) => match api::parse_id(guild_id) {
+                Ok(guild_id) => self.api_categories(GuildId(guild_id)).await,
+                Err(e) => Err(e),
+            },
+            (Method::Get, ["archives"]) => Self::api_archives(),
+            (Method::Get, ["archives", file]) => Self::api_archive(file),
+            _ => return None,
+        })
+    }
+    /// Liste les catégories de tickets du serveur
+    async fn api_categories(&self, guild_id: GuildId) -> ApiResult {
+        let data = self
+            .guild_data(guild_id)
+            .await
+            .map_err(ApiError::internal)?;
+        let data = data.read().await;
+        Ok(data
+            .read()
+            .categories
+            .iter()
+            .map(|cat| {
+                json!({
+                    "name": cat.name,
+                    "prefix": cat.prefix,
+                    "id": cat.id.to_string(),
+                    "desc": cat.desc,
+                })
+            })
+            .collect())
+    }
+    /// Liste les fichiers des tickets archivés, du plus récent au plus ancien
+    fn api_archives() -> ApiResult {
+        let folder = Self::get_archive_folder().map_err(ApiError::internal)?;
+        let mut archives = std::fs::read_dir(folder)
+            .map_err(ApiError::internal)?
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let metadata = entry.metadata().ok()?;
+                let modified = chrono::DateTime::<chrono::Utc>::from(metadata.modified().ok()?);
+                Some((modified, entry.file_name().to_string_lossy().to_string(), metadata.len()))
+            })
+            .collect::<Vec<_>>();
+        archives.sort_by_key(|archive| std::cmp::Reverse(archive.0));
+        Ok(archives
+            .into_iter()
+            .map(|(modified, file, size)| {
+                json!({ "file": file, "size": size, "modified": modified.to_rfc3339() })
+            })
+            .collect())
+    }
+    /// Retourne le contenu d'un ticket archivé
+    fn api_archive(file: &str) -> ApiResult {
+        if file.starts_with('.') || file.contains('\\') {
+            return Err(ApiError::bad_request("Nom de fichier invalide"));
+        }
+        let path = Self::get_archive_folder()
+            .map_err(ApiError::internal)?
+            .join(file);
+        match std::fs::read_to_string(path) {
+            Ok(content) => Ok(json!({ "file": file, "content": content })),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Err(ApiError::not_found("Archive non trouvée"))
+            }
+            Err(e) => Err(ApiError::internal(e)),
+        }
+    }
     /// Retourne le chemin du dossier de stockage des tickets
     ///
     /// Le dossier est créé s'il n'existe pas
     fn get_archive_folder() -> Result<PathBuf, std::io::Error> {
         let path = utils::DATA_DIR.join("tickets/archives");
         if !path.exists() {
-            println!("tickets: Création du dossier d'archives");
+            log::info!(target: "tickets", "Création du dossier d'archives");
             match std::fs::create_dir_all(&path) {
-                Ok(_) => println!("tickets: Dossier créé"),
+                Ok(_) => log::info!(target: "tickets", "Dossier créé"),
                 Err(e) => return Err(e),
             }
         }
@@ -506,81 +805,91 @@ impl Tickets {
             Err(e) => return Err(format!("Impossible de créer le fichier d'archive: {}", e)),
         };
         let mut users = HashMap::new();
-        struct UserData {
-            id: u64,
-            avatar: String,
-        }
         let contents: Vec<String> = channel
             .messages_iter(ctx)
             .map(|v| match v {
                 Ok(v) => {
-                    let attachments = if v.attachments.is_empty() {
-                        None
-                    } else {
-                        let attachs = v
-                            .attachments
-                            .iter()
-                            .map(|attachment| {
-                                let is_image = attachment
-                                    .content_type
-                                    .as_ref()
-                                    .map(|v| v.contains("image"))
-                                    .unwrap_or(false);
-                                format!(
-                                    "\n{}[{}]({})",
-                                    if is_image { "!" } else { "" },
-                                    attachment.filename,
-                                    attachment.url
-                                )
-                            })
-                            .collect::<String>();
-                        Some(format!("\nFichiers joints: {}", attachs))
-                    };
-                    let content = v.content.as_str();
                     let author = format!("{}#{:04}", v.author.name, v.author.discriminator);
-                    let date = v.timestamp.to_rfc3339();
                     if users.get(&author).is_none() {
-                        users.insert(
-                            author.clone(),
-                            UserData {
-                                id: v.author.id.0,
-                                avatar: v.author.avatar_url().unwrap_or(
-                                    "https://cdn.discordapp.com/embed/avatars/0.png".to_string(),
-                                ),
-                            },
-                        );
+                        users.insert(author, Self::archive_author(&v));
                     }
-                    format!(
-                        "[{}] {}: {}{}\n\n",
-                        date,
-                        author,
-                        content,
-                        attachments.unwrap_or_default()
-                    )
+                    Self::archive_content(&v)
                 }
                 Err(e) => format!("Erreur lors de la récupération d'un message: {}\n", e),
             })
             .collect()
             .await;
-        users.iter().for_each(|(username, userdata)| {
-            file.write_all(
-                format!(
-                    "{}\navatar_url: {}\nid: {}\n",
-                    username, userdata.avatar, userdata.id
-                )
-                .as_bytes(),
-            )
-            .unwrap();
+        users.values().for_each(|author| {
+            file.write_all(author.as_bytes()).unwrap();
         });
         contents
             .iter()
             .rev()
             .for_each(|v| match file.write_all(v.as_bytes()) {
                 Ok(_) => (),
-                Err(e) => eprintln!("Error writing to file: {}", e),
+                Err(e) => log::error!(target: "tickets", "Error writing to file: {}", e),
             });
         Ok(())
     }
+    /// Archive un message seul
+    ///
+    /// Le message est enregistré au même format qu'un ticket. Retourne le nom du fichier d'archive.
+    fn archive_message(msg: &Message) -> Result<String, String> {
+        let archive_path = match Self::get_archive_folder() {
+            Ok(v) => v,
+            Err(_) => return Err("Impossible de créer le dossier d'archives".to_string()),
+        };
+        let file_name = format!("{}-message-{}.txt", msg.channel_id.0, msg.id.0);
+        let content = Self::archive_author(msg) + &Self::archive_content(msg);
+        std::fs::write(archive_path.join(&file_name), content)
+            .map_err(|e| format!("Impossible d'écrire le fichier d'archive: {}", e))?;
+        Ok(file_name)
+    }
+    /// Entête d'archive de l'auteur d'un message : nom, avatar et identifiant
+    fn archive_author(msg: &Message) -> String {
+        format!(
+            "{}#{:04}\navatar_url: {}\nid: {}\n",
+            msg.author.name,
+            msg.author.discriminator,
+            msg.author
+                .avatar_url()
+                .unwrap_or_else(|| "https://cdn.discordapp.com/embed/avatars/0.png".to_string()),
+            msg.author.id.0
+        )
+    }
+    /// Contenu d'archive d'un message : date, auteur, texte et fichiers joints
+    fn archive_content(msg: &Message) -> String {
+        let attachments = if msg.attachments.is_empty() {
+            None
+        } else {
+            let attachs = msg
+                .attachments
+                .iter()
+                .map(|attachment| {
+                    let is_image = attachment
+                        .content_type
+                        .as_ref()
+                        .map(|v| v.contains("image"))
+                        .unwrap_or(false);
+                    format!(
+                        "\n{}[{}]({})",
+                        if is_image { "!" } else { "" },
+                        attachment.filename,
+                        attachment.url
+                    )
+                })
+                .collect::<String>();
+            Some(format!("\nFichiers joints: {}", attachs))
+        };
+        format!(
+            "[{}] {}#{:04}: {}{}\n\n",
+            msg.timestamp.to_rfc3339(),
+            msg.author.name,
+            msg.author.discriminator,
+            msg.content,
+            attachments.unwrap_or_default()
+        )
+    }
     /// Créer un bouton de fermeture de ticket
     ///
     /// Ce bouton ets attaché au message d'ouverture du ticket
@@ -603,17 +912,30 @@ impl Tickets {
         cmps.add_action_row(act);
     }
     /// Supprime l'ancien message de création de ticket, celui contenant le menu de sélection de catégorie
-    async fn delete_old_creation_message(&self, ctx: &Context) -> serenity::Result<()> {
-        let old_msg = self.data.read().await.read().msg_choose;
+    async fn delete_old_creation_message(
+        &self,
+        ctx: &Context,
+        data: &RwLock<Data<DataTickets>>,
+    ) -> serenity::Result<()> {
+        let old_msg = data.read().await.read().msg_choose;
         if let Some((channel_id, msg_id)) = old_msg {
             ctx.http.delete_message(channel_id, msg_id).await
         } else {
             Ok(())
         }
     }
-    async fn set_channel(&self, ctx: &Context, guild_id: GuildId, id: u64) -> message::Message {
-        if let Err(e) = self.delete_old_creation_message(ctx).await {
-            eprintln!("tickets: unable to delete previous message.\n{:?}", e)
+    async fn set_channel(
+        &self,
+        ctx: &Context,
+        guild_id: GuildId,
+        id: u64,
+    ) -> message::Message {
+        let data = &match self.guild_data(guild_id).await {
+            Ok(data) => data,
+            Err(e) => return message::error(e),
+        };
+        if let Err(e) = self.delete_old_creation_message(ctx, data).await {
+            log::error!(target: "tickets", "unable to delete previous message.\n{:?}", e)
         }
         let channel = guild_id
             .channels(ctx)
@@ -634,13 +956,13 @@ impl Tickets {
             match msg_tickets {
                 Ok(msg_sent) => {
                     {
-                        let mut data = self.data.write().await;
+                        let mut data = data.write().await;
                         let mut data = data.write();
                         data.msg_choose = Some((channel.0, msg_sent.id.0));
                     }
-                    err_println!(
-                        self.update_select_menu(ctx).await,
-                        "tickets: unable to update message after adding a category.\n{:?}"
+                    err_log!(
+                        self.update_select_menu(ctx, data).await,
+                        "unable to update message after adding a category.\n{:?}"
                     );
                     message::success(format!(
                         "Le message de création de ticket a été mis à jour dans le salon {}.",
@@ -686,8 +1008,12 @@ impl Tickets {
         cmps.add_action_row(act);
     }
     /// Met à jour le menu de sélection de catégorie
-    async fn update_select_menu(&self, ctx: &Context) -> serenity::Result<()> {
-        let data = self.data.read().await;
+    async fn update_select_menu(
+        &self,
+        ctx: &Context,
+        data: &RwLock<Data<DataTickets>>,
+    ) -> serenity::Result<()> {
+        let data = data.read().await;
         let data = data.read();
         let categories = &data.categories;
 
@@ -727,14 +1053,17 @@ impl Tickets {
         channel_id: u64,
         prefix: String,
     ) -> message::Message {
-        if let Some(_) = self
-            .data
+        let data = &match self.guild_data(guild_id).await {
+            Ok(data) => data,
+            Err(e) => return message::error(e),
+        };
+        if data
             .read()
             .await
             .read()
             .categories
             .iter()
-            .find(|v| v.name == name)
+            .any(|v| v.name == name)
         {
             return message::error(format!("La catégorie de ticket {} existe déjà.", name));
         }
@@ -749,7 +1078,7 @@ impl Tickets {
             None => return message::error("Le salon n'existe pas."),
         };
         match guild_channel.kind {
-            serenity::model::channel::ChannelType::Category => (),
+            ChannelType::Category => (),
             _ => {
                 return message::error(format!(
                     "L'id ne pointe pas sur une catégorie mais sur {} de type {:?}.",
@@ -759,7 +1088,7 @@ impl Tickets {
             }
         }
         {
-            let mut data = self.data.write().await;
+            let mut data = data.write().await;
             let mut data = data.write();
             data.categories.push(CategoryTicket {
                 name,
@@ -770,18 +1099,26 @@ impl Tickets {
                 emoji: None,
             });
         }
-        err_println!(
-            self.update_select_menu(ctx).await,
-            "tickets: unable to update message after adding a category.\n{:?}"
+        err_log!(
+            self.update_select_menu(ctx, data).await,
+            "unable to update message after adding a category.\n{:?}"
         );
-        let data = self.data.read().await;
+        let data = data.read().await;
         let category = data.read().categories.last().unwrap();
         message::success(format!("La catégorie {} a été ajoutée.", category.name))
     }
     /// Supprime une catégorie de ticket
-    async fn category_remove(&self, ctx: &Context, name: String) -> message::Message {
-        let i = match self
-            .data
+    async fn category_remove(
+        &self,
+        ctx: &Context,
+        guild_id: GuildId,
+        name: String,
+    ) -> message::Message {
+        let data = &match self.guild_data(guild_id).await {
+            Ok(data) => data,
+            Err(e) => return message::error(e),
+        };
+        let i = match data
             .read()
             .await
             .read()
@@ -792,16 +1129,24 @@ impl Tickets {
             Some(i) => i,
             None => return message::error(format!("La catégorie {} n'existe pas.", name)),
         };
-        self.data.write().await.write().categories.swap_remove(i);
-        err_println!(
-            self.update_select_menu(ctx).await,
-            "tickets: unable to update message after deleting a category.\n{:?}"
+        data.write().await.write().categories.swap_remove(i);
+        err_log!(
+            self.update_select_menu(ctx, data).await,
+            "unable to update message after deleting a category.\n{:?}"
         );
         message::success(format!("La catégorie {} a été supprimée.", name))
     }
     /// Liste les catégories de ticket du composant
-    async fn categories_list(&self, ctx: &Context, guild_id: GuildId) -> message::Message {
-        let data = self.data.read().await;
+    async fn categories_list(
+        &self,
+        ctx: &Context,
+        guild_id: GuildId,
+    ) -> message::Message {
+        let data = match self.guild_data(guild_id).await {
+            Ok(data) => data,
+            Err(e) => return message::error(e),
+        };
+        let data = data.read().await;
         let data = data.read();
         let categories = &data.categories;
         if categories.is_empty() {
@@ -1,161 +1,100 @@
+use super::commands::{self, ToCommand};
+use crate::component_system::command_parser as cmd;
 use serenity::model::{
+    channel::Message,
     id::GuildId,
-    interactions::application_command::{
-        ApplicationCommandInteraction, ApplicationCommandInteractionData,
-        ApplicationCommandInteractionDataOption, ApplicationCommandOptionType,
-    },
+    interactions::application_command::{ApplicationCommandInteraction, ResolvedTarget},
+    user::User,
 };
 
-enum CommandType<'b> {
-    Command(&'b ApplicationCommandInteractionData),
-    Option(&'b ApplicationCommandInteractionDataOption),
-}
-
-impl<'a> CommandType<'a> {
-    pub fn get_argument(
-        &'a self,
-        name: &str,
-    ) -> Option<&'a ApplicationCommandInteractionDataOption> {
-        match self {
-            CommandType::Command(command) => {
-                command.options.iter().find(|option| option.name == name)
-            }
-            CommandType::Option(option) => option.options.iter().find(|option| option.name == name),
-        }
-    }
-}
-
 /// # Conteneur d'application command
 ///
 /// Lorsque le bot recoie une commande via un evenement d'interaction, cette structure est un utilitaire
 /// pour accéder aux données d'une struct ApplicationCommandInteraction plus facilement. La commande et
-/// ses arguments pouvant se situer à différent niveau en fonction des sous groupes, [`get_argument`]
-/// permet d'obtenir directement les arguments.
+/// ses arguments pouvant se situer à différent niveau en fonction des sous groupes, [`command`]
+/// permet d'obtenir directement la commande et ses arguments typés.
 ///
-/// [`get_argument`]: `Self::get_argument`
-pub struct ApplicationCommandEmbed<'a>(pub &'a ApplicationCommandInteraction, CommandType<'a>);
+/// [`command`]: `Self::command`
+pub struct ApplicationCommandEmbed<'a>(pub &'a ApplicationCommandInteraction, commands::Command);
 
 impl<'a> ApplicationCommandEmbed<'a> {
     /// Créer un conteneur d'application command
     ///
     /// La (sous) commande est recherchée dans la commande principale, puis dans les options.
     pub fn new(interaction: &'a ApplicationCommandInteraction) -> Self {
-        let mut command = CommandType::Command(&interaction.data);
-        loop {
-            let options = match command {
-                CommandType::Command(data) => &data.options,
-                CommandType::Option(data) => &data.options,
-            };
-            if options.is_empty() {
-                break;
-            }
-            if let Some(cmd) = options.iter().find(|option| {
-                option.kind == ApplicationCommandOptionType::SubCommand
-                    || option.kind == ApplicationCommandOptionType::SubCommandGroup
-            }) {
-                command = CommandType::Option(cmd);
-            } else {
-                break;
-            }
-        }
-        ApplicationCommandEmbed(interaction, command)
+        ApplicationCommandEmbed(interaction, interaction.to_command())
     }
     /// Retourne le nom de la commande complète.
     ///
     /// Ca inclut le nom des sous groupes et de la commande tel que `groupe.sous_groupe.commande`
     pub fn fullname(&self) -> String {
-        let mut names = vec![self.0.data.name.as_str()];
-        let mut cmd = self.0.data.options.first();
-        // s'inspirer de la fonction get_command pour produire le nom
-        while let Some(&ApplicationCommandInteractionDataOption {
-            ref name,
-            ref options,
-            kind:
-                ApplicationCommandOptionType::SubCommandGroup | ApplicationCommandOptionType::SubCommand,
-            ..
-        }) = cmd
-        {
-            names.push(name.as_str());
-            cmd = options.first();
-        }
-        names.join(".")
+        self.1.fullname()
     }
     /// Retourne l'id du serveur sur lequel la commande a été effectuée.
     pub fn get_guild_id(&self) -> Option<GuildId> {
         self.0.guild_id
     }
-    /// Cherche et retourne l'argument `name`.
-    pub fn get_argument(
-        &'a self,
-        name: &str,
-    ) -> Option<&'a ApplicationCommandInteractionDataOption> {
-        self.1.get_argument(name)
+    /// Retourne l'id du serveur sur lequel la commande a été effectuée.
+    ///
+    /// Retourne une erreur si la commande n'a pas été lancée depuis un serveur.
+    pub fn require_guild_id(&self) -> Result<GuildId, String> {
+        self.get_guild_id()
+            .ok_or_else(|| "Vous devez être dans un serveur pour utiliser cette commande.".into())
     }
-}
-/// Helper pour accéder à un argument d'une commande
-///
-/// Fait plusieurs vérifications pour obtenir l'argument et simplifie la lecture du code
-macro_rules! get_argument_result {
-    ($app_command:expr, $name:expr, $typ:ident) => {
-        match $app_command.get_argument($name) {
-            Some(ref v) => {
-                match v.resolved {
-                    Some(ref v) => {
-                        match v {
-                            serenity::model::interactions::application_command::ApplicationCommandInteractionDataOptionValue::$typ (v) => Ok(v),
-                            _ => Err(format!("{}: Mauvais type d'argument. {} attendu", $name, stringify!($typ)))
-                        }
-                    },
-                    None => Err("Erreur de syntaxe".to_string())
-                }
-            },
-            None => Err(format!("{}: Paramètre requis manquant", $name))
+    /// Retourne la commande et ses arguments.
+    ///
+    /// Voir [`commands::Command::get`] et [`commands::Command::opt`] pour lire les arguments.
+    pub fn command(&self) -> &commands::Command {
+        &self.1
+    }
+    /// Retourne le membre ciblé par une commande de menu contextuel sur un membre.
+    ///
+    /// Retourne None pour les autres commandes.
+    pub fn target_user(&self) -> Option<&User> {
+        match &self.0.data.target {
+            Some(ResolvedTarget::User(user, _)) => Some(user),
+            _ => None,
         }
-    };
-}
-/// Helper pour accéder à un argument d'une commande s'il existe
-///
-/// Fait plusieurs vérifications pour obtenir l'argument et simplifie la lecture du code
-macro_rules! get_optional_argument_result {
-    ($app_command:expr, $name:expr, $typ:ident) => {
-        match $app_command.get_argument($name) {
-            Some(ref v) => {
-                match v.resolved {
-                    Some(ref v) => {
-                        match v {
-                            serenity::model::interactions::application_command::ApplicationCommandInteractionDataOptionValue::$typ (v) => Ok(Some(v)),
-                            _ => Err(format!("{}: Mauvais type d'argument. {} attendu", $name, stringify!($typ)))
-                        }
-                    },
-                    None => Err("Erreur de syntaxe".to_string())
-                }
-            },
-            None => Ok(None)
+    }
+    /// Retourne le message ciblé par une commande de menu contextuel sur un message.
+    ///
+    /// Retourne None pour les autres commandes.
+    pub fn target_message(&self) -> Option<&Message> {
+        match &self.0.data.target {
+            Some(ResolvedTarget::Message(message)) => Some(message),
+            _ => None,
         }
-    };
+    }
+    /// Vérifie que les arguments respectent les contraintes de leur paramètre.
+    ///
+    /// Voir [`commands::Command::check_constraints`].
+    pub fn check_constraints(&self, params: &[cmd::Argument]) -> Result<(), String> {
+        self.1.check_constraints(params)
+    }
+    /// Ajoute la valeur par défaut des arguments absents de la commande.
+    ///
+    /// Voir [`commands::Command::apply_defaults`].
+    pub fn apply_defaults(&mut self, params: &[cmd::Argument]) {
+        self.1.apply_defaults(params)
+    }
 }
-/// Helper pour accéder à un argument d'une commande
+/// Helper pour créer le [handler] d'une commande à partir d'une méthode asynchrone
 ///
-/// Contraitement à [`get_argument_result!`] et [`get_optional_argument_result!`], cette macro ne fait
-/// pas de vérification superflu et retourne l'argument de la commande si les critères sont remplis.
-macro_rules! get_argument {
-    ($app_command:expr, $name:expr, User) => {
-        match $app_command.get_argument($name) {
-            Some(serenity::model::interactions::application_command::ApplicationCommandInteractionDataOption{
-                resolved: Some(serenity::model::interactions::application_command::ApplicationCommandInteractionDataOptionValue::User(a, b)),
-                ..
-            }) => Some((a, b)),
-            _ => None
-        }
-    };
-    ($app_command:expr, $name:expr, $typ:ident) => {
-        match $app_command.get_argument($name) {
-            Some(serenity::model::interactions::application_command::ApplicationCommandInteractionDataOption{
-                resolved: Some(serenity::model::interactions::application_command::ApplicationCommandInteractionDataOptionValue::$typ(s)),
-                ..
-            }) => Some(s),
-            _ => None
+/// `$inner` est un `Arc` vers la structure qui implémente la méthode `$method`. Il est cloné à chaque appel
+/// et la méthode est appelée avec le contexte, la commande puis les éventuels arguments supplémentaires.
+///
+/// ```ignore
+/// cmd::Command::new("ban").set_handler(handler!(inner, moderate, TypeModeration::Ban, false))
+/// ```
+///
+/// [handler]: crate::component_system::command_parser::Handler
+macro_rules! handler {
+    ($inner:expr, $method:ident $(, $arg:expr)*) => {{
+        let inner = std::sync::Arc::clone(&$inner);
+        move |ctx, app_cmd| {
+            let inner = std::sync::Arc::clone(&inner);
+            Box::pin(async move { inner.$method(ctx, app_cmd $(, $arg)*).await })
         }
-    };
+    }};
 }
-pub(crate) use get_argument;
+pub(crate) use handler;
@@ -1,5 +1,6 @@
 pub use serenity::model::interactions::application_command::ApplicationCommandInteractionDataOptionValue;
 use serenity::model::{
+    channel::ChannelType,
     id::{ChannelId, RoleId, UserId},
     interactions::application_command::{
         ApplicationCommandInteraction, ApplicationCommandInteractionData,
@@ -7,17 +8,17 @@ use serenity::model::{
     },
 };
 
-use crate::component_system::command_parser::matching;
+use crate::component_system::command_parser::{self as cmd, parse_id, ValueType};
 #[derive(Debug)]
 pub enum Value {
     String(String),
     Integer(i64),
     Boolean(bool),
     User(UserId),
-    Channel(ChannelId),
+    /// Salon et son type, connu si la valeur vient d'une slash commande
+    Channel(ChannelId, Option<ChannelType>),
     Role(RoleId),
     Number(f64),
-    Mention(u64),
 }
 
 impl From<ApplicationCommandInteractionDataOptionValue> for Value {
@@ -27,13 +28,72 @@ impl From<ApplicationCommandInteractionDataOptionValue> for Value {
             ApplicationCommandInteractionDataOptionValue::Integer(i) => Value::Integer(i),
             ApplicationCommandInteractionDataOptionValue::Boolean(b) => Value::Boolean(b),
             ApplicationCommandInteractionDataOptionValue::User(u, _) => Value::User(u.id),
-            ApplicationCommandInteractionDataOptionValue::Channel(c) => Value::Channel(c.id),
+            ApplicationCommandInteractionDataOptionValue::Channel(c) => {
+                Value::Channel(c.id, Some(c.kind))
+            }
             ApplicationCommandInteractionDataOptionValue::Role(r) => Value::Role(r.id),
             ApplicationCommandInteractionDataOptionValue::Number(n) => Value::Number(n),
             _ => panic!("Unsupported value type"),
         }
     }
 }
+/// Membre ou rôle, valeur d'un argument de type [`ValueType::Mentionable`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mentionable {
+    User(UserId),
+    Role(RoleId),
+}
+/// Conversion de la [`Value`] d'un argument vers un type Rust
+///
+/// Utilisé par [`Command::get`] et [`Command::opt`] pour extraire un argument typé.
+pub trait FromValue: Sized {
+    /// Type de valeur attendu, indiqué dans les messages d'erreur
+    const VALUE_TYPE: ValueType;
+    /// Retourne None si la valeur n'est pas du type attendu
+    fn from_value(value: &Value) -> Option<Self>;
+}
+macro_rules! impl_from_value {
+    ($($typ:ty => $variant:ident),*) => {
+        $(
+            impl FromValue for $typ {
+                const VALUE_TYPE: ValueType = ValueType::$variant;
+                fn from_value(value: &Value) -> Option<Self> {
+                    match value {
+                        Value::$variant(v) => Some(v).cloned(),
+                        _ => None,
+                    }
+                }
+            }
+        )*
+    };
+}
+impl_from_value!(
+    String => String,
+    i64 => Integer,
+    bool => Boolean,
+    UserId => User,
+    RoleId => Role,
+    f64 => Number
+);
+impl FromValue for ChannelId {
+    const VALUE_TYPE: ValueType = ValueType::Channel;
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Channel(v, _) => Some(*v),
+            _ => None,
+        }
+    }
+}
+impl FromValue for Mentionable {
+    const VALUE_TYPE: ValueType = ValueType::Mentionable;
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::User(v) => Some(Mentionable::User(*v)),
+            Value::Role(v) => Some(Mentionable::Role(*v)),
+            _ => None,
+        }
+    }
+}
 #[derive(Debug)]
 pub struct Command {
     pub path: Vec<String>,
@@ -54,6 +114,77 @@ impl Command {
     pub fn get_argument(&self, name: &str) -> Option<&Argument> {
         self.args.iter().find(|arg| arg.name == name)
     }
+    /// Retourne la valeur de l'argument `name` convertie en `T`.
+    ///
+    /// Retourne une erreur si l'argument est absent ou n'est pas du type attendu.
+    ///
+    /// ```ignore
+    /// let user = command.get::<UserId>("qui")?;
+    /// ```
+    pub fn get<T: FromValue>(&self, name: &str) -> Result<T, String> {
+        self.opt(name)?
+            .ok_or_else(|| format!("{}: paramètre requis manquant", name))
+    }
+    /// Retourne la valeur de l'argument `name` convertie en `T`, ou None si l'argument est absent.
+    ///
+    /// Retourne une erreur si l'argument n'est pas du type attendu.
+    pub fn opt<T: FromValue>(&self, name: &str) -> Result<Option<T>, String> {
+        match self.get_argument(name) {
+            Some(arg) => T::from_value(&arg.value).map(Some).ok_or_else(|| {
+                format!(
+                    "{}: mauvais type d'argument, {} attendu",
+                    name,
+                    cmd::value_type_str(T::VALUE_TYPE)
+                )
+            }),
+            None => Ok(None),
+        }
+    }
+    /// Vérifie que les arguments respectent les contraintes de leur paramètre (bornes, longueur, motif, type de salon).
+    ///
+    /// Discord ne vérifie que les bornes des slash commandes : la longueur et le motif sont vérifiés ici.
+    pub fn check_constraints(&self, params: &[cmd::Argument]) -> Result<(), String> {
+        for arg in &self.args {
+            let param = match params.iter().find(|param| param.name == arg.name) {
+                Some(param) => param,
+                None => continue,
+            };
+            let result = match &arg.value {
+                Value::String(value) => param.check_string(value),
+                Value::Integer(value) => param.check_number(*value),
+                Value::Number(value) => param.check_number(*value),
+                Value::Channel(_, Some(kind)) => param.check_channel_type(*kind),
+                _ => Ok(()),
+            };
+            if let Err(reason) = result {
+                return Err(cmd::ParseError::InvalidValue {
+                    name: &param.name,
+                    reason,
+                }
+                .to_string());
+            }
+        }
+        Ok(())
+    }
+    /// Ajoute la valeur par défaut des arguments absents de la commande.
+    ///
+    /// Voir [`cmd::Argument::set_default`].
+    pub fn apply_defaults(&mut self, params: &[cmd::Argument]) {
+        for param in params {
+            if self.get_argument(&param.name).is_some() {
+                continue;
+            }
+            let value = param
+                .default()
+                .and_then(|default| parse_value(param.value_type(), default));
+            if let Some(value) = value {
+                self.args.push(Argument {
+                    name: param.name.clone(),
+                    value,
+                });
+            }
+        }
+    }
 }
 
 pub trait ToCommand {
@@ -65,50 +196,44 @@ enum CommandType<'b> {
     Option(&'b ApplicationCommandInteractionDataOption),
 }
 
-impl<'a> CommandType<'a> {
-    pub fn get_argument(
-        &'a self,
-        name: &str,
-    ) -> Option<&'a ApplicationCommandInteractionDataOption> {
-        match self {
-            CommandType::Command(command) => {
-                command.options.iter().find(|option| option.name == name)
-            }
-            CommandType::Option(option) => option.options.iter().find(|option| option.name == name),
+/// Retourne le chemin de la (sous) commande et ses options.
+///
+/// La sous commande est recherchée dans les options de la commande principale puis des sous groupes.
+fn command_options(
+    data: &ApplicationCommandInteractionData,
+) -> (Vec<String>, &Vec<ApplicationCommandInteractionDataOption>) {
+    use serenity::model::interactions::application_command::ApplicationCommandOptionType;
+
+    let mut command = CommandType::Command(data);
+    let mut path = vec![data.name.clone()];
+    loop {
+        let options = match command {
+            CommandType::Command(data) => &data.options,
+            CommandType::Option(data) => &data.options,
+        };
+        if options.is_empty() {
+            break;
+        }
+        if let Some(cmd) = options.iter().find(|option| {
+            option.kind == ApplicationCommandOptionType::SubCommand
+                || option.kind == ApplicationCommandOptionType::SubCommandGroup
+        }) {
+            path.push(cmd.name.clone());
+            command = CommandType::Option(cmd);
+        } else {
+            break;
         }
     }
+    let options = match command {
+        CommandType::Command(ApplicationCommandInteractionData { options, .. }) => options,
+        CommandType::Option(ApplicationCommandInteractionDataOption { options, .. }) => options,
+    };
+    (path, options)
 }
 
 impl ToCommand for ApplicationCommandInteraction {
     fn to_command(&self) -> Command {
-        use serenity::model::interactions::application_command::ApplicationCommandOptionType;
-
-        let mut command = CommandType::Command(&self.data);
-        let mut path = vec![self.data.name.clone()];
-        loop {
-            let options = match command {
-                CommandType::Command(data) => &data.options,
-                CommandType::Option(data) => &data.options,
-            };
-            if options.is_empty() {
-                break;
-            }
-            if let Some(cmd) = options.iter().find(|option| {
-                option.kind == ApplicationCommandOptionType::SubCommand
-                    || option.kind == ApplicationCommandOptionType::SubCommandGroup
-            }) {
-                path.push(cmd.name.clone());
-                command = CommandType::Option(cmd);
-            } else {
-                break;
-            }
-        }
-        let options = match command {
-            CommandType::Command(&ApplicationCommandInteractionData { ref options, .. }) => options,
-            CommandType::Option(&ApplicationCommandInteractionDataOption {
-                ref options, ..
-            }) => options,
-        };
+        let (path, options) = command_options(&self.data);
         let args = options
             .iter()
             .filter_map(|option| {
@@ -126,48 +251,49 @@ impl ToCommand for ApplicationCommandInteraction {
     }
 }
 
-impl<'a> ToCommand for matching::Command<'a> {
-    fn to_command(&self) -> Command {
-        let path = self.path.iter().map(|part| part.to_string()).collect();
-        let args = self.params.iter().filter_map(|arg| {
-            use serenity::model::interactions::application_command::ApplicationCommandOptionType::*;
-            Some(Argument {
-                name: arg.name.to_string(),
-                value: match arg.kind {
-                    String => Value::String(arg.value.to_string()),
-                    Integer => match arg.value.parse::<i64>() {
-                        Ok(value) => Value::Integer(value),
-                        Err(_) => return None
-                    },
-                    Boolean => match arg.value {
-                        "vrai"|"true"|"1" => Value::Boolean(true),
-                        _ => Value::Boolean(false),
-                    },
-                    User => match arg.value.parse::<u64>() {
-                        Ok(value) => Value::User(UserId(value)),
-                        Err(_) => return None
-                    },
-                    Channel => match arg.value.parse::<u64>() {
-                        Ok(value) => Value::Channel(ChannelId(value)),
-                        Err(_) => return None
-                    },
-                    Role => match arg.value.parse::<u64>() {
-                        Ok(value) => Value::Role(RoleId(value)),
-                        Err(_) => return None
-                    },
-                    Mentionable => match arg.value.parse::<u64>() {
-                        Ok(value) => Value::Mention(value),
-                        Err(_) => return None
-                    },
-                    Number => match arg.value.parse::<f64>() {
-                        Ok(value) => Value::Number(value),
-                        Err(_) => return None
-                    },
-                    _ => return None
-                }
-            })
-        }).collect();
+/// Argument en cours de saisie d'une demande d'autocomplétion
+#[derive(Debug)]
+pub struct FocusedArgument {
+    /// Chemin de la commande, tel que `groupe.commande`
+    pub command: String,
+    /// Nom de l'argument
+    pub name: String,
+    /// Saisie partielle de l'utilisateur
+    pub value: String,
+}
 
-        Command::new(path, args)
-    }
+/// Retourne l'argument en cours de saisie d'une demande d'autocomplétion
+pub fn focused_argument(data: &ApplicationCommandInteractionData) -> Option<FocusedArgument> {
+    let (path, options) = command_options(data);
+    let option = options.iter().find(|option| option.focused)?;
+    let value = match &option.value {
+        Some(serde_json::Value::String(value)) => value.clone(),
+        Some(value) => value.to_string(),
+        None => String::new(),
+    };
+    Some(FocusedArgument {
+        command: path.join("."),
+        name: option.name.clone(),
+        value,
+    })
+}
+
+/// Convertit la valeur textuelle d'un argument, comme sa valeur par défaut, en [`Value`] du type `kind`.
+///
+/// Retourne None si la valeur n'est pas du type attendu.
+pub fn parse_value(kind: ValueType, value: &str) -> Option<Value> {
+    use serenity::model::interactions::application_command::ApplicationCommandOptionType::*;
+    Some(match kind {
+        String => Value::String(value.to_string()),
+        Integer => Value::Integer(value.parse::<i64>().ok()?),
+        Boolean => Value::Boolean(cmd::parse_bool(value)?),
+        User => Value::User(UserId(parse_id(value)?)),
+        Channel => Value::Channel(ChannelId(parse_id(value)?), None),
+        Role => Value::Role(RoleId(parse_id(value)?)),
+        // Seule une mention indique s'il s'agit d'un membre ou d'un rôle
+        Mentionable if value.starts_with("<@&") => Value::Role(RoleId(parse_id(value)?)),
+        Mentionable if value.starts_with("<@") => Value::User(UserId(parse_id(value)?)),
+        Number => Value::Number(value.parse::<f64>().ok()?),
+        _ => return None,
+    })
 }
@@ -1,4 +1,5 @@
 use serenity::builder::{CreateEmbed, CreateInteractionResponse, CreateMessage};
+use serenity::http::AttachmentType;
 use serenity::utils::Colour;
 
 /// Interface de création de message
@@ -11,6 +12,8 @@ pub struct Message {
     pub message: String,
     pub embed: Option<CreateEmbed>,
     pub ephemeral: bool,
+    /// Fichiers joints. En réponse à une slash commande, ils sont envoyés dans un message de suivi.
+    pub files: Vec<AttachmentType<'static>>,
 }
 
 impl Message {
@@ -24,6 +27,14 @@ impl Message {
         self.ephemeral = ephemeral;
         self
     }
+    /// Joint le fichier `filename` de contenu `data` au message
+    pub fn add_file<S: Into<String>>(mut self, data: Vec<u8>, filename: S) -> Self {
+        self.files.push(AttachmentType::Bytes {
+            data: data.into(),
+            filename: filename.into(),
+        });
+        self
+    }
 }
 impl Default for Message {
     fn default() -> Self {
@@ -31,6 +42,7 @@ impl Default for Message {
             message: String::new(),
             embed: None,
             ephemeral: false,
+            files: Vec::new(),
         }
     }
 }
@@ -44,6 +56,7 @@ impl From<Message> for CreateMessage<'static> {
                 e
             });
         }
+        res.add_files(message.files);
         res
     }
 }
@@ -12,6 +12,9 @@ pub mod send;
 #[macro_use]
 pub mod app_command;
 pub mod commands;
+/// Permission des commandes réservées aux modérateurs : le rôle du serveur de ce nom
+pub const STAFF_PERMISSION: &str = "staff";
+
 /// Retourne vrai s'il sagit d'un message privé au bot
 pub fn is_dm(_ctx: &cmp::Context, msg: &cmp::Message) -> bool {
     msg.guild_id.is_none()
@@ -0,0 +1,522 @@
+//! Module de gestion des données des composants.
+//!
+//! Les données sont enregistrées dans le [support de stockage](storage) choisi dans le fichier de configuration.
+//! Chaque donnée est enregistrée avec la version de son [modèle](Schema).
+#![allow(dead_code)]
+
+mod schema;
+mod storage;
+pub mod write_behind;
+
+use std::collections::HashMap;
+use std::env;
+use std::fmt::{Debug, Display};
+use std::ops::{Deref, DerefMut};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use futures_locks::RwLock;
+use lazy_static::lazy_static;
+use serde::{de::DeserializeOwned, Serialize};
+use serenity::model::id::GuildId;
+
+lazy_static! {
+    /// Chemin du dossier contenant les données.
+    pub static ref DATA_DIR: PathBuf = env::current_dir().unwrap().join("data");
+    /// Support de stockage utilisé par les données.
+    static ref STORAGE: std::sync::RwLock<Arc<dyn Storage>> =
+        std::sync::RwLock::new(Arc::new(RonStorage::new(DATA_DIR.as_path(), 0)));
+}
+pub use schema::Schema;
+pub use storage::{copy_all, DataKey, RonStorage, Storage, StorageConfig};
+
+/// Change le support de stockage des données.
+///
+/// À appeler au démarrage du bot, avant le chargement des données.
+pub fn set_storage(storage: Arc<dyn Storage>) {
+    *STORAGE.write().unwrap() = storage;
+}
+/// Retourne le support de stockage des données.
+pub fn storage() -> Arc<dyn Storage> {
+    STORAGE.read().unwrap().clone()
+}
+#[derive(Debug)]
+pub enum DataError {
+    /// Erreur lors de la lecture/écriture du fichier.
+    FileError(std::io::Error),
+    /// Erreur de la base SQLite.
+    SqliteError(rusqlite::Error),
+    /// Erreur de sérialisation/déserialisation.
+    SerdeError(ron::error::Error),
+    /// La donnée n'existe pas dans le support de stockage.
+    MissingFileError,
+    /// La donnée a été enregistrée avec une version du modèle plus récente que celle du composant.
+    VersionError(u32),
+    /// Aucune migration n'est définie depuis cette version du modèle.
+    MigrationError(u32),
+}
+use DataError::*;
+
+impl Display for DataError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FileError(e) => write!(f, "Erreur de fichier: {}", e),
+            SqliteError(e) => write!(f, "Erreur SQLite: {}", e),
+            SerdeError(e) => write!(f, "Erreur de format: {}", e),
+            MissingFileError => write!(f, "Fichier de données manquant"),
+            VersionError(v) => write!(f, "Version {} du modèle non supportée", v),
+            MigrationError(v) => write!(f, "Aucune migration depuis la version {}", v),
+        }
+    }
+}
+
+pub type DataResult<T> = std::result::Result<T, DataError>;
+
+/// Gestionnaire de donnée.
+///
+/// La structure contient les données d'un composant. Elle se charge de la lecture et de l'enregistrement des données dans le [`Storage`].
+pub struct Data<T>
+where
+    T: DeserializeOwned + Serialize,
+{
+    pub name: String,
+    pub value: T,
+    /// Serveur auquel appartient la donnée. Commune à tout le bot si None.
+    guild_id: Option<GuildId>,
+    /// Version du modèle de la donnée
+    version: u32,
+    /// Support dans lequel la donnée est enregistrée
+    storage: Arc<dyn Storage>,
+}
+impl<T> Debug for Data<T>
+where
+    T: DeserializeOwned + Serialize + Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Data {{ name: {}, value: {:?} }}", self.name, self.value)
+    }
+}
+
+impl<T> Data<T>
+where
+    T: DeserializeOwned + Serialize,
+{
+    /// Crée une nouvelle donnée.
+    pub fn new(name: &str, value: T) -> Data<T> {
+        Data {
+            name: name.to_string(),
+            value,
+            guild_id: None,
+            version: 0,
+            storage: storage(),
+        }
+    }
+    /// Charge une donnée depuis un fichier.
+    ///
+    /// Si le fichier n'existe pas, une nouvelle donnée est créée.
+    pub fn from_file<S: AsRef<str>>(name: S) -> DataResult<Data<T>> {
+        Self::load(storage(), name.as_ref(), None, &Schema::default())
+    }
+    /// Charge une donnée depuis le support de stockage `storage`.
+    ///
+    /// Si la donnée est illisible, elle est mise de côté (voir [`Storage::set_aside`]) et la sauvegarde valide
+    /// la plus récente est chargée à la place. La sauvegarde est aussi chargée si la donnée a été mise de côté
+    /// sans être enregistrée depuis.
+    fn load(
+        storage: Arc<dyn Storage>,
+        name: &str,
+        guild_id: Option<GuildId>,
+        schema: &Schema,
+    ) -> DataResult<Data<T>> {
+        let key = DataKey::new(name, guild_id);
+        let value = match storage.load(&key)? {
+            Some(content) => match schema.decode(&content) {
+                Ok(value) => value,
+                // Une version plus récente ne doit pas être remplacée par une ancienne sauvegarde
+                Err(err @ VersionError(_)) => return Err(err),
+                Err(err) => {
+                    log::warn!(
+                        target: "data",
+                        "{} - Impossible de lire la donnée ({}), chargement de la dernière sauvegarde valide.",
+                        key, err
+                    );
+                    let value = Self::load_backup(storage.as_ref(), &key, schema, err)?;
+                    // Sinon le prochain enregistrement placerait la donnée illisible dans les sauvegardes
+                    if let Err(e) = storage.set_aside(&key) {
+                        log::error!(target: "data", "{} - Impossible de mettre la donnée illisible de côté: {}", key, e);
+                    }
+                    value
+                }
+            },
+            None => Self::load_backup(storage.as_ref(), &key, schema, DataError::MissingFileError)?,
+        };
+        let data = Data {
+            name: name.to_string(),
+            value,
+            guild_id,
+            version: schema.version(),
+            storage,
+        };
+
+        Ok(data)
+    }
+    /// Charge la sauvegarde valide la plus récente d'une donnée illisible ou absente.
+    ///
+    /// Retourne l'erreur `err` de lecture de la donnée si aucune sauvegarde n'est valide.
+    fn load_backup(
+        storage: &dyn Storage,
+        key: &DataKey,
+        schema: &Schema,
+        err: DataError,
+    ) -> DataResult<T> {
+        for (index, content) in storage.load_backups(key)?.iter().enumerate() {
+            match schema.decode(content) {
+                Ok(value) => {
+                    log::warn!(target: "data", "{} - Sauvegarde n°{} chargée.", key, index + 1);
+                    return Ok(value);
+                }
+                Err(e) => {
+                    log::warn!(target: "data", "{} - Sauvegarde n°{} invalide: {}", key, index + 1, e)
+                }
+            }
+        }
+        Err(err)
+    }
+    /// Identifiant de la donnée dans le support de stockage
+    pub fn key(&self) -> DataKey {
+        DataKey::new(&self.name, self.guild_id)
+    }
+    /// Charge une donnée depuis un fichier.
+    ///
+    /// Si le fichier n'existe pas, le paramètre `default` est utilisé pour initialiser la donnée.
+    ///
+    /// Préférez la fonction [`from_file_default`](Data<T>::from_file_default()) si votre Le type de données implémente [`Default`].
+    ///
+    pub fn from_file_or<S: AsRef<str>>(name: S, default: T) -> DataResult<Data<T>> {
+        match Self::from_file(name.as_ref()) {
+            Err(DataError::MissingFileError) => Ok(Data::new(name.as_ref(), default)),
+            v => v,
+        }
+    }
+    /// Accède en lecture aux données.
+    ///
+    /// Aucune lecture ni enregistrement de fichier n'est effectué.
+    pub fn read(&self) -> &T {
+        &self.value
+    }
+    /// Accède en écriture aux données.
+    ///
+    /// Retourne un [`DataGuard`] qui vous permet d'écrire dans les données.
+    pub fn write<'a>(&'a mut self) -> DataGuard<'a, T> {
+        DataGuard(self)
+    }
+}
+impl<T> Data<T>
+where
+    T: DeserializeOwned + Serialize + Default,
+{
+    /// Charge une donnée depuis un fichier.
+    ///
+    /// Si le fichier n'existe pas, une nouvelle donnée est créée avec [`Default::default()`].
+    pub fn from_file_default<S: AsRef<str>>(name: S) -> DataResult<Data<T>> {
+        match Self::from_file(name.as_ref()) {
+            Err(DataError::MissingFileError) => Ok(Data::new(name.as_ref(), T::default())),
+            v => v,
+        }
+    }
+    /// Charge la donnée `name` propre au serveur `guild_id`.
+    ///
+    /// Avec le support RON, la donnée est enregistrée dans `data/<guild_id>/<name>.ron`.
+    /// Si le fichier n'existe pas, une nouvelle donnée est créée avec [`Default::default()`].
+    pub fn for_guild<S: AsRef<str>>(name: S, guild_id: GuildId) -> DataResult<Data<T>> {
+        Self::load_or_default(name.as_ref(), Some(guild_id), &Schema::default())
+    }
+    /// Charge une donnée selon le modèle `schema`.
+    ///
+    /// Si la donnée n'existe pas, une nouvelle donnée est créée avec [`Default::default()`].
+    fn load_or_default(
+        name: &str,
+        guild_id: Option<GuildId>,
+        schema: &Schema,
+    ) -> DataResult<Data<T>> {
+        match Self::load(storage(), name, guild_id, schema) {
+            Err(DataError::MissingFileError) => Ok(Data {
+                guild_id,
+                version: schema.version(),
+                ..Data::new(name, T::default())
+            }),
+            v => v,
+        }
+    }
+}
+
+/// Données d'un composant séparées par serveur.
+///
+/// Chaque serveur possède sa propre [`Data`], chargée à la première utilisation via [`Data::for_guild`].
+/// Les données d'un serveur sont partagées par un [`RwLock`] qu'il suffit de cloner pour le transmettre à une tâche.
+pub struct GuildData<T>
+where
+    T: DeserializeOwned + Serialize,
+{
+    name: String,
+    schema: Arc<Schema>,
+    guilds: RwLock<HashMap<GuildId, RwLock<Data<T>>>>,
+}
+impl<T> Debug for GuildData<T>
+where
+    T: DeserializeOwned + Serialize,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "GuildData {{ name: {} }}", self.name)
+    }
+}
+
+impl<T> GuildData<T>
+where
+    T: DeserializeOwned + Serialize + Default,
+{
+    pub fn new<S: Into<String>>(name: S) -> GuildData<T> {
+        GuildData {
+            name: name.into(),
+            schema: Arc::new(Schema::default()),
+            guilds: RwLock::new(HashMap::new()),
+        }
+    }
+    /// Définit le modèle des données, pour migrer les données enregistrées avec une ancienne version.
+    pub fn set_schema(mut self, schema: Schema) -> GuildData<T> {
+        self.schema = Arc::new(schema);
+        self
+    }
+    /// Retourne la donnée du serveur `guild_id`, chargée depuis le disque si nécessaire.
+    pub async fn get(&self, guild_id: GuildId) -> DataResult<RwLock<Data<T>>> {
+        if let Some(data) = self.guilds.read().await.get(&guild_id) {
+            return Ok(data.clone());
+        }
+        let mut guilds = self.guilds.write().await;
+        if let Some(data) = guilds.get(&guild_id) {
+            return Ok(data.clone());
+        }
+        let data = RwLock::new(Data::load_or_default(
+            &self.name,
+            Some(guild_id),
+            &self.schema,
+        )?);
+        guilds.insert(guild_id, data.clone());
+        Ok(data)
+    }
+    /// Migre l'ancienne donnée commune à tout le bot (`data/<name>.ron`) vers la donnée du serveur.
+    ///
+    /// La migration n'est faite que si le bot est présent sur un seul serveur, sans quoi il est impossible
+    /// de savoir à quel serveur appartient la donnée. L'ancienne donnée est alors conservée et doit être déplacée à la main.
+    pub fn migrate_legacy(&self, guilds: &[GuildId]) -> DataResult<()> {
+        let storage = storage();
+        let legacy_key = DataKey::new(&self.name, None);
+        let content = match storage.load(&legacy_key)? {
+            Some(content) => content,
+            None => return Ok(()),
+        };
+        let guild_id = match guilds {
+            [guild_id] => *guild_id,
+            _ => {
+                log::warn!(
+                    target: "data",
+                    "{} - Le bot est présent sur {} serveurs, impossible de migrer la donnée automatiquement. Déplacez le fichier dans data/<id serveur>/.",
+                    self.name,
+                    guilds.len()
+                );
+                return Ok(());
+            }
+        };
+        let guild_key = DataKey::new(&self.name, Some(guild_id));
+        if storage.load(&guild_key)?.is_some() {
+            log::warn!(
+                target: "data",
+                "{} - {} existe déjà, l'ancienne donnée est conservée.",
+                self.name, guild_key
+            );
+            return Ok(());
+        }
+        storage.save(&guild_key, &content)?;
+        storage.remove(&legacy_key)?;
+        log::info!(target: "data", "{} migré vers le serveur {}", self.name, guild_id);
+        Ok(())
+    }
+}
+
+/// Gère l'enregistrement des données d'un composant.
+///
+/// Dès que le [`DataGuard`] est détruit, les données sont enregistrées dans le support de stockage,
+/// ou mises en attente si l'[enregistrement différé](write_behind) est activé.
+pub struct DataGuard<'a, T>(&'a mut Data<T>)
+where
+    T: Serialize + DeserializeOwned;
+
+impl<T> Deref for DataGuard<'_, T>
+where
+    T: DeserializeOwned + Serialize,
+{
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.0.value
+    }
+}
+impl<T> DerefMut for DataGuard<'_, T>
+where
+    T: DeserializeOwned + Serialize,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0.value
+    }
+}
+
+impl<T> Drop for DataGuard<'_, T>
+where
+    T: DeserializeOwned + Serialize,
+{
+    fn drop(&mut self) {
+        let ron_content = match schema::encode(self.0.version, &self.0.value) {
+            Ok(content) => content,
+            Err(err) => {
+                log::error!(
+                    target: "data",
+                    "Saving {} - Unable to serialize the data: {}",
+                    self.0.name, err
+                );
+                return;
+            }
+        };
+        let key = self.0.key();
+        if write_behind::defer(&key, &self.0.storage, &ron_content) {
+            return;
+        }
+        if let Err(err) = self.0.storage.save(&key, &ron_content) {
+            log::error!(target: "data", "Saving {} - Unable to write the data: {}", self.0.name, err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::storage::tests::TempDir;
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+    struct Old {
+        name: String,
+    }
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+    struct New {
+        names: Vec<String>,
+    }
+
+    fn schema() -> Schema {
+        Schema::new(1).add_migration(0, |old: Old| New {
+            names: vec![old.name],
+        })
+    }
+
+    fn load(storage: &Arc<dyn Storage>) -> DataResult<Data<New>> {
+        Data::load(storage.clone(), "test", Some(GuildId(1)), &schema())
+    }
+
+    #[test]
+    fn migrates_older_version() {
+        let dir = TempDir::new("migrate");
+        let storage: Arc<dyn Storage> = Arc::new(RonStorage::new(dir.path(), 2));
+        let key = DataKey::new("test", Some(GuildId(1)));
+        storage.save(&key, r#"(name: "bot")"#).unwrap();
+
+        let data = load(&storage).unwrap();
+        assert_eq!(data.value.names, ["bot"]);
+        assert_eq!(data.version, 1);
+    }
+
+    #[test]
+    fn falls_back_to_latest_valid_backup() {
+        let dir = TempDir::new("backup");
+        let storage: Arc<dyn Storage> = Arc::new(RonStorage::new(dir.path(), 3));
+        let key = DataKey::new("test", Some(GuildId(1)));
+        // Sauvegarde n°3 : ancienne version, n°2 : version actuelle, n°1 : illisible
+        storage.save(&key, r#"(name: "old")"#).unwrap();
+        let current = New {
+            names: vec!["current".to_string()],
+        };
+        storage
+            .save(&key, &schema::encode(1, &current).unwrap())
+            .unwrap();
+        storage.save(&key, "(names: [").unwrap();
+        storage.save(&key, "corrompu").unwrap();
+
+        assert_eq!(load(&storage).unwrap().value, current);
+    }
+
+    #[test]
+    fn migrates_older_backup() {
+        let dir = TempDir::new("backup_migrate");
+        let storage: Arc<dyn Storage> = Arc::new(RonStorage::new(dir.path(), 2));
+        let key = DataKey::new("test", Some(GuildId(1)));
+        storage.save(&key, r#"(name: "old")"#).unwrap();
+        storage.save(&key, "corrompu").unwrap();
+
+        assert_eq!(load(&storage).unwrap().value.names, ["old"]);
+    }
+
+    /// Enregistre une donnée après le chargement d'une sauvegarde : les sauvegardes valides sont conservées
+    fn save_after_fallback(storage: Arc<dyn Storage>) {
+        let key = DataKey::new("test", Some(GuildId(1)));
+        let version = |name: &str| {
+            schema::encode(
+                1,
+                &New {
+                    names: vec![name.to_string()],
+                },
+            )
+            .unwrap()
+        };
+        storage.save(&key, &version("a")).unwrap();
+        storage.save(&key, &version("b")).unwrap();
+        storage.save(&key, "corrompu").unwrap();
+
+        let mut data = load(&storage).unwrap();
+        assert_eq!(data.value.names, ["b"]);
+        // Rechargée avant d'être enregistrée, la donnée mise de côté n'est pas remplacée par la valeur par défaut
+        assert_eq!(load(&storage).unwrap().value.names, ["b"]);
+        data.write().names.push("c".to_string());
+
+        assert_eq!(load(&storage).unwrap().value.names, ["b", "c"]);
+        assert_eq!(
+            storage.load_backups(&key).unwrap(),
+            [version("b"), version("a")]
+        );
+    }
+
+    #[test]
+    fn ron_save_after_fallback() {
+        let dir = TempDir::new("fallback_ron");
+        save_after_fallback(Arc::new(RonStorage::new(dir.path(), 2)));
+        assert!(dir.path().join("1").join("test.ron.corrupt").exists());
+    }
+
+    #[test]
+    fn sqlite_save_after_fallback() {
+        let dir = TempDir::new("fallback_sqlite");
+        let path = dir.path().join("data.sqlite");
+        save_after_fallback(Arc::new(storage::SqliteStorage::open(path, 2).unwrap()));
+    }
+
+    #[test]
+    fn keeps_newer_version() {
+        let dir = TempDir::new("newer");
+        let storage: Arc<dyn Storage> = Arc::new(RonStorage::new(dir.path(), 2));
+        let key = DataKey::new("test", Some(GuildId(1)));
+        storage
+            .save(&key, &schema::encode(1, &New::default()).unwrap())
+            .unwrap();
+        storage
+            .save(&key, &schema::encode(2, &New::default()).unwrap())
+            .unwrap();
+
+        assert!(matches!(load(&storage), Err(VersionError(2))));
+    }
+}
@@ -0,0 +1,210 @@
+//! Version du modèle de données et migrations.
+//!
+//! Chaque donnée est enregistrée avec la version de son modèle :
+//!
+//! ```ron
+//! (
+//!     version: 1,
+//!     value: (...),
+//! )
+//! ```
+//!
+//! Les données enregistrées avant l'ajout des versions sont considérées en version 0.
+//! Lorsque le modèle d'une donnée change, le composant incrémente la version de son [`Schema`]
+//! et ajoute la migration depuis l'ancienne version.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::{DataError::*, DataResult};
+
+/// Migration d'une donnée vers la version suivante.
+///
+/// Reçoit le contenu enregistré (avec ou sans l'en-tête de version) et retourne le contenu migré, sans en-tête.
+type MigrationFn = Box<dyn Fn(&str, bool) -> DataResult<String> + Send + Sync>;
+
+/// En-tête d'une donnée versionnée, pour lire sa version sans connaitre son modèle.
+#[derive(Deserialize)]
+struct Header {
+    version: u32,
+}
+/// Donnée versionnée
+#[derive(Serialize, Deserialize)]
+struct Versioned<T> {
+    version: u32,
+    value: T,
+}
+
+/// Modèle d'une donnée : sa version actuelle et les migrations depuis les versions précédentes.
+#[derive(Default)]
+pub struct Schema {
+    version: u32,
+    migrations: HashMap<u32, MigrationFn>,
+}
+
+impl Schema {
+    pub fn new(version: u32) -> Schema {
+        Schema {
+            version,
+            migrations: HashMap::new(),
+        }
+    }
+    /// Version actuelle du modèle
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+    /// Ajoute la migration de la version `from` vers la version `from + 1`.
+    ///
+    /// `Old` est le modèle de la version `from` et `New` celui de la version suivante.
+    pub fn add_migration<Old, New>(mut self, from: u32, migration: fn(Old) -> New) -> Schema
+    where
+        Old: DeserializeOwned + 'static,
+        New: Serialize + 'static,
+    {
+        self.migrations.insert(
+            from,
+            Box::new(move |content, versioned| {
+                let old = if versioned {
+                    ron::from_str::<Versioned<Old>>(content)
+                        .map_err(SerdeError)?
+                        .value
+                } else {
+                    ron::from_str::<Old>(content).map_err(SerdeError)?
+                };
+                ron::to_string(&migration(old)).map_err(SerdeError)
+            }),
+        );
+        self
+    }
+    /// Lit le contenu d'une donnée, en la migrant vers la version actuelle si nécessaire.
+    pub(super) fn decode<T: DeserializeOwned>(&self, content: &str) -> DataResult<T> {
+        let header = ron::from_str::<Header>(content).ok();
+        let mut versioned = header.is_some();
+        let mut version = header.map_or(0, |header| header.version);
+        if version > self.version {
+            return Err(VersionError(version));
+        }
+        let mut content = Cow::Borrowed(content);
+        while version < self.version {
+            let migration = self
+                .migrations
+                .get(&version)
+                .ok_or(MigrationError(version))?;
+            content = Cow::Owned(migration(&content, versioned)?);
+            versioned = false;
+            version += 1;
+        }
+        if versioned {
+            Ok(ron::from_str::<Versioned<T>>(&content)
+                .map_err(SerdeError)?
+                .value)
+        } else {
+            ron::from_str(&content).map_err(SerdeError)
+        }
+    }
+}
+
+/// Sérialise une donnée avec la version de son modèle.
+pub(super) fn encode<T: Serialize>(version: u32, value: &T) -> ron::Result<String> {
+    ron::ser::to_string_pretty(
+        &Versioned { version, value },
+        ron::ser::PrettyConfig::default(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct V0 {
+        name: String,
+    }
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct V1 {
+        name: String,
+        count: u32,
+    }
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct V2 {
+        names: Vec<String>,
+        count: u32,
+    }
+
+    fn schema() -> Schema {
+        Schema::new(2)
+            .add_migration(0, |old: V0| V1 {
+                name: old.name,
+                count: 0,
+            })
+            .add_migration(1, |old: V1| V2 {
+                names: vec![old.name],
+                count: old.count,
+            })
+    }
+
+    #[test]
+    fn migrates_unversioned_data() {
+        let data = schema().decode::<V2>(r#"(name: "bot")"#).unwrap();
+        assert_eq!(
+            data,
+            V2 {
+                names: vec!["bot".to_string()],
+                count: 0
+            }
+        );
+    }
+
+    #[test]
+    fn migrates_from_intermediate_version() {
+        let content = encode(
+            1,
+            &V1 {
+                name: "bot".to_string(),
+                count: 3,
+            },
+        )
+        .unwrap();
+        let data = schema().decode::<V2>(&content).unwrap();
+        assert_eq!(data.names, ["bot"]);
+        assert_eq!(data.count, 3);
+    }
+
+    #[test]
+    fn reads_current_version() {
+        let value = V2 {
+            names: vec!["a".to_string(), "b".to_string()],
+            count: 1,
+        };
+        let content = encode(2, &value).unwrap();
+        assert_eq!(schema().decode::<V2>(&content).unwrap(), value);
+    }
+
+    #[test]
+    fn rejects_newer_version_and_missing_migration() {
+        let content = encode(
+            3,
+            &V0 {
+                name: "bot".to_string(),
+            },
+        )
+        .unwrap();
+        assert!(matches!(
+            schema().decode::<V2>(&content),
+            Err(VersionError(3))
+        ));
+        let content = encode(
+            0,
+            &V0 {
+                name: "bot".to_string(),
+            },
+        )
+        .unwrap();
+        assert!(matches!(
+            Schema::new(1).decode::<V1>(&content),
+            Err(MigrationError(0))
+        ));
+    }
+}
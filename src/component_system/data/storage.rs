@@ -0,0 +1,508 @@
+//! Supports de stockage des données des composants.
+//!
+//! Les données sont toujours sérialisées au format RON, le support ne fait que conserver le texte.
+//! Deux supports sont disponibles : des fichiers RON dans le dossier [`DATA_DIR`] et une base SQLite embarquée.
+//! Le support utilisé est choisi dans le fichier de configuration via [`StorageConfig`].
+
+use std::fmt::Display;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use rusqlite::OptionalExtension;
+use serde::{Deserialize, Serialize};
+use serenity::model::id::GuildId;
+
+use super::{DataError::*, DataResult, DATA_DIR};
+
+/// Identifiant d'une donnée dans un support de stockage.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DataKey {
+    /// Nom de la donnée
+    pub name: String,
+    /// Serveur auquel appartient la donnée. Commune à tout le bot si None.
+    pub guild_id: Option<GuildId>,
+}
+impl DataKey {
+    pub fn new<S: Into<String>>(name: S, guild_id: Option<GuildId>) -> DataKey {
+        DataKey {
+            name: name.into(),
+            guild_id,
+        }
+    }
+}
+impl Display for DataKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.guild_id {
+            Some(guild_id) => write!(f, "{}/{}", guild_id, self.name),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// Support de stockage des données.
+///
+/// Un support conserve le contenu sérialisé de chaque donnée, identifiée par sa [`DataKey`].
+pub trait Storage: Send + Sync {
+    /// Nom du support, utilisé dans les messages
+    fn name(&self) -> &'static str;
+    /// Charge le contenu de la donnée. Retourne None si la donnée n'existe pas.
+    fn load(&self, key: &DataKey) -> DataResult<Option<String>>;
+    /// Enregistre le contenu de la donnée, en remplaçant l'ancien s'il existe.
+    ///
+    /// L'ancien contenu est conservé dans les sauvegardes de la donnée.
+    /// L'écriture doit être atomique : en cas d'arrêt brutal, l'ancien contenu doit rester intact.
+    fn save(&self, key: &DataKey, content: &str) -> DataResult<()>;
+    /// Charge les sauvegardes de la donnée, de la plus récente à la plus ancienne.
+    fn load_backups(&self, key: &DataKey) -> DataResult<Vec<String>>;
+    /// Met de côté le contenu illisible de la donnée, pour que le prochain enregistrement ne le place pas
+    /// dans les sauvegardes à la place d'une sauvegarde valide.
+    ///
+    /// Le contenu est conservé à part pour pouvoir être inspecté, en remplaçant un éventuel contenu déjà mis de côté.
+    /// Les sauvegardes ne sont pas modifiées. Ne fait rien si la donnée n'existe pas.
+    fn set_aside(&self, key: &DataKey) -> DataResult<()>;
+    /// Supprime la donnée et ses sauvegardes. Ne fait rien si la donnée n'existe pas.
+    fn remove(&self, key: &DataKey) -> DataResult<()>;
+    /// Liste toutes les données présentes dans le support.
+    fn keys(&self) -> DataResult<Vec<DataKey>>;
+}
+
+/// Support de stockage par fichiers RON.
+///
+/// Chaque donnée est un fichier `<name>.ron`, placé dans le dossier du serveur (`<dir>/<guild_id>/<name>.ron`) s'il y a lieu.
+/// Les sauvegardes sont placées à côté : `<name>.ron.bak.1` est la plus récente.
+/// Un fichier illisible est mis de côté dans `<name>.ron.corrupt`.
+pub struct RonStorage {
+    dir: PathBuf,
+    /// Nombre de sauvegardes conservées par donnée
+    backups: usize,
+}
+impl RonStorage {
+    pub fn new<P: Into<PathBuf>>(dir: P, backups: usize) -> RonStorage {
+        RonStorage {
+            dir: dir.into(),
+            backups,
+        }
+    }
+    /// Retourne le chemin du fichier de la donnée.
+    fn path(&self, key: &DataKey) -> PathBuf {
+        let filename = format!("{}.ron", key.name);
+        match key.guild_id {
+            Some(guild_id) => self.dir.join(guild_id.0.to_string()).join(filename),
+            None => self.dir.join(filename),
+        }
+    }
+    /// Retourne le chemin de la sauvegarde n°`index` de la donnée.
+    fn backup_path(&self, key: &DataKey, index: usize) -> PathBuf {
+        let mut path = self.path(key).into_os_string();
+        path.push(format!(".bak.{}", index));
+        path.into()
+    }
+    /// Décale les sauvegardes puis copie le fichier actuel dans la sauvegarde la plus récente.
+    fn rotate_backups(&self, key: &DataKey, path: &Path) -> std::io::Result<()> {
+        for index in (1..self.backups).rev() {
+            let backup = self.backup_path(key, index);
+            if backup.exists() {
+                fs::rename(backup, self.backup_path(key, index + 1))?;
+            }
+        }
+        fs::copy(path, self.backup_path(key, 1)).map(|_| ())
+    }
+    /// Liste les fichiers RON du dossier `dir`.
+    fn ron_files(dir: &Path) -> DataResult<Vec<String>> {
+        let mut names = Vec::new();
+        for entry in fs::read_dir(dir).map_err(FileError)? {
+            let path = entry.map_err(FileError)?.path();
+            if !path.is_file() || path.extension().is_none_or(|ext| ext != "ron") {
+                continue;
+            }
+            if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+                names.push(name.to_string());
+            }
+        }
+        Ok(names)
+    }
+}
+impl Storage for RonStorage {
+    fn name(&self) -> &'static str {
+        "ron"
+    }
+    fn load(&self, key: &DataKey) -> DataResult<Option<String>> {
+        let path = self.path(key);
+        if !path.exists() {
+            return Ok(None);
+        }
+        fs::read_to_string(path).map(Some).map_err(FileError)
+    }
+    fn save(&self, key: &DataKey, content: &str) -> DataResult<()> {
+        let path = self.path(key);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(FileError)?;
+        }
+        if self.backups > 0 && path.exists() {
+            self.rotate_backups(key, &path).map_err(FileError)?;
+        }
+        // Le contenu est écrit dans un fichier temporaire puis renommé,
+        // le fichier de données n'est donc jamais à moitié écrit.
+        let tmp_path = path.with_extension("ron.tmp");
+        let mut file = fs::File::create(&tmp_path).map_err(FileError)?;
+        file.write_all(content.as_bytes()).map_err(FileError)?;
+        file.sync_all().map_err(FileError)?;
+        fs::rename(tmp_path, path).map_err(FileError)
+    }
+    fn load_backups(&self, key: &DataKey) -> DataResult<Vec<String>> {
+        let mut backups = Vec::new();
+        for index in 1..=self.backups {
+            let path = self.backup_path(key, index);
+            if path.exists() {
+                backups.push(fs::read_to_string(path).map_err(FileError)?);
+            }
+        }
+        Ok(backups)
+    }
+    fn set_aside(&self, key: &DataKey) -> DataResult<()> {
+        let path = self.path(key);
+        if !path.exists() {
+            return Ok(());
+        }
+        fs::rename(&path, path.with_extension("ron.corrupt")).map_err(FileError)
+    }
+    fn remove(&self, key: &DataKey) -> DataResult<()> {
+        for index in 1..=self.backups {
+            let backup = self.backup_path(key, index);
+            if backup.exists() {
+                fs::remove_file(backup).map_err(FileError)?;
+            }
+        }
+        let path = self.path(key);
+        if !path.exists() {
+            return Ok(());
+        }
+        fs::remove_file(path).map_err(FileError)
+    }
+    fn keys(&self) -> DataResult<Vec<DataKey>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut keys = Self::ron_files(&self.dir)?
+            .into_iter()
+            .map(|name| DataKey::new(name, None))
+            .collect::<Vec<_>>();
+        // Les dossiers des serveurs sont nommés par leur identifiant, les autres dossiers sont ignorés.
+        for entry in fs::read_dir(&self.dir).map_err(FileError)? {
+            let path = entry.map_err(FileError)?.path();
+            let guild_id = match path.file_name().and_then(|name| name.to_str()) {
+                Some(name) if path.is_dir() => match name.parse::<u64>() {
+                    Ok(id) => GuildId(id),
+                    Err(_) => continue,
+                },
+                _ => continue,
+            };
+            keys.extend(
+                Self::ron_files(&path)?
+                    .into_iter()
+                    .map(|name| DataKey::new(name, Some(guild_id))),
+            );
+        }
+        Ok(keys)
+    }
+}
+
+/// Support de stockage dans une base SQLite embarquée.
+///
+/// Toutes les données sont enregistrées dans une même table `data`, leurs sauvegardes dans la table `data_backups`
+/// et les données illisibles mises de côté dans la table `data_corrupt`.
+/// Les données communes à tout le bot ont pour identifiant de serveur 0.
+pub struct SqliteStorage {
+    conn: Mutex<rusqlite::Connection>,
+    /// Nombre de sauvegardes conservées par donnée
+    backups: usize,
+}
+impl SqliteStorage {
+    /// Ouvre la base SQLite, en la créant si elle n'existe pas.
+    pub fn open<P: AsRef<Path>>(path: P, backups: usize) -> DataResult<SqliteStorage> {
+        if let Some(parent) = path.as_ref().parent() {
+            fs::create_dir_all(parent).map_err(FileError)?;
+        }
+        let conn = rusqlite::Connection::open(path).map_err(SqliteError)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS data (
+                name TEXT NOT NULL,
+                guild_id INTEGER NOT NULL,
+                value TEXT NOT NULL,
+                PRIMARY KEY (name, guild_id)
+            );
+            CREATE TABLE IF NOT EXISTS data_backups (
+                name TEXT NOT NULL,
+                guild_id INTEGER NOT NULL,
+                generation INTEGER NOT NULL,
+                value TEXT NOT NULL,
+                PRIMARY KEY (name, guild_id, generation)
+            );
+            CREATE TABLE IF NOT EXISTS data_corrupt (
+                name TEXT NOT NULL,
+                guild_id INTEGER NOT NULL,
+                value TEXT NOT NULL,
+                PRIMARY KEY (name, guild_id)
+            );",
+        )
+        .map_err(SqliteError)?;
+        Ok(SqliteStorage {
+            conn: Mutex::new(conn),
+            backups,
+        })
+    }
+    fn guild_column(key: &DataKey) -> i64 {
+        key.guild_id.map_or(0, |guild_id| guild_id.0 as i64)
+    }
+}
+impl Storage for SqliteStorage {
+    fn name(&self) -> &'static str {
+        "sqlite"
+    }
+    fn load(&self, key: &DataKey) -> DataResult<Option<String>> {
+        self.conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT value FROM data WHERE name = ?1 AND guild_id = ?2",
+                rusqlite::params![key.name, Self::guild_column(key)],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(SqliteError)
+    }
+    fn save(&self, key: &DataKey, content: &str) -> DataResult<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction().map_err(SqliteError)?;
+        let guild_id = Self::guild_column(key);
+        if self.backups > 0 {
+            tx.execute(
+                "INSERT INTO data_backups (name, guild_id, generation, value)
+                SELECT name, guild_id, (
+                    SELECT IFNULL(MAX(generation), 0) + 1 FROM data_backups WHERE name = ?1 AND guild_id = ?2
+                ), value FROM data WHERE name = ?1 AND guild_id = ?2",
+                rusqlite::params![key.name, guild_id],
+            )
+            .map_err(SqliteError)?;
+            tx.execute(
+                "DELETE FROM data_backups WHERE name = ?1 AND guild_id = ?2 AND generation <= (
+                    SELECT MAX(generation) FROM data_backups WHERE name = ?1 AND guild_id = ?2
+                ) - ?3",
+                rusqlite::params![key.name, guild_id, self.backups as i64],
+            )
+            .map_err(SqliteError)?;
+        }
+        tx.execute(
+            "INSERT INTO data (name, guild_id, value) VALUES (?1, ?2, ?3)
+            ON CONFLICT (name, guild_id) DO UPDATE SET value = excluded.value",
+            rusqlite::params![key.name, guild_id, content],
+        )
+        .map_err(SqliteError)?;
+        tx.commit().map_err(SqliteError)
+    }
+    fn load_backups(&self, key: &DataKey) -> DataResult<Vec<String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare(
+                "SELECT value FROM data_backups WHERE name = ?1 AND guild_id = ?2
+                ORDER BY generation DESC LIMIT ?3",
+            )
+            .map_err(SqliteError)?;
+        let rows = stmt
+            .query_map(
+                rusqlite::params![key.name, Self::guild_column(key), self.backups as i64],
+                |row| row.get(0),
+            )
+            .map_err(SqliteError)?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(SqliteError)
+    }
+    fn set_aside(&self, key: &DataKey) -> DataResult<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction().map_err(SqliteError)?;
+        let params = rusqlite::params![key.name, Self::guild_column(key)];
+        tx.execute(
+            "INSERT OR REPLACE INTO data_corrupt (name, guild_id, value)
+            SELECT name, guild_id, value FROM data WHERE name = ?1 AND guild_id = ?2",
+            params,
+        )
+        .map_err(SqliteError)?;
+        tx.execute("DELETE FROM data WHERE name = ?1 AND guild_id = ?2", params)
+            .map_err(SqliteError)?;
+        tx.commit().map_err(SqliteError)
+    }
+    fn remove(&self, key: &DataKey) -> DataResult<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction().map_err(SqliteError)?;
+        for table in ["data", "data_backups"] {
+            tx.execute(
+                &format!("DELETE FROM {} WHERE name = ?1 AND guild_id = ?2", table),
+                rusqlite::params![key.name, Self::guild_column(key)],
+            )
+            .map_err(SqliteError)?;
+        }
+        tx.commit().map_err(SqliteError)
+    }
+    fn keys(&self) -> DataResult<Vec<DataKey>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare("SELECT name, guild_id FROM data")
+            .map_err(SqliteError)?;
+        let rows = stmt
+            .query_map([], |row| {
+                let guild_id: i64 = row.get(1)?;
+                Ok(DataKey::new(
+                    row.get::<_, String>(0)?,
+                    if guild_id == 0 {
+                        None
+                    } else {
+                        Some(GuildId(guild_id as u64))
+                    },
+                ))
+            })
+            .map_err(SqliteError)?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(SqliteError)
+    }
+}
+
+/// Choix du support de stockage dans le fichier de configuration.
+///
+/// ```ron
+/// storage: Ron,
+/// // ou
+/// storage: Sqlite("data/data.sqlite"),
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum StorageConfig {
+    /// Fichiers RON dans le dossier [`DATA_DIR`]
+    #[default]
+    Ron,
+    /// Base SQLite au chemin indiqué
+    Sqlite(PathBuf),
+}
+impl StorageConfig {
+    /// Ouvre le support de stockage configuré, en conservant `backups` sauvegardes par donnée.
+    pub fn open(&self, backups: usize) -> DataResult<Arc<dyn Storage>> {
+        Ok(match self {
+            StorageConfig::Ron => Arc::new(RonStorage::new(DATA_DIR.as_path(), backups)),
+            StorageConfig::Sqlite(path) => Arc::new(SqliteStorage::open(path, backups)?),
+        })
+    }
+}
+
+/// Copie toutes les données du support `from` vers le support `to`.
+///
+/// Les données déjà présentes dans `to` sont écrasées. Retourne le nombre de données copiées.
+pub fn copy_all(from: &dyn Storage, to: &dyn Storage) -> DataResult<usize> {
+    let mut count = 0;
+    for key in from.keys()? {
+        if let Some(content) = from.load(&key)? {
+            to.save(&key, &content)?;
+            log::info!(target: "data", "{} copié ({} -> {})", key, from.name(), to.name());
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Dossier temporaire supprimé à la fin du test
+    pub struct TempDir(PathBuf);
+    impl TempDir {
+        pub fn new(name: &str) -> TempDir {
+            static COUNTER: AtomicUsize = AtomicUsize::new(0);
+            let dir = std::env::temp_dir().join(format!(
+                "open_cdd-{}-{}-{}",
+                name,
+                std::process::id(),
+                COUNTER.fetch_add(1, Ordering::Relaxed)
+            ));
+            fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+        pub fn path(&self) -> &Path {
+            &self.0
+        }
+    }
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn sorted_keys(storage: &dyn Storage) -> Vec<String> {
+        let mut keys = storage
+            .keys()
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        keys.sort();
+        keys
+    }
+
+    /// Écrit, relit, liste puis supprime des données, et vérifie la rotation des sauvegardes
+    fn round_trip(storage: &dyn Storage) {
+        let common = DataKey::new("config", None);
+        let guild = DataKey::new("tickets", Some(GuildId(42)));
+        assert_eq!(storage.load(&common).unwrap(), None);
+
+        storage.save(&common, "(a: 1)").unwrap();
+        storage.save(&guild, "(b: 1)").unwrap();
+        storage.save(&guild, "(b: 2)").unwrap();
+        storage.save(&guild, "(b: 3)").unwrap();
+        assert_eq!(storage.load(&common).unwrap().as_deref(), Some("(a: 1)"));
+        assert_eq!(storage.load(&guild).unwrap().as_deref(), Some("(b: 3)"));
+        assert_eq!(storage.load_backups(&guild).unwrap(), ["(b: 2)", "(b: 1)"]);
+        assert_eq!(sorted_keys(storage), ["42/tickets", "config"]);
+
+        storage.remove(&guild).unwrap();
+        assert_eq!(storage.load(&guild).unwrap(), None);
+        assert!(storage.load_backups(&guild).unwrap().is_empty());
+        assert_eq!(sorted_keys(storage), ["config"]);
+    }
+
+    #[test]
+    fn ron_round_trip() {
+        let dir = TempDir::new("ron");
+        round_trip(&RonStorage::new(dir.path(), 2));
+    }
+
+    #[test]
+    fn sqlite_round_trip() {
+        let dir = TempDir::new("sqlite");
+        round_trip(&SqliteStorage::open(dir.path().join("data.sqlite"), 2).unwrap());
+    }
+
+    #[test]
+    fn copy_ron_to_sqlite_and_back() {
+        let dir = TempDir::new("copy");
+        let ron = RonStorage::new(dir.path().join("ron"), 0);
+        let sqlite = SqliteStorage::open(dir.path().join("data.sqlite"), 0).unwrap();
+        let keys = [
+            DataKey::new("config", None),
+            DataKey::new("tickets", Some(GuildId(1))),
+            DataKey::new("moderation", Some(GuildId(2))),
+        ];
+        for (index, key) in keys.iter().enumerate() {
+            ron.save(key, &format!("({})", index)).unwrap();
+        }
+
+        assert_eq!(copy_all(&ron, &sqlite).unwrap(), keys.len());
+        assert_eq!(sorted_keys(&sqlite), sorted_keys(&ron));
+        for (index, key) in keys.iter().enumerate() {
+            assert_eq!(sqlite.load(key).unwrap(), Some(format!("({})", index)));
+        }
+
+        let back = RonStorage::new(dir.path().join("back"), 0);
+        assert_eq!(copy_all(&sqlite, &back).unwrap(), keys.len());
+        for key in &keys {
+            assert_eq!(back.load(key).unwrap(), ron.load(key).unwrap());
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! Enregistrement différé des données.
+//!
+//! Lorsqu'il est activé, l'enregistrement d'une donnée n'écrit plus directement dans le support de stockage :
+//! la donnée est marquée comme modifiée et une tâche de fond l'enregistre au plus tard après l'intervalle configuré.
+//! Plusieurs modifications d'une même donnée pendant cet intervalle ne donnent lieu qu'à une seule écriture.
+//!
+//! Les écritures sont faites sur un thread dédié aux tâches bloquantes pour ne pas bloquer le runtime.
+//! Pensez à appeler [`flush`] à l'arrêt du bot pour enregistrer les modifications en attente.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+
+use super::{DataKey, Storage};
+
+/// Modifications en attente d'enregistrement : le support et le contenu à écrire pour chaque donnée.
+type Pending = HashMap<DataKey, (Arc<dyn Storage>, String)>;
+
+lazy_static! {
+    /// Modifications en attente. None si l'enregistrement différé n'est pas activé.
+    static ref PENDING: Mutex<Option<Pending>> = Mutex::new(None);
+    /// Empêche deux enregistrements simultanés, qui pourraient écrire une ancienne version après une plus récente.
+    static ref FLUSHING: tokio::sync::Mutex<()> = tokio::sync::Mutex::new(());
+}
+
+/// Active l'enregistrement différé : les données modifiées sont enregistrées au plus toutes les `interval`.
+///
+/// Doit être appelé depuis le runtime tokio.
+pub fn start(interval: Duration) {
+    {
+        let mut pending = PENDING.lock().unwrap();
+        if pending.is_some() {
+            return;
+        }
+        *pending = Some(HashMap::new());
+    }
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            flush().await;
+        }
+    });
+}
+
+/// Met l'enregistrement de la donnée en attente.
+///
+/// Retourne faux si l'enregistrement différé n'est pas activé, la donnée doit alors être enregistrée immédiatement.
+pub(super) fn defer(key: &DataKey, storage: &Arc<dyn Storage>, content: &str) -> bool {
+    match PENDING.lock().unwrap().as_mut() {
+        Some(pending) => {
+            pending.insert(key.clone(), (Arc::clone(storage), content.to_string()));
+            true
+        }
+        None => false,
+    }
+}
+
+/// Enregistre toutes les modifications en attente.
+///
+/// Une donnée dont l'enregistrement échoue est remise en attente, sauf si elle a été modifiée entre temps.
+pub async fn flush() {
+    let _flushing = FLUSHING.lock().await;
+    let pending = match PENDING.lock().unwrap().as_mut() {
+        Some(pending) if !pending.is_empty() => std::mem::take(pending),
+        _ => return,
+    };
+    let failed = tokio::task::spawn_blocking(move || {
+        pending
+            .into_iter()
+            .filter(
+                |(key, (storage, content))| match storage.save(key, content) {
+                    Ok(_) => false,
+                    Err(err) => {
+                        log::error!(target: "data", "Saving {} - Unable to write the data: {}", key, err);
+                        true
+                    }
+                },
+            )
+            .collect::<Pending>()
+    })
+    .await;
+    match failed {
+        Ok(failed) => {
+            if let Some(pending) = PENDING.lock().unwrap().as_mut() {
+                for (key, value) in failed {
+                    pending.entry(key).or_insert(value);
+                }
+            }
+        }
+        Err(err) => log::error!(target: "data", "La tâche d'enregistrement a échoué: {}", err),
+    }
+}
@@ -1,40 +1,259 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bitflags::bitflags;
 use serenity::async_trait;
 use serenity::client::{Context, RawEventHandler};
 pub use serenity::model::event::Event;
+use serenity::model::{event::InteractionCreateEvent, id::GuildId, interactions::Interaction};
+
+use super::manager::{ArcManager, Manager};
+use super::ArcComponent;
+use crate::metrics;
+
+/// Durée au delà de laquelle le traitement d'un évènement par un composant est signalé comme lent
+const SLOW_EVENT: Duration = Duration::from_secs(3);
+/// Durée maximale du traitement d'un évènement par un composant
+const EVENT_TIMEOUT: Duration = Duration::from_secs(120);
+
+bitflags! {
+    /// Types d'évènements auxquels un composant peut s'abonner.
+    ///
+    /// Voir [`Component::subscriptions`](super::Component::subscriptions).
+    pub struct EventKinds: u32 {
+        /// Connexion et reconnexion du bot
+        const READY = 1 << 0;
+        /// Serveurs (création, mise à jour, suppression, intégrations, invitations...)
+        const GUILD = 1 << 1;
+        /// Membres et bannissements
+        const MEMBER = 1 << 2;
+        /// Rôles
+        const ROLE = 1 << 3;
+        /// Salons et fils de discussion
+        const CHANNEL = 1 << 4;
+        /// Messages
+        const MESSAGE = 1 << 5;
+        /// Réactions
+        const REACTION = 1 << 6;
+        /// Interactions (slash commandes, autocomplétion, boutons, menus déroulants)
+        const INTERACTION = 1 << 7;
+        /// Présence des utilisateurs
+        const PRESENCE = 1 << 8;
+        /// Utilisateurs en train d'écrire
+        const TYPING = 1 << 9;
+        /// Salons vocaux
+        const VOICE = 1 << 10;
+        /// Autres évènements
+        const OTHER = 1 << 11;
+    }
+}
+
+impl EventKinds {
+    /// Retourne le type de l'évènement
+    pub fn of(evt: &Event) -> EventKinds {
+        use Event::*;
+        match evt {
+            Ready(_) | Resumed(_) => EventKinds::READY,
+            GuildCreate(_)
+            | GuildDelete(_)
+            | GuildUpdate(_)
+            | GuildUnavailable(_)
+            | GuildEmojisUpdate(_)
+            | GuildIntegrationsUpdate(_)
+            | IntegrationCreate(_)
+            | IntegrationUpdate(_)
+            | IntegrationDelete(_)
+            | InviteCreate(_)
+            | InviteDelete(_)
+            | StageInstanceCreate(_)
+            | StageInstanceUpdate(_)
+            | StageInstanceDelete(_)
+            | WebhookUpdate(_) => EventKinds::GUILD,
+            GuildMemberAdd(_) | GuildMemberRemove(_) | GuildMemberUpdate(_)
+            | GuildMembersChunk(_) | GuildBanAdd(_) | GuildBanRemove(_) | UserUpdate(_) => {
+                EventKinds::MEMBER
+            }
+            GuildRoleCreate(_) | GuildRoleDelete(_) | GuildRoleUpdate(_) => EventKinds::ROLE,
+            ChannelCreate(_)
+            | ChannelDelete(_)
+            | ChannelPinsUpdate(_)
+            | ChannelUpdate(_)
+            | ThreadCreate(_)
+            | ThreadUpdate(_)
+            | ThreadDelete(_)
+            | ThreadListSync(_)
+            | ThreadMemberUpdate(_)
+            | ThreadMembersUpdate(_) => EventKinds::CHANNEL,
+            MessageCreate(_) | MessageDelete(_) | MessageDeleteBulk(_) | MessageUpdate(_) => {
+                EventKinds::MESSAGE
+            }
+            ReactionAdd(_) | ReactionRemove(_) | ReactionRemoveAll(_) => EventKinds::REACTION,
+            InteractionCreate(_) => EventKinds::INTERACTION,
+            PresenceUpdate(_) | PresencesReplace(_) => EventKinds::PRESENCE,
+            TypingStart(_) => EventKinds::TYPING,
+            VoiceStateUpdate(_) | VoiceServerUpdate(_) => EventKinds::VOICE,
+            _ => EventKinds::OTHER,
+        }
+    }
+    /// Retourne le nom du type d'évènement, utilisé dans les métriques
+    pub fn label(&self) -> &'static str {
+        match *self {
+            EventKinds::READY => "ready",
+            EventKinds::GUILD => "guild",
+            EventKinds::MEMBER => "member",
+            EventKinds::ROLE => "role",
+            EventKinds::CHANNEL => "channel",
+            EventKinds::MESSAGE => "message",
+            EventKinds::REACTION => "reaction",
+            EventKinds::INTERACTION => "interaction",
+            EventKinds::PRESENCE => "presence",
+            EventKinds::TYPING => "typing",
+            EventKinds::VOICE => "voice",
+            _ => "other",
+        }
+    }
+}
 
-use super::manager::ArcManager;
+/// Retourne le serveur concerné par l'évènement, s'il est connu.
+fn guild_id_of(evt: &Event) -> Option<GuildId> {
+    use Event::*;
+    match evt {
+        GuildCreate(e) => Some(e.guild.id),
+        GuildDelete(e) => Some(e.guild.id),
+        GuildUpdate(e) => Some(e.guild.id),
+        GuildMemberAdd(e) => Some(e.guild_id),
+        GuildMemberRemove(e) => Some(e.guild_id),
+        GuildMemberUpdate(e) => Some(e.guild_id),
+        GuildBanAdd(e) => Some(e.guild_id),
+        GuildBanRemove(e) => Some(e.guild_id),
+        GuildRoleCreate(e) => Some(e.guild_id),
+        GuildRoleDelete(e) => Some(e.guild_id),
+        GuildRoleUpdate(e) => Some(e.guild_id),
+        MessageCreate(e) => e.message.guild_id,
+        MessageUpdate(e) => e.guild_id,
+        MessageDelete(e) => e.guild_id,
+        ReactionAdd(e) => e.reaction.guild_id,
+        ReactionRemove(e) => e.reaction.guild_id,
+        TypingStart(e) => e.guild_id,
+        VoiceStateUpdate(e) => e.guild_id,
+        InteractionCreate(e) => match &e.interaction {
+            Interaction::ApplicationCommand(i) => i.guild_id,
+            Interaction::MessageComponent(i) => i.guild_id,
+            Interaction::Autocomplete(i) => i.guild_id,
+            Interaction::Ping(_) => None,
+        },
+        _ => None,
+    }
+}
 
 /// Event handler qui dispatch les events aux composants.
 ///
-/// Dès qu'un event est reçu par le client, il est envoyé à tous les composants enregistrés.
-/// C'est au composant de traiter quel type d'event il a besoin.
+/// Dès qu'un event est reçu par le client, il est envoyé aux composants abonnés à ce type d'event (voir [`EventKinds`]).
+/// Chaque composant traite l'event dans sa propre tâche, avec une durée limite.
+/// Les events d'un serveur ne sont pas transmis aux composants désactivés sur ce serveur (voir [`Manager::set_enabled`]).
+///
+/// Les slash commandes sont d'abord routées vers le handler de la commande par le [`Manager`], et les demandes
+/// d'autocomplétion vers le fournisseur de l'argument en cours de saisie.
+///
+/// Au premier évènement Ready, les composants sont initialisés (voir [`Manager::init`]) avant de recevoir l'évènement.
 pub struct EventDispatcher {
     cmp_manager: ArcManager,
+    /// Vrai une fois les composants initialisés
+    initialized: Arc<AtomicBool>,
 }
 
 impl EventDispatcher {
     pub fn new(cmp_manager: ArcManager) -> EventDispatcher {
-        EventDispatcher { cmp_manager }
+        EventDispatcher {
+            cmp_manager,
+            initialized: Arc::new(AtomicBool::new(false)),
+        }
+    }
+    /// Transmet l'event au composant.
+    ///
+    /// Un avertissement est affiché si le composant met trop de temps à traiter l'event,
+    /// et le traitement est abandonné au bout de [`EVENT_TIMEOUT`].
+    /// Le nombre d'events, leur durée de traitement et les erreurs sont comptés dans les [métriques](crate::metrics).
+    async fn dispatch(component: ArcComponent, ctx: &Context, evt: &Event, kind: EventKinds) {
+        let component = component.read().await;
+        let start = Instant::now();
+        let result = tokio::time::timeout(EVENT_TIMEOUT, component.event(ctx, evt)).await;
+        let elapsed = start.elapsed();
+        let labels = [component.name(), kind.label()];
+        metrics::EVENTS.inc(&labels);
+        metrics::EVENT_DURATION.observe(&labels, elapsed);
+        match result {
+            Ok(Ok(())) => (),
+            Ok(Err(what)) => {
+                metrics::ERRORS.inc(&[component.name(), "event"]);
+                log::error!(target: component.name(), "event error: {}\nEvent: {:?}", what, evt)
+            }
+            Err(_) => {
+                metrics::ERRORS.inc(&[component.name(), "timeout"]);
+                log::error!(
+                    target: component.name(),
+                    "event timed out after {} s\nEvent: {:?}",
+                    EVENT_TIMEOUT.as_secs(),
+                    kind
+                )
+            }
+        }
+        if elapsed > SLOW_EVENT && elapsed < EVENT_TIMEOUT {
+            log::warn!(
+                target: component.name(),
+                "slow event: {} ms\nEvent: {:?}",
+                elapsed.as_millis(),
+                kind
+            );
+        }
     }
 }
 
 #[async_trait]
 impl RawEventHandler for EventDispatcher {
     async fn raw_event(&self, ctx: Context, evt: Event) {
-        let components = self.cmp_manager.read().await.get_components().clone();
+        let manager = self.cmp_manager.clone();
+        let initialized = self.initialized.clone();
+        let kind = EventKinds::of(&evt);
         tokio::spawn(async move {
-            for component in components {
-                let component = component.read().await;
-                if let Err(what) = component.event(&ctx, &evt).await {
-                    println!(
-                        "[{}] Module {} command error: {}\nEvent: {:?}\n\n",
-                        chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
-                        component.name(),
-                        what,
-                        evt
-                    );
+            if let Event::Ready(_) = evt {
+                if !initialized.swap(true, Ordering::SeqCst) {
+                    manager.read().await.init(&ctx).await;
+                }
+            }
+            if let Event::InteractionCreate(InteractionCreateEvent {
+                ref interaction, ..
+            }) = evt
+            {
+                match interaction {
+                    Interaction::ApplicationCommand(app_command) => {
+                        if let Err(what) = Manager::run_command(&manager, &ctx, app_command).await {
+                            log::error!("Command {} error: {}", app_command.data.name, what);
+                        }
+                    }
+                    Interaction::Autocomplete(autocomplete) => {
+                        if let Err(what) =
+                            Manager::run_autocomplete(&manager, &ctx, autocomplete).await
+                        {
+                            log::error!("Autocomplete {} error: {}", autocomplete.data.name, what);
+                        }
+                    }
+                    _ => (),
                 }
             }
+            // Seuls les composants abonnés reçoivent l'event, chacun dans sa propre tâche
+            let components = manager
+                .read()
+                .await
+                .get_subscribed_components(guild_id_of(&evt), kind)
+                .await;
+            let evt = Arc::new(evt);
+            for component in components {
+                let ctx = ctx.clone();
+                let evt = Arc::clone(&evt);
+                tokio::spawn(async move { Self::dispatch(component, &ctx, &evt, kind).await });
+            }
         });
     }
 }
@@ -1,10 +1,14 @@
+use std::sync::{Arc, RwLock};
+
 use serenity::async_trait;
 pub use serenity::client::Context;
 pub use serenity::model::channel::Message;
 
+use super::bus::{Bus, BusEvent};
 use super::manager::ArcManager;
 
 /// Configuration du framework.
+#[derive(Clone)]
 pub struct FrameworkConfig {
     pub prefix: char,
 }
@@ -14,19 +18,33 @@ pub struct FrameworkConfig {
 /// Les commandes sont envoyées à chaque composant jusqu'à ce que le composant reconnaisse la commande.
 pub struct Framework {
     components: ArcManager,
-    config: FrameworkConfig,
+    /// Configuration, mise à jour lorsque la configuration du bot est rechargée
+    config: Arc<RwLock<FrameworkConfig>>,
 }
 
 impl Framework {
-    pub fn new(prefix: char, cmp_manager: ArcManager) -> Framework {
+    /// Crée le framework.
+    ///
+    /// Doit être appelé depuis le runtime tokio pour s'abonner au bus.
+    pub fn new(prefix: char, cmp_manager: ArcManager, bus: &Bus) -> Framework {
+        let config = Arc::new(RwLock::new(FrameworkConfig { prefix }));
+        {
+            let config = Arc::clone(&config);
+            bus.subscribe("framework", move |evt| {
+                if let BusEvent::ConfigReloaded { prefix, .. } = evt {
+                    config.write().unwrap().prefix = prefix;
+                }
+                async { Ok(()) }
+            });
+        }
         Framework {
             components: cmp_manager,
-            config: FrameworkConfig { prefix },
+            config,
         }
     }
     /// Retourne la configuration du framework.
-    pub fn config(&self) -> &FrameworkConfig {
-        &self.config
+    pub fn config(&self) -> FrameworkConfig {
+        self.config.read().unwrap().clone()
     }
 }
 
@@ -36,7 +54,7 @@ impl serenity::framework::Framework for Framework {
     /// Le premier composant qui reconnait la commande est utilisé puis termine la fonction.
     #[allow(unused_results)]
     async fn dispatch(&self, ctx: Context, msg: Message) {
-        if !msg.content.starts_with(self.config.prefix) {
+        if !msg.content.starts_with(self.config().prefix) {
             return;
         }
 
@@ -46,7 +64,7 @@ impl serenity::framework::Framework for Framework {
             .await
         {
             Ok(_) => (),
-            Err(e) => println!("{}", e),
+            Err(e) => log::error!("{}", e),
         }
         return;
 
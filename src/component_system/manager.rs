@@ -1,23 +1,558 @@
-use super::ArcComponent;
+use super::bus::Bus;
+use super::command_parser as cmd;
+use super::components::utils::{app_command::ApplicationCommandEmbed, commands, message};
+use super::data::GuildData;
+use super::slash;
+use super::{ArcComponent, Context, EventKinds};
+use crate::metrics;
 use crate::util::ArcRw;
+use serde::{Deserialize, Serialize};
+use serenity::model::{
+    guild::Member,
+    id::{ApplicationId, GuildId, UserId},
+    interactions::{
+        application_command::{
+            ApplicationCommandInteraction, ApplicationCommandOptionType, ApplicationCommandType,
+        },
+        autocomplete::AutocompleteInteraction,
+        InteractionApplicationCommandCallbackDataFlags,
+    },
+};
+
+/// Composants nécessaires au fonctionnement du bot, qui ne peuvent pas être désactivés
+pub const SYSTEM_COMPONENTS: [&str; 3] = ["component", "help", "slash"];
+
+/// Données persistantes du manager pour un serveur
+#[derive(Serialize, Deserialize, Default, Debug)]
+struct ManagerData {
+    /// Noms des composants désactivés sur le serveur
+    disabled: Vec<String>,
+}
 
 pub struct Manager {
     components: Vec<ArcComponent>,
+    app_id: ApplicationId,
+    /// Owners du bot, qui ont toutes les permissions sur les commandes
+    owners: Vec<UserId>,
+    /// Bus de messages entre composants
+    bus: Bus,
+    /// Composants désactivés de chaque serveur
+    data: GuildData<ManagerData>,
 }
 
 impl Manager {
-    pub fn new() -> Self {
+    pub fn new(app_id: ApplicationId, owners: Vec<UserId>) -> Self {
         Manager {
             components: Vec::new(),
+            app_id,
+            owners,
+            bus: Bus::new(),
+            data: GuildData::new("components"),
         }
     }
     pub fn add_component(&mut self, cmp_arc: ArcComponent) -> &mut Self {
         self.components.push(cmp_arc);
         self
     }
+    /// Vérifie les arbres de commandes de tous les composants avec [`slash::validate_trees`].
+    ///
+    /// Retourne toutes les violations, une par ligne.
+    pub async fn validate_components(&self) -> Result<(), String> {
+        let mut components = Vec::new();
+        for compo in &self.components {
+            components.push(compo.read().await);
+        }
+        let trees = components
+            .iter()
+            .filter_map(|compo| compo.node().map(|node| (compo.name(), node)));
+        slash::validate_trees(trees).map_err(|errors| {
+            errors
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+    }
+    /// Remplace les owners du bot, par exemple au rechargement de la configuration
+    pub fn set_owners(&mut self, owners: Vec<UserId>) {
+        self.owners = owners;
+    }
     pub fn get_components(&self) -> &Vec<ArcComponent> {
         &self.components
     }
+    /// Retourne les composants activés sur le serveur `guild_id`, ou tous les composants si aucun serveur n'est donné.
+    pub async fn get_enabled_components(&self, guild_id: Option<GuildId>) -> Vec<ArcComponent> {
+        let disabled = match guild_id {
+            Some(guild_id) => self.disabled_components(guild_id).await,
+            None => return self.components.clone(),
+        };
+        let mut components = Vec::new();
+        for compo in &self.components {
+            let name = compo.read().await.name().to_string();
+            if !disabled.contains(&name) {
+                components.push(compo.clone());
+            }
+        }
+        components
+    }
+    /// Retourne les composants abonnés aux évènements `kind` (voir [`Component::subscriptions`]) et activés
+    /// sur le serveur `guild_id`.
+    ///
+    /// Les données du serveur ne sont lues que si au moins un composant est abonné.
+    ///
+    /// [`Component::subscriptions`]: super::Component::subscriptions
+    pub async fn get_subscribed_components(
+        &self,
+        guild_id: Option<GuildId>,
+        kind: EventKinds,
+    ) -> Vec<ArcComponent> {
+        let mut subscribed = Vec::new();
+        for compo in &self.components {
+            let component = compo.read().await;
+            if component.subscriptions().intersects(kind) {
+                subscribed.push((component.name().to_string(), compo.clone()));
+            }
+        }
+        let disabled = match guild_id {
+            Some(guild_id) if !subscribed.is_empty() => self.disabled_components(guild_id).await,
+            _ => Vec::new(),
+        };
+        subscribed
+            .into_iter()
+            .filter(|(name, _)| !disabled.contains(name))
+            .map(|(_, compo)| compo)
+            .collect()
+    }
+    /// Retourne les noms des composants désactivés sur le serveur `guild_id`
+    pub async fn disabled_components(&self, guild_id: GuildId) -> Vec<String> {
+        match self.data.get(guild_id).await {
+            Ok(data) => data.read().await.read().disabled.clone(),
+            Err(e) => {
+                log::error!(target: "data", "Données des composants indisponibles: {}", e);
+                Vec::new()
+            }
+        }
+    }
+    /// Active ou désactive le composant `name` sur le serveur `guild_id`.
+    ///
+    /// Retourne faux si le composant était déjà dans cet état.
+    /// Les [composants système](SYSTEM_COMPONENTS) ne peuvent pas être désactivés.
+    pub async fn set_enabled(
+        &self,
+        guild_id: GuildId,
+        name: &str,
+        enabled: bool,
+    ) -> Result<bool, String> {
+        let mut found = false;
+        for compo in &self.components {
+            found |= compo.read().await.name() == name;
+        }
+        if !found {
+            return Err(format!("Le composant `{}` n'existe pas.", name));
+        }
+        if !enabled && SYSTEM_COMPONENTS.contains(&name) {
+            return Err(format!(
+                "Le composant `{}` est un composant système et ne peut pas être désactivé.",
+                name
+            ));
+        }
+        let data = self
+            .data
+            .get(guild_id)
+            .await
+            .map_err(|e| format!("Données des composants indisponibles: {}", e))?;
+        let is_enabled = !data.read().await.read().disabled.iter().any(|v| v == name);
+        if is_enabled == enabled {
+            return Ok(false);
+        }
+        let mut data = data.write().await;
+        let mut data = data.write();
+        if enabled {
+            data.disabled.retain(|v| v != name);
+        } else {
+            data.disabled.push(name.to_string());
+        }
+        Ok(true)
+    }
+    /// Retourne le bus de messages entre composants. Voir [`Bus`].
+    pub fn bus(&self) -> &Bus {
+        &self.bus
+    }
+    /// Initialise tous les composants. Voir [`Component::init`].
+    ///
+    /// [`Component::init`]: super::Component::init
+    pub async fn init(&self, ctx: &Context) {
+        for compo in &self.components {
+            let compo = compo.read().await;
+            if let Err(what) = compo.init(ctx).await {
+                log::error!(target: compo.name(), "init error: {}", what);
+            }
+        }
+    }
+    /// Arrête tous les composants. Voir [`Component::shutdown`].
+    ///
+    /// [`Component::shutdown`]: super::Component::shutdown
+    pub async fn shutdown(&self) {
+        futures::future::join_all(
+            self.components
+                .iter()
+                .map(|compo| async move { compo.read().await.shutdown().await }),
+        )
+        .await;
+    }
+    /// Cherche la commande `id` dans les noeuds des composants activés sur le serveur `guild_id`.
+    ///
+    /// Seules les commandes ayant un handler sont retournées, avec le nom de leur composant.
+    /// La permission de la commande retournée est celle qui s'y applique, héritée de ses groupes si besoin.
+    pub async fn find_command(
+        &self,
+        guild_id: Option<GuildId>,
+        id: &str,
+    ) -> Option<(String, cmd::Command)> {
+        for compo in &self.get_enabled_components(guild_id).await {
+            let compo = compo.read().await;
+            let command = compo
+                .node()
+                .and_then(|node| node.find_command_permission(id))
+                .filter(|(command, _)| command.handler().is_some());
+            if let Some((command, permission)) = command {
+                let mut command = command.clone();
+                command.permission = permission.map(str::to_string);
+                return Some((compo.name().to_string(), command));
+            }
+        }
+        None
+    }
+    /// Cherche la commande de menu contextuel `name` ciblant `target` dans les composants activés sur le serveur `guild_id`.
+    ///
+    /// Seules les commandes ayant un handler sont retournées, avec le nom de leur composant.
+    pub async fn find_context_command(
+        &self,
+        guild_id: Option<GuildId>,
+        name: &str,
+        target: cmd::ContextTarget,
+    ) -> Option<(String, cmd::ContextCommand)> {
+        for compo in &self.get_enabled_components(guild_id).await {
+            let compo = compo.read().await;
+            let command = compo
+                .node()
+                .and_then(|node| node.find_context_command(name, target))
+                .filter(|command| command.handler().is_some());
+            if let Some(command) = command {
+                return Some((compo.name().to_string(), command.clone()));
+            }
+        }
+        None
+    }
+    /// Cherche la commande de l'interaction `app_cmd` dans les composants activés sur le serveur `guild_id`.
+    ///
+    /// Les commandes de menu contextuel, de cible `context_target`, n'ont pas de paramètres.
+    async fn find_interaction_command(
+        &self,
+        guild_id: Option<GuildId>,
+        app_cmd: &ApplicationCommandEmbed<'_>,
+        context_target: Option<cmd::ContextTarget>,
+    ) -> Option<FoundCommand> {
+        match context_target {
+            Some(target) => self
+                .find_context_command(guild_id, &app_cmd.0.data.name, target)
+                .await
+                .map(|(component, command)| {
+                    (component, command.handler, Vec::new(), command.permission)
+                }),
+            None => self.find_command(guild_id, &app_cmd.fullname()).await.map(
+                |(component, command)| {
+                    (
+                        component,
+                        command.handler,
+                        command.params,
+                        command.permission,
+                    )
+                },
+            ),
+        }
+    }
+    /// Exécute une slash commande reçue par le bot.
+    ///
+    /// La commande est routée vers le [handler] dont l'id correspond au nom complet de la commande,
+    /// puis le message retourné est envoyé en réponse à l'interaction.
+    /// Les arguments absents prennent leur valeur par défaut et les arguments qui ne respectent pas
+    /// les contraintes de leur paramètre sont refusés.
+    /// Une erreur du handler est renvoyée à l'utilisateur sous forme de message éphémère.
+    /// Les fichiers joints au message sont envoyés dans un message de suivi.
+    /// Les commandes des composants désactivés sur le serveur ne sont pas exécutées : elles sont refusées
+    /// par un message éphémère.
+    /// Une commande réservée (voir [`has_permission`]) est refusée par un message éphémère avant l'appel du handler.
+    /// Les commandes exécutées (hors refus de permission) et les erreurs des handlers sont comptées dans les
+    /// [métriques](crate::metrics).
+    ///
+    /// Les commandes de menu contextuel sont routées vers la [`cmd::ContextCommand`] du même nom et de même cible.
+    ///
+    /// Le verrou du manager est relaché avant l'appel du handler.
+    ///
+    /// [handler]: cmd::Handler
+    pub async fn run_command(
+        manager: &ArcManager,
+        ctx: &Context,
+        app_command: &ApplicationCommandInteraction,
+    ) -> Result<(), String> {
+        let mut app_cmd = ApplicationCommandEmbed::new(app_command);
+        let context_target = match app_command.data.kind {
+            ApplicationCommandType::User => Some(cmd::ContextTarget::User),
+            ApplicationCommandType::Message => Some(cmd::ContextTarget::Message),
+            _ => None,
+        };
+        let (command, owners, disabled) = {
+            let manager = manager.read().await;
+            if app_command.application_id != manager.app_id {
+                // La commande n'est pas destiné à ce bot
+                return Ok(());
+            }
+            let guild_id = app_command.guild_id;
+            let command = manager
+                .find_interaction_command(guild_id, &app_cmd, context_target)
+                .await;
+            // Les commandes globales d'un composant désactivé restent enregistrées sur le serveur
+            let disabled = command.is_none()
+                && guild_id.is_some()
+                && manager
+                    .find_interaction_command(None, &app_cmd, context_target)
+                    .await
+                    .is_some();
+            (command, manager.owners.clone(), disabled)
+        };
+        let mut msg = match command {
+            Some((component, Some(handler), params, permission)) => {
+                let fullname = app_cmd.fullname();
+                app_cmd.apply_defaults(&params);
+                let result = match has_permission(
+                    ctx,
+                    &owners,
+                    app_command.user.id,
+                    app_command.guild_id,
+                    app_command.member.as_ref(),
+                    permission.as_deref(),
+                )
+                .await
+                {
+                    Ok(true) => {
+                        metrics::COMMANDS.inc(&[&fullname]);
+                        match app_cmd.check_constraints(&params) {
+                            Ok(()) => handler.call(ctx, &app_cmd).await,
+                            Err(e) => Err(e),
+                        }
+                    }
+                    Ok(false) => {
+                        log::debug!(
+                            "Command {} refused to {}: missing permission {:?}",
+                            fullname,
+                            app_command.user.id,
+                            permission
+                        );
+                        Err("Vous n'avez pas la permission d'utiliser cette commande.".into())
+                    }
+                    Err(e) => Err(e),
+                };
+                if result.is_err() {
+                    metrics::ERRORS.inc(&[&component, "command"]);
+                }
+                result
+            }
+            None if disabled => Err("Ce composant est désactivé sur ce serveur.".into()),
+            _ => Err(cmd::ParseError::NotMatched.to_string()),
+        }
+        .unwrap_or_else(|e| message::error(e).set_ephemeral(true));
+        let files = std::mem::take(&mut msg.files);
+        let ephemeral = msg.ephemeral;
+        app_command
+            .create_interaction_response(ctx, |resp| {
+                *resp = msg.into();
+                resp
+            })
+            .await
+            .map_err(|e| format!("Cannot create response: {}", e))?;
+        if files.is_empty() {
+            return Ok(());
+        }
+        app_command
+            .create_followup_message(ctx, |followup| {
+                if ephemeral {
+                    followup.flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL);
+                }
+                followup.add_files(files)
+            })
+            .await
+            .map(|_| ())
+            .map_err(|e| format!("Cannot send attached files: {}", e))
+    }
+    /// Répond à une demande d'autocomplétion de l'argument d'une slash commande.
+    ///
+    /// Les suggestions sont fournies par le [fournisseur] de l'argument en cours de saisie, puis filtrées
+    /// selon la saisie de l'utilisateur. Une liste vide est envoyée si l'argument n'a pas de fournisseur,
+    /// ou si l'utilisateur n'a pas la permission d'utiliser la commande (voir [`has_permission`]).
+    ///
+    /// Le verrou du manager est relaché avant l'appel du fournisseur.
+    ///
+    /// [fournisseur]: cmd::AutocompleteProvider
+    pub async fn run_autocomplete(
+        manager: &ArcManager,
+        ctx: &Context,
+        interaction: &AutocompleteInteraction,
+    ) -> Result<(), String> {
+        let focused = match commands::focused_argument(&interaction.data) {
+            Some(focused) => focused,
+            None => return Ok(()),
+        };
+        let (command, owners) = {
+            let manager = manager.read().await;
+            if interaction.application_id != manager.app_id {
+                // La commande n'est pas destiné à ce bot
+                return Ok(());
+            }
+            let command = manager
+                .find_command(interaction.guild_id, &focused.command)
+                .await
+                .map(|(_, command)| command);
+            (command, manager.owners.clone())
+        };
+        let permission = command
+            .as_ref()
+            .and_then(|command| command.permission.clone());
+        let allowed = has_permission(
+            ctx,
+            &owners,
+            interaction.user.id,
+            interaction.guild_id,
+            interaction.member.as_ref(),
+            permission.as_deref(),
+        )
+        .await
+        .unwrap_or_else(|e| {
+            log::warn!("Autocomplete of {} refused: {}", focused.command, e);
+            false
+        });
+        let param = command.and_then(|command| {
+            command
+                .params()
+                .iter()
+                .find(|param| param.name == focused.name)
+                .cloned()
+        });
+        // Les suggestions d'une commande réservée ne sont pas divulguées
+        let autocomplete = param
+            .as_ref()
+            .filter(|_| allowed)
+            .and_then(|param| param.autocomplete());
+        let suggestions = match autocomplete {
+            Some(autocomplete) => {
+                autocomplete
+                    .suggestions(ctx, interaction.guild_id, &focused.value)
+                    .await
+            }
+            None => Vec::new(),
+        };
+        let value_type = param.map_or(ApplicationCommandOptionType::String, |param| {
+            param.value_type()
+        });
+        interaction
+            .create_autocomplete_response(&ctx.http, |resp| {
+                for suggestion in &suggestions {
+                    match value_type {
+                        ApplicationCommandOptionType::Integer => {
+                            if let Ok(value) = suggestion.parse::<i64>() {
+                                resp.add_int_choice(suggestion, value);
+                            }
+                        }
+                        ApplicationCommandOptionType::Number => {
+                            if let Ok(value) = suggestion.parse::<f64>() {
+                                resp.add_number_choice(suggestion, value);
+                            }
+                        }
+                        _ => {
+                            resp.add_string_choice(suggestion, suggestion);
+                        }
+                    }
+                }
+                resp
+            })
+            .await
+            .map_err(|e| format!("Cannot create autocomplete response: {}", e))
+    }
 }
 
 pub type ArcManager = ArcRw<Manager>;
+/// Commande trouvée par [`Manager::find_interaction_command`] : nom de son composant, handler, paramètres et permission
+type FoundCommand = (
+    String,
+    Option<cmd::Handler>,
+    Vec<cmd::Argument>,
+    Option<String>,
+);
+
+/// Vérifie que l'utilisateur `user_id`, membre `member` du serveur `guild_id`, a la permission `permission`.
+///
+/// Une commande sans permission est utilisable par tous et les owners ont toutes les permissions.
+/// La permission `"owners"` leur est réservée, les autres désignent le rôle du serveur de même nom.
+/// Hors d'un serveur, seuls les owners peuvent utiliser une commande réservée.
+async fn has_permission(
+    ctx: &Context,
+    owners: &[UserId],
+    user_id: UserId,
+    guild_id: Option<GuildId>,
+    member: Option<&Member>,
+    permission: Option<&str>,
+) -> Result<bool, String> {
+    let permission = match permission {
+        Some(permission) => permission,
+        None => return Ok(true),
+    };
+    if owners.contains(&user_id) {
+        return Ok(true);
+    }
+    let (guild_id, member) = match (guild_id, member) {
+        (Some(guild_id), Some(member)) if permission != "owners" => (guild_id, member),
+        _ => return Ok(false),
+    };
+    let roles = match ctx.cache.guild_roles(guild_id).await {
+        Some(roles) => roles,
+        None => guild_id
+            .roles(&ctx.http)
+            .await
+            .map_err(|e| format!("Impossible d'obtenir les rôles du serveur: {}", e))?,
+    };
+    Ok(member
+        .roles
+        .iter()
+        .filter_map(|role_id| roles.get(role_id))
+        .any(|role| role.name == permission))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::component_system::{components::*, Component};
+
+    #[tokio::test]
+    async fn dispatches_only_to_subscribers() {
+        let bus = Bus::new();
+        let mut manager = Manager::new(ApplicationId(1), Vec::new());
+        manager
+            .add_component(Tickets::new(bus.clone()).to_arc())
+            .add_component(Moderation::new(bus.clone()).to_arc())
+            .add_component(Misc::new(8, bus).to_arc());
+        let names = |components: Vec<ArcComponent>| async move {
+            let mut names = Vec::new();
+            for compo in components {
+                names.push(compo.read().await.name().to_string());
+            }
+            names
+        };
+        let ready = manager.get_subscribed_components(None, EventKinds::READY);
+        assert_eq!(names(ready.await).await, ["tickets", "mod", "misc"]);
+        let interaction = manager.get_subscribed_components(None, EventKinds::INTERACTION);
+        assert_eq!(names(interaction.await).await, ["tickets"]);
+        // Aucun abonné : les données du serveur ne sont pas lues
+        let typing = manager.get_subscribed_components(Some(GuildId(1)), EventKinds::TYPING);
+        assert!(typing.await.is_empty());
+    }
+}
@@ -6,15 +6,17 @@
 
 use serenity::async_trait;
 
+pub mod api;
+pub mod bus;
 pub mod command_parser;
 pub mod components;
-mod data;
+pub mod data;
 mod event;
 mod framework;
 pub mod manager;
 mod slash;
 
-pub use event::EventDispatcher;
+pub use event::{EventDispatcher, EventKinds};
 pub use framework::{Context, Message};
 pub use framework::{Framework, FrameworkConfig};
 pub use serenity::model::event::Event;
@@ -58,8 +60,14 @@ pub trait Component: Sync + Send {
     /// Cette fonction est appelée lorsque le bot reçoit un évènement.
     ///
     /// Si l'event s'est bien passé ou n'a pas été traité, elle doit retourner `Ok(())`.
-    /// Sinon, un Err contenant le message d'erreur doit être retourné. Ce message d'erreur sera ensuite renvoyé à la sortie standard.
+    /// Sinon, un Err contenant le message d'erreur doit être retourné. Ce message d'erreur sera ensuite journalisé.
     async fn event(&self, ctx: &Context, evt: &Event) -> Result<(), String>;
+    /// Types d'évènements reçus par le composant.
+    ///
+    /// Seuls les évènements de ces types sont transmis à [`Component::event`]. Par défaut, tous les évènements.
+    fn subscriptions(&self) -> EventKinds {
+        EventKinds::all()
+    }
     /// Retournes le groupe de commandes lié au composant.
     ///
     /// Le système d'aide du bot se repose sur ce groupe.
@@ -68,6 +76,23 @@ pub trait Component: Sync + Send {
         None
     }
     fn register_slash(&self) {}
+    /// Initialisation du composant.
+    ///
+    /// Appelée une seule fois, à la première connexion du bot (évènement Ready),
+    /// avant que l'évènement ne soit transmis aux composants.
+    async fn init(&self, _: &Context) -> Result<(), String> {
+        Ok(())
+    }
+    /// Arrêt du composant.
+    ///
+    /// Appelée à l'arrêt du bot. Le composant doit y arrêter proprement ses tâches en cours.
+    async fn shutdown(&self) {}
+    /// Requête de l'API d'administration adressée au composant (`/components/<nom>/...`). Voir [`api`].
+    ///
+    /// Doit retourner None si le composant ne prend pas en charge la route demandée. Par défaut, aucune route.
+    async fn api(&self, _: &Context, _: &api::ApiRequest) -> Option<api::ApiResult> {
+        None
+    }
     /// Helper : convertir un composant en ArcComponent
     fn to_arc(self) -> ArcComponent
     where
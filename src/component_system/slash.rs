@@ -1,10 +1,96 @@
 //! Générateurs de commandes slash via notre API de commandes.
 
 use super::command_parser::{self as cmd, Named};
+use serde_json::{json, Map, Number, Value};
 use serenity::{
     builder::{CreateApplicationCommand, CreateApplicationCommandOption},
-    model::interactions::application_command::ApplicationCommandOptionType,
+    model::interactions::application_command::{
+        ApplicationCommandOptionType, ApplicationCommandType,
+    },
+    utils::hashmap_to_json_map,
 };
+use std::fmt;
+
+/// Vérifie les arbres de commandes de plusieurs composants, donnés avec le nom de leur composant.
+///
+/// En plus de [`cmd::Node::validate`] pour chaque arbre, vérifie que les commandes de premier niveau
+/// de tous les composants ont des noms distincts et ne dépassent pas la limite de Discord.
+/// Les commandes de menu contextuel doivent aussi avoir des noms distincts par cible, dans la limite de Discord.
+/// Le chemin de chaque erreur est préfixé par le nom du composant : `tickets/ticket.create`.
+pub fn validate_trees<'a, I>(trees: I) -> Result<(), Vec<cmd::ValidationError>>
+where
+    I: IntoIterator<Item = (&'a str, &'a cmd::Node)>,
+{
+    let mut errors = Vec::new();
+    let mut roots: Vec<(&str, &str)> = Vec::new();
+    let mut context_roots: Vec<(&str, &cmd::ContextCommand)> = Vec::new();
+    for (component, node) in trees {
+        if let Err(node_errors) = node.validate() {
+            errors.extend(node_errors.into_iter().map(|e| cmd::ValidationError {
+                path: format!("{}/{}", component, e.path),
+                ..e
+            }));
+        }
+        let names = node
+            .groups
+            .list()
+            .map(|group| group.name())
+            .chain(node.commands.list().map(|command| command.name()));
+        for name in names {
+            if let Some((other, _)) = roots.iter().find(|(_, root)| *root == name) {
+                errors.push(cmd::ValidationError {
+                    path: format!("{}/{}", component, name),
+                    reason: format!("commande déjà définie par le composant {}", other),
+                });
+            }
+            roots.push((component, name));
+        }
+        for command in node.context_commands.list() {
+            let duplicate = context_roots
+                .iter()
+                .find(|(_, other)| other.name == command.name && other.target == command.target);
+            if let Some((other, _)) = duplicate {
+                errors.push(cmd::ValidationError {
+                    path: format!("{}/[{}] {}", component, command.target.name(), command.name),
+                    reason: format!("commande déjà définie par le composant {}", other),
+                });
+            }
+            context_roots.push((component, command));
+        }
+    }
+    for target in [cmd::ContextTarget::User, cmd::ContextTarget::Message] {
+        let count = context_roots
+            .iter()
+            .filter(|(_, command)| command.target == target)
+            .count();
+        if count > cmd::limits::CONTEXT_COMMANDS {
+            errors.push(cmd::ValidationError {
+                path: String::new(),
+                reason: format!(
+                    "{} commandes de menu contextuel {}, {} maximum",
+                    count,
+                    target.name(),
+                    cmd::limits::CONTEXT_COMMANDS
+                ),
+            });
+        }
+    }
+    if roots.len() > cmd::limits::COMMANDS {
+        errors.push(cmd::ValidationError {
+            path: String::new(),
+            reason: format!(
+                "{} commandes de premier niveau, {} maximum",
+                roots.len(),
+                cmd::limits::COMMANDS
+            ),
+        });
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
 
 pub fn register_root_with_perm(
     node: &cmd::Node,
@@ -33,7 +119,17 @@ pub fn register_root_with_perm(
         app_cmd.set_options(command.params().iter().map(register_argument).collect());
         app_cmd
     });
-    group_iter.chain(command_iter).collect()
+    let context_iter = node.context_commands.list().map(|command| {
+        let mut app_cmd = CreateApplicationCommand::default();
+        app_cmd.name(command.name());
+        app_cmd.kind(match command.target() {
+            cmd::ContextTarget::User => ApplicationCommandType::User,
+            cmd::ContextTarget::Message => ApplicationCommandType::Message,
+        });
+        app_cmd.default_permission(permission);
+        app_cmd
+    });
+    group_iter.chain(command_iter).chain(context_iter).collect()
 }
 fn register_group(group: &cmd::Group) -> CreateApplicationCommandOption {
     let mut app_cmd = CreateApplicationCommandOption::default();
@@ -62,11 +158,406 @@ fn register_command(command: &cmd::Command) -> CreateApplicationCommandOption {
 
     app_cmd
 }
+/// Valeur JSON d'une borne, entière si la borne l'est
+fn bound_value(bound: cmd::Bound) -> Value {
+    match bound {
+        cmd::Bound::Integer(value) => Value::from(value),
+        cmd::Bound::Number(value) => Value::from(value),
+    }
+}
+/// Ajoute un choix entier 64 bits à une option
+fn add_int_choice(app_cmd: &mut CreateApplicationCommandOption, name: &str, value: i64) {
+    let choices = app_cmd
+        .0
+        .entry("choices")
+        .or_insert_with(|| Value::Array(Vec::new()));
+    if let Value::Array(choices) = choices {
+        choices.push(json!({ "name": name, "value": value }));
+    }
+}
 fn register_argument(argument: &cmd::Argument) -> CreateApplicationCommandOption {
     let mut app_cmd = CreateApplicationCommandOption::default();
     app_cmd.name(argument.name());
-    app_cmd.required(argument.required());
+    app_cmd.required(argument.required() && argument.default().is_none());
     app_cmd.kind(argument.value_type());
     argument.help().map(|help| app_cmd.description(help));
+    app_cmd.set_autocomplete(argument.autocomplete().is_some());
+    match argument.value_type() {
+        ApplicationCommandOptionType::Integer | ApplicationCommandOptionType::Number => {
+            // Les bornes et choix entiers de serenity sont des i32, alors que Discord accepte des i64
+            if let Some(min) = argument.min() {
+                app_cmd.0.insert("min_value", bound_value(min));
+            }
+            if let Some(max) = argument.max() {
+                app_cmd.0.insert("max_value", bound_value(max));
+            }
+        }
+        ApplicationCommandOptionType::Channel if !argument.channel_types().is_empty() => {
+            app_cmd.channel_types(argument.channel_types());
+        }
+        _ => (),
+    }
+    for choice in argument.choices() {
+        match argument.value_type() {
+            ApplicationCommandOptionType::String => {
+                app_cmd.add_string_choice(choice, choice);
+            }
+            ApplicationCommandOptionType::Integer => {
+                if let Ok(value) = choice.parse::<i64>() {
+                    add_int_choice(&mut app_cmd, choice, value);
+                }
+            }
+            ApplicationCommandOptionType::Number => {
+                if let Ok(value) = choice.parse::<f64>() {
+                    app_cmd.add_number_choice(choice, value);
+                }
+            }
+            _ => (),
+        }
+    }
     app_cmd
 }
+
+/// Modifications à apporter aux commandes enregistrées sur un serveur pour qu'elles correspondent
+/// aux commandes générées par [`register_root_with_perm`].
+///
+/// Les commandes sont identifiées par leur nom et leur type (slash, membre ou message). Une commande
+/// existante n'est modifiée que si sa définition diffère, ce qui conserve son id et ses permissions.
+#[derive(Debug, Default)]
+pub struct CommandsDiff {
+    /// Commandes à créer
+    pub create: Vec<Value>,
+    /// Commandes à modifier : id de la commande existante et nouvelle définition
+    pub edit: Vec<(u64, Value)>,
+    /// Commandes à supprimer : id et définition de la commande existante
+    pub delete: Vec<(u64, Value)>,
+}
+
+impl CommandsDiff {
+    /// Compare les commandes `existing`, telles que retournées par Discord, aux commandes `generated`.
+    pub fn new(existing: &[Value], generated: Vec<CreateApplicationCommand>) -> CommandsDiff {
+        let mut diff = CommandsDiff::default();
+        let mut remaining = existing.iter().collect::<Vec<_>>();
+        for command in generated {
+            let command = Value::Object(hashmap_to_json_map(command.0));
+            let key = command_key(&command);
+            match remaining.iter().position(|other| command_key(other) == key) {
+                Some(idx) => {
+                    let other = remaining.remove(idx);
+                    if normalize(other, true) != normalize(&command, true) {
+                        diff.edit.push((command_id(other), command));
+                    }
+                }
+                None => diff.create.push(command),
+            }
+        }
+        diff.delete = remaining
+            .into_iter()
+            .map(|other| (command_id(other), other.clone()))
+            .collect();
+        diff
+    }
+    pub fn is_empty(&self) -> bool {
+        self.create.is_empty() && self.edit.is_empty() && self.delete.is_empty()
+    }
+    /// Noms des commandes à créer, modifier et supprimer
+    pub fn to_json(&self) -> Value {
+        json!({
+            "create": self.create.iter().map(command_label).collect::<Vec<_>>(),
+            "edit": self.edit.iter().map(|(_, c)| command_label(c)).collect::<Vec<_>>(),
+            "delete": self.delete.iter().map(|(_, c)| command_label(c)).collect::<Vec<_>>(),
+        })
+    }
+}
+/// Une ligne par commande : `+` création, `~` modification, `-` suppression
+impl fmt::Display for CommandsDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for command in &self.create {
+            writeln!(f, "+ {}", command_label(command))?;
+        }
+        for (_, command) in &self.edit {
+            writeln!(f, "~ {}", command_label(command))?;
+        }
+        for (_, command) in &self.delete {
+            writeln!(f, "- {}", command_label(command))?;
+        }
+        Ok(())
+    }
+}
+/// Type d'une commande, slash (1) si absent
+fn command_type(command: &Value) -> u64 {
+    command["type"].as_u64().unwrap_or(1)
+}
+/// Nom et type identifiant une commande
+fn command_key(command: &Value) -> (&str, u64) {
+    (
+        command["name"].as_str().unwrap_or(""),
+        command_type(command),
+    )
+}
+fn command_id(command: &Value) -> u64 {
+    command["id"]
+        .as_str()
+        .and_then(|id| id.parse().ok())
+        .unwrap_or(0)
+}
+/// Nom d'une commande, suivi de sa cible pour les commandes de menu contextuel
+fn command_label(command: &Value) -> String {
+    let name = command["name"].as_str().unwrap_or("");
+    match command_type(command) {
+        2 => format!("{} [user]", name),
+        3 => format!("{} [message]", name),
+        _ => name.to_string(),
+    }
+}
+/// Ne conserve que les champs qui définissent une commande ou une option, sans les ids, versions
+/// ni les valeurs par défaut de Discord, pour comparer une commande existante à une commande générée.
+fn normalize(value: &Value, root: bool) -> Value {
+    const FIELDS: [&str; 11] = [
+        "type",
+        "name",
+        "description",
+        "default_permission",
+        "required",
+        "autocomplete",
+        "choices",
+        "options",
+        "channel_types",
+        "min_value",
+        "max_value",
+    ];
+    let mut map = Map::new();
+    for field in FIELDS {
+        let value = match (field, &value[field]) {
+            // Commande slash par défaut, en flottant comme les autres nombres
+            ("type", Value::Null) if root => json!(1.0),
+            ("default_permission", Value::Null) if root => json!(true),
+            ("options", Value::Array(options)) => {
+                Value::Array(options.iter().map(|o| normalize(o, false)).collect())
+            }
+            ("choices", Value::Array(choices)) => Value::Array(
+                choices
+                    .iter()
+                    .map(|choice| json!({ "name": choice["name"], "value": normalize_number(&choice["value"]) }))
+                    .collect(),
+            ),
+            (_, value) => normalize_number(value),
+        };
+        let empty = match &value {
+            Value::Null => true,
+            Value::Bool(b) => !b && field != "default_permission",
+            Value::String(s) => s.is_empty(),
+            Value::Array(a) => a.is_empty(),
+            _ => false,
+        };
+        if !empty {
+            map.insert(field.to_string(), value);
+        }
+    }
+    Value::Object(map)
+}
+/// Les nombres sont comparés en flottants : Discord peut retourner `1.0` pour `1`
+fn normalize_number(value: &Value) -> Value {
+    match value.as_f64().and_then(Number::from_f64) {
+        Some(number) if value.is_number() => Value::Number(number),
+        _ => value.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::component_system::{
+        bus::Bus,
+        components::*,
+        manager::{ArcManager, Manager},
+        Component,
+    };
+    use crate::util::ArcRw;
+    use serenity::model::id::ApplicationId;
+
+    /// Composants du bot, tels que créés par `Bot::new`
+    fn components() -> Vec<Box<dyn Component>> {
+        let manager: ArcManager = ArcRw::new(Manager::new(ApplicationId(1), Vec::new()));
+        let bus = Bus::new();
+        vec![
+            Box::new(Misc::new(8, bus.clone())),
+            Box::new(Tickets::new(bus.clone())),
+            Box::new(Help::new(manager.clone())),
+            Box::new(Moderation::new(bus)),
+            Box::new(ComponentCommands::new(manager.clone())),
+            Box::new(SlashCommands::new(
+                manager,
+                ApplicationId(1),
+                false,
+                SlashConfig::default(),
+            )),
+        ]
+    }
+
+    fn command(name: &str) -> cmd::Command {
+        cmd::Command::new(name).set_help("Aide")
+    }
+
+    fn errors(trees: &[(&str, &cmd::Node)]) -> Vec<String> {
+        validate_trees(trees.iter().copied())
+            .unwrap_err()
+            .into_iter()
+            .map(|e| e.to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn components_trees_are_valid() {
+        let components = components();
+        let trees = components
+            .iter()
+            .filter_map(|compo| compo.node().map(|node| (compo.name(), node)));
+        if let Err(errors) = validate_trees(trees) {
+            let errors = errors.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+            panic!("invalid command trees:\n{}", errors.join("\n"));
+        }
+    }
+
+    #[test]
+    fn name_too_long() {
+        let name = "a".repeat(cmd::limits::NAME_LENGTH + 1);
+        let node = cmd::Node::new().add_command(command(&name));
+        let errors = errors(&[("misc", &node)]);
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert!(errors[0].starts_with(&format!("misc/{}", name)));
+    }
+
+    #[test]
+    fn too_many_choices() {
+        let choices = (0..=cmd::limits::CHOICES).map(|i| i.to_string());
+        let node = cmd::Node::new().add_command(
+            command("cmd").add_param(
+                cmd::Argument::new("choix")
+                    .set_value_type(cmd::ValueType::String)
+                    .set_help("Aide")
+                    .set_choices(choices),
+            ),
+        );
+        let errors = errors(&[("misc", &node)]);
+        assert_eq!(errors, vec!["misc/cmd -choix: 26 choix, 25 maximum"]);
+    }
+
+    #[test]
+    fn duplicate_name() {
+        let first = cmd::Node::new().add_command(command("ban"));
+        let second = cmd::Node::new().add_command(command("ban"));
+        let errors = errors(&[("mod", &first), ("misc", &second)]);
+        assert_eq!(
+            errors,
+            vec!["misc/ban: commande déjà définie par le composant mod"]
+        );
+    }
+
+    #[test]
+    fn required_after_optional() {
+        let node = cmd::Node::new().add_command(
+            command("cmd")
+                .add_param(
+                    cmd::Argument::new("optionnel")
+                        .set_value_type(cmd::ValueType::String)
+                        .set_help("Aide"),
+                )
+                .add_param(
+                    cmd::Argument::new("requis")
+                        .set_value_type(cmd::ValueType::String)
+                        .set_help("Aide")
+                        .set_required(true),
+                ),
+        );
+        let errors = errors(&[("misc", &node)]);
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert!(errors[0].starts_with("misc/cmd -requis"), "{:?}", errors);
+    }
+
+    #[test]
+    fn invalid_pattern() {
+        let node = cmd::Node::new().add_command(
+            command("cmd").add_param(
+                cmd::Argument::new("code")
+                    .set_help("Aide")
+                    .set_pattern("(abc"),
+            ),
+        );
+        let errors = errors(&[("misc", &node)]);
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert!(errors[0].starts_with("misc/cmd -code"), "{:?}", errors);
+    }
+
+    #[test]
+    fn integer_bounds_sent_as_integers() {
+        let argument = cmd::Argument::new("id")
+            .set_value_type(cmd::ValueType::Integer)
+            .set_help("Aide")
+            .set_min(i64::MAX - 1);
+        let option = register_argument(&argument);
+        assert_eq!(option.0["min_value"], json!(i64::MAX - 1));
+    }
+
+    /// Commande générée `name`, avec un argument `qui`
+    fn generated(name: &str, description: &str) -> CreateApplicationCommand {
+        let mut command = CreateApplicationCommand::default();
+        command
+            .name(name)
+            .description(description)
+            .create_option(|option| {
+                option
+                    .name("qui")
+                    .description("Le membre")
+                    .kind(ApplicationCommandOptionType::User)
+                    .required(true)
+            });
+        command
+    }
+
+    /// Commande telle que retournée par Discord : id, version, valeurs par défaut et champs dans un autre ordre
+    fn existing(id: u64, name: &str, description: &str) -> Value {
+        json!({
+            "version": "1",
+            "options": [{
+                "required": true,
+                "type": 6,
+                "description": "Le membre",
+                "name": "qui",
+                "autocomplete": false,
+            }],
+            "id": id.to_string(),
+            "application_id": "1",
+            "default_permission": true,
+            "type": 1,
+            "description": description,
+            "name": name,
+        })
+    }
+
+    #[test]
+    fn diff_identical_commands() {
+        let existing = [existing(10, "ban", "Bannir un membre")];
+        let diff = CommandsDiff::new(&existing, vec![generated("ban", "Bannir un membre")]);
+        assert!(diff.is_empty(), "{}", diff);
+    }
+
+    #[test]
+    fn diff_renamed_command() {
+        let existing = [existing(10, "ban", "Bannir un membre")];
+        let diff = CommandsDiff::new(&existing, vec![generated("bannir", "Bannir un membre")]);
+        assert_eq!(diff.to_string(), "+ bannir\n- ban\n");
+        assert_eq!(diff.delete[0].0, 10);
+        assert!(diff.edit.is_empty());
+    }
+
+    #[test]
+    fn diff_changed_description() {
+        let existing = [existing(10, "ban", "Bannir un membre")];
+        let diff = CommandsDiff::new(&existing, vec![generated("ban", "Exclure un membre")]);
+        assert!(diff.create.is_empty() && diff.delete.is_empty());
+        assert_eq!(diff.edit.len(), 1);
+        assert_eq!(diff.edit[0].0, 10);
+        assert_eq!(diff.edit[0].1["description"], "Exclure un membre");
+    }
+}
@@ -1,5 +1,14 @@
+use crate::component_system::components::{AdminConfig, SlashConfig};
+use crate::component_system::data::StorageConfig;
+use crate::logger::LogConfig;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use serenity::model::{id::UserId, Permissions};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+/// Configuration partagée entre le bot et le rechargement de la configuration
+pub type SharedConfig = Arc<RwLock<Config>>;
 
 /// Configuration de l'application
 ///
@@ -15,12 +24,40 @@ pub struct Config {
     pub prefix: char,
     pub permissions: u64,
     pub owners: Vec<String>,
+    /// Support de stockage des données des composants. Fichiers RON par défaut.
+    #[serde(default)]
+    pub storage: StorageConfig,
+    /// Nombre de sauvegardes conservées pour chaque donnée. 3 par défaut.
+    #[serde(default = "default_data_backups")]
+    pub data_backups: usize,
+    /// Si renseigné, les données modifiées sont enregistrées en différé, au plus toutes les `data_flush_interval_ms` millisecondes.
+    #[serde(default)]
+    pub data_flush_interval_ms: Option<u64>,
+    /// Journalisation : niveau de chaque composant et destinations des messages
+    #[serde(default)]
+    pub log: LogConfig,
+    /// Si renseigné, les métriques du bot sont exposées au format Prometheus sur `http://<metrics_addr>/metrics`
+    #[serde(default)]
+    pub metrics_addr: Option<SocketAddr>,
+    /// Si renseigné, l'API d'administration est exposée sur `admin_api.addr` et protégée par `admin_api.token`
+    #[serde(default)]
+    pub admin_api: Option<AdminConfig>,
+    /// Si vrai, les modifications des slash commandes sont affichées dans les logs sans être envoyées à Discord
+    #[serde(default)]
+    pub slash_dry_run: bool,
+    /// Portée des slash commandes : globales ou par serveur, pour tous les composants ou par composant
+    #[serde(default)]
+    pub slash: SlashConfig,
     #[serde(skip)]
     filepath: PathBuf,
 }
 
+fn default_data_backups() -> usize {
+    3
+}
+
 impl Config {
-    pub fn load<P: AsRef<std::path::Path>>(filepath: P) -> Result<Self, String> {
+    pub fn load<P: AsRef<Path>>(filepath: P) -> Result<Self, String> {
         let str_config = match std::fs::read_to_string(filepath.as_ref()) {
             Ok(v) => v,
             Err(e) => {
@@ -31,7 +68,11 @@ impl Config {
                 ))
             }
         };
-        let mut config: Config = match ron::from_str(&str_config) {
+        Self::parse(&str_config, filepath)
+    }
+    /// Lit et valide la configuration `str_config` du fichier `filepath`
+    pub fn parse<P: AsRef<Path>>(str_config: &str, filepath: P) -> Result<Self, String> {
+        let mut config: Config = match ron::from_str(str_config) {
             Ok(v) => v,
             Err(e) => {
                 return Err(format!(
@@ -42,27 +83,100 @@ impl Config {
             }
         };
         config.filepath = filepath.as_ref().to_path_buf();
+        config.validate().map_err(|e| {
+            format!(
+                "Invalid configuration {}: {}",
+                filepath.as_ref().to_string_lossy(),
+                e
+            )
+        })?;
         Ok(config)
     }
-    pub fn save(&self) -> Result<(), String> {
-        let str_config = match ron::ser::to_string_pretty(&self, ron::ser::PrettyConfig::default())
+    /// Vérifie les valeurs de la configuration
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(owner) = self.owners.iter().find(|id| id.parse::<u64>().is_err()) {
+            return Err(format!("owners: `{}` is not a user id", owner));
+        }
+        if Permissions::from_bits(self.permissions).is_none() {
+            return Err(format!("permissions: {} is not valid", self.permissions));
+        }
+        if self.prefix.is_whitespace() {
+            return Err("prefix: must not be a whitespace".to_string());
+        }
+        if let Some(admin_api) = &self.admin_api {
+            admin_api.validate()?;
+        }
+        Ok(())
+    }
+    /// Chemin du fichier de configuration
+    pub fn filepath(&self) -> &Path {
+        &self.filepath
+    }
+    /// Identifiants des owners du bot
+    pub fn owners_id(&self) -> Vec<UserId> {
+        self.owners
+            .iter()
+            .filter_map(|id| id.parse::<u64>().ok())
+            .map(UserId)
+            .collect()
+    }
+    /// Retourne les noms des paramètres modifiés dans `other` qui ne peuvent être pris en compte qu'au redémarrage du bot
+    pub fn restart_required(&self, other: &Config) -> Vec<&'static str> {
+        let mut fields = Vec::new();
+        if self.token != other.token {
+            fields.push("token");
+        }
+        if self.app_id != other.app_id {
+            fields.push("app_id");
+        }
+        if self.storage != other.storage {
+            fields.push("storage");
+        }
+        if self.data_backups != other.data_backups {
+            fields.push("data_backups");
+        }
+        if self.data_flush_interval_ms != other.data_flush_interval_ms {
+            fields.push("data_flush_interval_ms");
+        }
+        if self.log.sinks != other.log.sinks
+            || self.log.max_file_size != other.log.max_file_size
+            || self.log.max_files != other.log.max_files
         {
-            Ok(v) => v,
-            Err(e) => {
-                return Err(format!(
-                    "Unable to serialize {}: {}",
-                    self.filepath.to_string_lossy(),
-                    e
-                ))
-            }
-        };
-        match std::fs::write(&self.filepath, str_config) {
-            Ok(_) => Ok(()),
-            Err(e) => Err(format!(
-                "Unable to write {}: {}",
-                self.filepath.to_string_lossy(),
-                e
-            )),
+            fields.push("log");
+        }
+        if self.metrics_addr != other.metrics_addr {
+            fields.push("metrics_addr");
+        }
+        if self.admin_api != other.admin_api {
+            fields.push("admin_api");
+        }
+        if self.slash != other.slash {
+            fields.push("slash");
         }
+        fields
     }
 }
+
+/// Retourne les différences entre deux versions du fichier de configuration, au format diff unifié.
+///
+/// La valeur du token est masquée pour ne pas apparaitre dans les logs.
+pub fn diff(old: &str, new: &str) -> String {
+    let mask = |content: &str| {
+        content
+            .lines()
+            .map(|line| match line.trim_start().strip_prefix("token:") {
+                Some(_) => format!(
+                    "{}token: \"***\",\n",
+                    &line[..line.len() - line.trim_start().len()]
+                ),
+                None => format!("{}\n", line),
+            })
+            .collect::<String>()
+    };
+    let (old, new) = (mask(old), mask(new));
+    similar::TextDiff::from_lines(&old, &new)
+        .unified_diff()
+        .context_radius(1)
+        .header("running", "new")
+        .to_string()
+}
@@ -0,0 +1,310 @@
+//! Journalisation du bot.
+//!
+//! Les messages sont émis avec les macros de la crate `log` (`log::info!`, `log::warn!`...).
+//! La cible d'un message (`target:`) est le nom du composant qui l'émet (voir [`Component::name`]) ou le nom
+//! du sous-système (`data`, `bus`, `config`). Les messages sans cible explicite, comme ceux de serenity,
+//! ont pour cible le chemin de leur module (ex. `serenity::gateway::shard`).
+//!
+//! Le niveau de verbosité est réglable pour chaque cible dans le fichier de configuration :
+//!
+//! ```ron
+//! log: (
+//!     level: Info,
+//!     components: { "tickets": Debug, "serenity": Warn },
+//!     sinks: [Stderr, File],
+//! ),
+//! ```
+//!
+//! Les messages peuvent être écrits dans la sortie d'erreur, dans un fichier texte ou dans un fichier
+//! JSON (un objet par ligne). Les fichiers sont enregistrés dans `data/logs` et archivés lorsqu'ils dépassent
+//! `max_file_size` octets.
+//!
+//! [`Component::name`]: crate::component_system::Component::name
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+
+use lazy_static::lazy_static;
+use log::{LevelFilter, Log, Metadata, Record};
+use serde::{Deserialize, Serialize};
+
+use crate::component_system::data::DATA_DIR;
+
+lazy_static! {
+    /// Niveau de verbosité de chaque cible, modifiable pendant l'exécution
+    static ref FILTER: RwLock<Filter> = RwLock::new(Filter::default());
+}
+
+/// Niveau de verbosité
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Off => LevelFilter::Off,
+            LogLevel::Error => LevelFilter::Error,
+            LogLevel::Warn => LevelFilter::Warn,
+            LogLevel::Info => LevelFilter::Info,
+            LogLevel::Debug => LevelFilter::Debug,
+            LogLevel::Trace => LevelFilter::Trace,
+        }
+    }
+}
+
+/// Destination des messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LogSink {
+    /// Sortie d'erreur
+    Stderr,
+    /// Fichier texte `data/logs/open_cdd.log`
+    File,
+    /// Fichier `data/logs/open_cdd.jsonl`, un objet JSON par ligne
+    Json,
+}
+
+/// Configuration de la journalisation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogConfig {
+    /// Niveau par défaut
+    pub level: LogLevel,
+    /// Niveau de chaque composant ou module, prioritaire sur le niveau par défaut
+    pub components: HashMap<String, LogLevel>,
+    /// Destinations des messages
+    pub sinks: Vec<LogSink>,
+    /// Taille maximale d'un fichier de log en octets avant son archivage
+    pub max_file_size: u64,
+    /// Nombre de fichiers archivés conservés
+    pub max_files: usize,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            level: LogLevel::Info,
+            components: HashMap::new(),
+            sinks: vec![LogSink::Stderr],
+            max_file_size: 10 * 1024 * 1024,
+            max_files: 5,
+        }
+    }
+}
+
+/// Niveau de verbosité de chaque cible
+struct Filter {
+    level: LevelFilter,
+    targets: Vec<(String, LevelFilter)>,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter {
+            level: LevelFilter::Info,
+            targets: Vec::new(),
+        }
+    }
+}
+
+impl Filter {
+    fn new(config: &LogConfig) -> Filter {
+        Filter {
+            level: config.level.into(),
+            targets: config
+                .components
+                .iter()
+                .map(|(target, level)| (target.clone(), (*level).into()))
+                .collect(),
+        }
+    }
+    /// Retourne le niveau de la cible.
+    ///
+    /// Le niveau d'un module s'applique aussi à ses sous-modules : `serenity` règle `serenity::gateway`.
+    fn level(&self, target: &str) -> LevelFilter {
+        self.targets
+            .iter()
+            .filter(|(name, _)| {
+                target
+                    .strip_prefix(name.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+            })
+            .max_by_key(|(name, _)| name.len())
+            .map_or(self.level, |(_, level)| *level)
+    }
+    /// Niveau le plus verbeux de toutes les cibles
+    fn max(&self) -> LevelFilter {
+        self.targets
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.level, std::cmp::max)
+    }
+}
+
+/// Fichier de log archivé lorsqu'il dépasse une taille maximale.
+///
+/// A l'archivage, `open_cdd.log` est renommé `open_cdd.log.1`, `open_cdd.log.1` devient `open_cdd.log.2`, etc.
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    max_files: usize,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, max_size: u64, max_files: usize) -> io::Result<RotatingFile> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(RotatingFile {
+            path,
+            file,
+            size,
+            max_size,
+            max_files,
+        })
+    }
+    fn archive_path(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        path.into()
+    }
+    fn rotate(&mut self) -> io::Result<()> {
+        for index in (1..self.max_files).rev() {
+            rename_if_exists(&self.archive_path(index), &self.archive_path(index + 1))?;
+        }
+        if self.max_files > 0 {
+            rename_if_exists(&self.path, &self.archive_path(1))?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let len = line.len() as u64 + 1;
+        if self.size > 0 && self.size + len > self.max_size {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{}", line)?;
+        self.size += len;
+        Ok(())
+    }
+}
+
+fn rename_if_exists(from: &Path, to: &Path) -> io::Result<()> {
+    match std::fs::rename(from, to) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+enum Sink {
+    Stderr,
+    File {
+        file: Mutex<RotatingFile>,
+        json: bool,
+    },
+}
+
+struct Logger {
+    sinks: Vec<Sink>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= FILTER.read().unwrap().level(metadata.target())
+    }
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let now = chrono::Local::now();
+        let text = || {
+            format!(
+                "[{}] {:<5} {}: {}",
+                now.format("%Y-%m-%d %H:%M:%S"),
+                record.level(),
+                record.target(),
+                record.args()
+            )
+        };
+        for sink in &self.sinks {
+            let (file, line) = match sink {
+                Sink::Stderr => {
+                    eprintln!("{}", text());
+                    continue;
+                }
+                Sink::File { file, json: false } => (file, text()),
+                Sink::File { file, json: true } => (
+                    file,
+                    serde_json::json!({
+                        "time": now.to_rfc3339(),
+                        "level": record.level().as_str(),
+                        "target": record.target(),
+                        "message": record.args().to_string(),
+                    })
+                    .to_string(),
+                ),
+            };
+            if let Err(e) = file.lock().unwrap().write_line(&line) {
+                eprintln!("Unable to write the log file: {}", e);
+            }
+        }
+    }
+    fn flush(&self) {
+        for sink in &self.sinks {
+            if let Sink::File { file, .. } = sink {
+                let _ = file.lock().unwrap().file.flush();
+            }
+        }
+    }
+}
+
+/// Installe le logger du bot.
+///
+/// Doit être appelé une seule fois, avant tout message.
+pub fn init(config: &LogConfig) -> Result<(), String> {
+    let dir = DATA_DIR.join("logs");
+    let open = |name: &str, json: bool| {
+        RotatingFile::open(dir.join(name), config.max_file_size, config.max_files)
+            .map(|file| Sink::File {
+                file: Mutex::new(file),
+                json,
+            })
+            .map_err(|e| format!("Unable to open the log file {}: {}", name, e))
+    };
+    let sinks = config
+        .sinks
+        .iter()
+        .map(|sink| match sink {
+            LogSink::Stderr => Ok(Sink::Stderr),
+            LogSink::File => open("open_cdd.log", false),
+            LogSink::Json => open("open_cdd.jsonl", true),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    set_levels(config);
+    log::set_boxed_logger(Box::new(Logger { sinks })).map_err(|e| e.to_string())
+}
+
+/// Change le niveau de verbosité de chaque cible sans réinstaller le logger
+pub fn set_levels(config: &LogConfig) {
+    let filter = Filter::new(config);
+    log::set_max_level(filter.max());
+    *FILTER.write().unwrap() = filter;
+}
@@ -26,6 +26,8 @@
 mod bot;
 mod component_system;
 mod config;
+mod logger;
+mod metrics;
 #[macro_use]
 mod util;
 
@@ -47,16 +49,107 @@ impl<T, S: AsRef<str>> ResultLog for Result<T, S> {
     }
 }
 
+/// Attend un signal d'arrêt du processus (SIGINT ou SIGTERM).
+async fn wait_shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut sigterm = signal(SignalKind::terminate())
+            .map_err(|e| e.to_string())
+            .expect_log("Could not listen to SIGTERM");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => (),
+            _ = sigterm.recv() => (),
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c()
+            .await
+            .map_err(|e| e.to_string())
+            .expect_log("Could not listen to Ctrl-C");
+    }
+}
+
+/// Copie toutes les données d'un support de stockage à l'autre.
+///
+/// Usage : `open_cdd copy-data <ron|sqlite> <ron|sqlite>`.
+/// Le chemin de la base SQLite est celui du fichier de configuration s'il y est renseigné, sinon `data/data.sqlite`.
+fn copy_data(config: &config::Config, args: &[String]) -> Result<(), String> {
+    use component_system::data::{self, StorageConfig};
+    let storage_config = |name: &str| match name {
+        "ron" => Ok(StorageConfig::Ron),
+        "sqlite" => Ok(match &config.storage {
+            StorageConfig::Sqlite(path) => StorageConfig::Sqlite(path.clone()),
+            StorageConfig::Ron => StorageConfig::Sqlite(data::DATA_DIR.join("data.sqlite")),
+        }),
+        _ => Err(format!("Unknown storage {}, expected ron or sqlite", name)),
+    };
+    let (from, to) = match args {
+        [from, to] => (storage_config(from)?, storage_config(to)?),
+        _ => return Err("Usage: open_cdd copy-data <ron|sqlite> <ron|sqlite>".to_string()),
+    };
+    let from = from.open(config.data_backups).map_err(|e| e.to_string())?;
+    let to = to.open(config.data_backups).map_err(|e| e.to_string())?;
+    let count = data::copy_all(from.as_ref(), to.as_ref()).map_err(|e| e.to_string())?;
+    log::info!(
+        target: "data",
+        "{} data copied from {} to {}",
+        count,
+        from.name(),
+        to.name()
+    );
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() {
     let config =
         config::Config::load("./config.ron").expect_log("Could not load the configuration file");
+    logger::init(&config.log).expect_log("Could not initialize the logger");
+    let args = std::env::args().collect::<Vec<_>>();
+    if let Some("copy-data") = args.get(1).map(String::as_str) {
+        copy_data(&config, &args[2..]).expect_log("Could not copy the data");
+        return;
+    }
+    component_system::data::set_storage(
+        config
+            .storage
+            .open(config.data_backups)
+            .map_err(|e| e.to_string())
+            .expect_log("Could not open the data storage"),
+    );
+    if let Some(interval) = config.data_flush_interval_ms {
+        component_system::data::write_behind::start(std::time::Duration::from_millis(interval));
+    }
+    if let Some(addr) = config.metrics_addr {
+        if let Err(e) = metrics::start(addr) {
+            log::error!(target: "metrics", "Could not start the metrics server: {}", e);
+        }
+    }
     let mut bot = bot::Bot::new(&config)
         .await
         .map_err(|e| e.to_string())
         .expect_log("");
-    bot.start()
-        .await
-        .map_err(|e| e.to_string())
-        .expect_log("Could not start the bot");
+    let config = std::sync::Arc::new(std::sync::RwLock::new(config));
+    if let Err(e) = bot.watch_config(config.clone()).await {
+        log::error!(target: "config", "Could not watch the configuration file: {}", e);
+    }
+    let shutdown = bot.shutdown_handle();
+    tokio::select! {
+        result = bot.start() => {
+            component_system::data::write_behind::flush().await;
+            result
+                .map_err(|e| e.to_string())
+                .expect_log("Could not start the bot");
+        }
+        _ = wait_shutdown_signal() => {
+            log::info!("Shutting down the bot...");
+            // La configuration n'est pas enregistrée : seul le fichier la modifie, et l'écrire
+            // effacerait les modifications en attente de redémarrage et les commentaires
+            shutdown.shutdown().await;
+            log::info!("Bot stopped");
+        }
+    }
+    log::logger().flush();
 }
@@ -0,0 +1,315 @@
+//! Métriques du bot au format texte de Prometheus.
+//!
+//! Les métriques sont des variables globales mises à jour par le système de composants et par les composants
+//! eux-mêmes. Lorsque `metrics_addr` est renseigné dans le fichier de configuration, elles sont exposées
+//! par un serveur HTTP local sur `http://<metrics_addr>/metrics` :
+//!
+//! ```sh
+//! curl http://127.0.0.1:9100/metrics
+//! ```
+//!
+//! Pour ajouter une métrique, déclarez-la dans le bloc `lazy_static!` de ce module puis ajoutez-la à [`render`].
+
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{header, Body, Method, Request, Response, Server, StatusCode};
+use lazy_static::lazy_static;
+
+/// Bornes supérieures, en secondes, des intervalles des histogrammes de durée
+const DURATION_BUCKETS: [f64; 12] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 30.0, 120.0,
+];
+
+lazy_static! {
+    /// Évènements transmis à chaque composant, par type d'évènement
+    pub static ref EVENTS: Counter = Counter::new(
+        "open_cdd_events_total",
+        "Évènements transmis aux composants",
+        &["component", "kind"]
+    );
+    /// Durée de traitement des évènements par chaque composant
+    pub static ref EVENT_DURATION: Histogram = Histogram::new(
+        "open_cdd_event_duration_seconds",
+        "Durée de traitement des évènements par les composants",
+        &["component", "kind"]
+    );
+    /// Erreurs de chaque composant, par origine (`event`, `timeout` ou `command`)
+    pub static ref ERRORS: Counter = Counter::new(
+        "open_cdd_errors_total",
+        "Erreurs retournées par les composants",
+        &["component", "source"]
+    );
+    /// Slash commandes exécutées, par id de commande
+    pub static ref COMMANDS: Counter = Counter::new(
+        "open_cdd_commands_total",
+        "Slash commandes exécutées",
+        &["command"]
+    );
+    /// Tickets ouverts de chaque serveur, par catégorie
+    pub static ref OPEN_TICKETS: Gauge = Gauge::new(
+        "open_cdd_open_tickets",
+        "Tickets ouverts",
+        &["guild", "category"]
+    );
+    /// Sanctions temporaires en cours de chaque serveur, par type de sanction
+    pub static ref ACTIVE_SANCTIONS: Gauge = Gauge::new(
+        "open_cdd_active_sanctions",
+        "Sanctions temporaires en cours",
+        &["guild", "sanction"]
+    );
+}
+
+/// Famille de valeurs d'une métrique, une valeur par combinaison de labels
+struct Family<V> {
+    name: &'static str,
+    help: &'static str,
+    labels: &'static [&'static str],
+    values: Mutex<BTreeMap<Vec<String>, V>>,
+}
+
+impl<V: Default> Family<V> {
+    fn new(name: &'static str, help: &'static str, labels: &'static [&'static str]) -> Self {
+        Family {
+            name,
+            help,
+            labels,
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+    /// Modifie la valeur correspondant aux labels `labels`, donnés dans l'ordre de déclaration
+    fn update(&self, labels: &[&str], f: impl FnOnce(&mut V)) {
+        debug_assert_eq!(labels.len(), self.labels.len(), "metric {}", self.name);
+        let key = labels.iter().map(|label| label.to_string()).collect();
+        f(self.values.lock().unwrap().entry(key).or_default());
+    }
+    fn header(&self, out: &mut String, kind: &str) {
+        let _ = writeln!(out, "# HELP {} {}", self.name, self.help);
+        let _ = writeln!(out, "# TYPE {} {}", self.name, kind);
+    }
+    /// Formate les labels d'une valeur, avec un label supplémentaire éventuel (`le` des histogrammes)
+    fn format_labels(&self, values: &[String], extra: Option<(&str, &str)>) -> String {
+        let labels = self
+            .labels
+            .iter()
+            .zip(values.iter().map(String::as_str))
+            .map(|(name, value)| (*name, value))
+            .chain(extra)
+            .map(|(name, value)| format!("{}=\"{}\"", name, escape(value)))
+            .collect::<Vec<_>>();
+        if labels.is_empty() {
+            String::new()
+        } else {
+            format!("{{{}}}", labels.join(","))
+        }
+    }
+}
+
+/// Échappe une valeur de label
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Compteur, qui ne fait qu'augmenter
+pub struct Counter(Family<u64>);
+
+impl Counter {
+    fn new(name: &'static str, help: &'static str, labels: &'static [&'static str]) -> Self {
+        Counter(Family::new(name, help, labels))
+    }
+    /// Incrémente le compteur des labels `labels`
+    pub fn inc(&self, labels: &[&str]) {
+        self.0.update(labels, |value| *value += 1);
+    }
+    fn render(&self, out: &mut String) {
+        self.0.header(out, "counter");
+        for (labels, value) in self.0.values.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "{}{} {}",
+                self.0.name,
+                self.0.format_labels(labels, None),
+                value
+            );
+        }
+    }
+}
+
+/// Jauge, dont la valeur peut augmenter ou diminuer
+pub struct Gauge(Family<i64>);
+
+impl Gauge {
+    fn new(name: &'static str, help: &'static str, labels: &'static [&'static str]) -> Self {
+        Gauge(Family::new(name, help, labels))
+    }
+    /// Ajoute `delta` à la jauge des labels `labels`
+    pub fn add(&self, labels: &[&str], delta: i64) {
+        self.0.update(labels, |value| *value += delta);
+    }
+    /// Remplace les valeurs dont les premiers labels sont `prefix` par `values`.
+    ///
+    /// Chaque valeur est donnée avec ses labels restants. Par exemple, pour remplacer les tickets ouverts d'un serveur :
+    /// `OPEN_TICKETS.replace(&[guild], vec![(vec![category], count)])`.
+    pub fn replace(&self, prefix: &[&str], values: Vec<(Vec<String>, i64)>) {
+        let mut current = self.0.values.lock().unwrap();
+        current.retain(|labels, _| {
+            labels.len() < prefix.len() || labels.iter().zip(prefix).any(|(a, b)| a != b)
+        });
+        for (labels, value) in values {
+            let key = prefix
+                .iter()
+                .map(|label| label.to_string())
+                .chain(labels)
+                .collect();
+            current.insert(key, value);
+        }
+    }
+    fn render(&self, out: &mut String) {
+        self.0.header(out, "gauge");
+        for (labels, value) in self.0.values.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "{}{} {}",
+                self.0.name,
+                self.0.format_labels(labels, None),
+                value
+            );
+        }
+    }
+}
+
+/// Valeurs d'un histogramme : nombre d'observations par intervalle, somme et nombre total d'observations
+#[derive(Default)]
+struct Buckets {
+    counts: [u64; DURATION_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+/// Histogramme de durées, en secondes
+pub struct Histogram(Family<Buckets>);
+
+impl Histogram {
+    fn new(name: &'static str, help: &'static str, labels: &'static [&'static str]) -> Self {
+        Histogram(Family::new(name, help, labels))
+    }
+    /// Enregistre une durée pour les labels `labels`
+    pub fn observe(&self, labels: &[&str], duration: std::time::Duration) {
+        let seconds = duration.as_secs_f64();
+        self.0.update(labels, |buckets| {
+            if let Some(idx) = DURATION_BUCKETS.iter().position(|le| seconds <= *le) {
+                buckets.counts[idx] += 1;
+            }
+            buckets.sum += seconds;
+            buckets.count += 1;
+        });
+    }
+    fn render(&self, out: &mut String) {
+        let name = self.0.name;
+        self.0.header(out, "histogram");
+        for (labels, buckets) in self.0.values.lock().unwrap().iter() {
+            let mut cumulative = 0;
+            for (le, count) in DURATION_BUCKETS.iter().zip(buckets.counts) {
+                cumulative += count;
+                let le = le.to_string();
+                let labels = self.0.format_labels(labels, Some(("le", &le)));
+                let _ = writeln!(out, "{}_bucket{} {}", name, labels, cumulative);
+            }
+            let inf = self.0.format_labels(labels, Some(("le", "+Inf")));
+            let labels = self.0.format_labels(labels, None);
+            let _ = writeln!(out, "{}_bucket{} {}", name, inf, buckets.count);
+            let _ = writeln!(out, "{}_sum{} {}", name, labels, buckets.sum);
+            let _ = writeln!(out, "{}_count{} {}", name, labels, buckets.count);
+        }
+    }
+}
+
+/// Retourne toutes les métriques au format texte de Prometheus
+pub fn render() -> String {
+    let mut out = String::new();
+    EVENTS.render(&mut out);
+    EVENT_DURATION.render(&mut out);
+    ERRORS.render(&mut out);
+    COMMANDS.render(&mut out);
+    OPEN_TICKETS.render(&mut out);
+    ACTIVE_SANCTIONS.render(&mut out);
+    out
+}
+
+async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let response = match (req.method(), req.uri().path()) {
+        (&Method::GET, "/metrics") => Response::builder()
+            .header(
+                header::CONTENT_TYPE,
+                "text/plain; version=0.0.4; charset=utf-8",
+            )
+            .body(Body::from(render())),
+        _ => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::from("Not found")),
+    };
+    Ok(response.unwrap())
+}
+
+/// Démarre le serveur HTTP des métriques sur l'adresse `addr`.
+///
+/// Doit être appelé depuis le runtime tokio. Retourne l'adresse d'écoute effective (utile avec le port 0),
+/// ou une erreur si l'adresse n'est pas disponible.
+pub fn start(addr: SocketAddr) -> Result<SocketAddr, String> {
+    let server = Server::try_bind(&addr)
+        .map_err(|e| format!("Unable to listen on {}: {}", addr, e))?
+        .serve(make_service_fn(|_| async {
+            Ok::<_, Infallible>(service_fn(handle))
+        }));
+    let addr = server.local_addr();
+    log::info!(target: "metrics", "Metrics available on http://{}/metrics", addr);
+    tokio::spawn(async move {
+        if let Err(e) = server.await {
+            log::error!(target: "metrics", "Metrics server error: {}", e);
+        }
+    });
+    Ok(addr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    /// Envoie une requête GET au serveur et retourne la réponse brute
+    async fn get(addr: SocketAddr, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let request = format!(
+            "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+            path, addr
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn exporter_serves_commands() {
+        let addr = start("127.0.0.1:0".parse().unwrap()).unwrap();
+        assert_ne!(addr.port(), 0);
+        COMMANDS.inc(&["metrics_test"]);
+        COMMANDS.inc(&["metrics_test"]);
+
+        let response = get(addr, "/metrics").await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.contains("# TYPE open_cdd_commands_total counter"));
+        assert!(response.contains("open_cdd_commands_total{command=\"metrics_test\"} 2"));
+
+        let response = get(addr, "/autre").await;
+        assert!(response.starts_with("HTTP/1.1 404"), "{}", response);
+    }
+}